@@ -0,0 +1,340 @@
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use serde_json::json;
+
+use rust_parcel::cityjson::{compression, export, filter, format, gltf, inspect, subset, tiles, to_bufferattributes};
+use rust_parcel::logging::{self, Silent};
+use rust_parcel::cityjson::cache::GeometryCache;
+use rust_parcel::cityjson::format::Format;
+use rust_parcel::cityjson::mesh::{Mesh, MeshOptions};
+use rust_parcel::cityjson::picking::PickingIndex;
+use rust_parcel::cityjson::tiles::{Georeference, TileSubdivision};
+
+///// Command-line tool for preprocessing and inspecting CityJSON files, with the same code as the viewer
+
+const USAGE: &str = "Usage: cityjson <command> <file> [options]
+
+<file> is a CityJSON file as JSON, CBOR or MessagePack, optionally gzip or zlib compressed (e.g. .city.json.gz)
+
+Commands:
+  info <file> [--json]                      Version, counts per type, LoDs, extent and vertex stats
+  validate <file> [--json]                  Check the file for what the viewer needs
+  subset <file> -o <out> [--id <id>]... [--filter <expression>] [--children] [--parents]
+                                            Write the selected CityObjects as a new CityJSON file
+  export <file> -f <glb|obj|stl|ply|tiles> -o <out> [--lod <lod>] [--type <type>]... [--recenter]
+         [--max-features <n>] [--octree] [--origin <lon,lat[,height]>]
+                                            Export the triangles (tiles: a directory with tileset.json, with --origin
+                                            the WGS84 position of the model center unless the file is in ECEF)
+  prebuild <file> -o <out> [--json]         Write the triangles, groups, vertices and picking index of the viewer as a
+                                            geometry cache file (see load_geometry_cache()), or as JSON
+  convert <file> -f <json|cbor|msgpack> -o <out>
+                                            Write the file in another encoding of CityJSON
+
+Options:
+  --verbose                                 Print progress messages to stderr";
+
+// Options without a value
+const FLAGS: [&str; 7] = [ "--json", "--children", "--parents", "--recenter", "--octree", "--verbose", "--help" ];
+
+struct Args {
+
+    positional: Vec<String>,
+    options: Vec<( String, String )>,
+    flags: Vec<String>,
+
+}
+
+impl Args {
+
+    fn parse( args: impl Iterator<Item = String> ) -> Result<Args, String> {
+
+        let mut parsed = Args { positional: Vec::new(), options: Vec::new(), flags: Vec::new() };
+        let mut args = args.peekable();
+
+        while let Some( arg ) = args.next() {
+
+            if FLAGS.contains( &arg.as_str() ) {
+                parsed.flags.push( arg );
+            } else if arg.starts_with( '-' ) && arg.len() > 1 {
+                let value = args.next().ok_or_else( || format!( "Option {} needs a value", arg ) )?;
+                parsed.options.push( ( arg, value ) );
+            } else {
+                parsed.positional.push( arg );
+            }
+
+        }
+
+        Ok( parsed )
+
+    }
+
+    fn flag( &self, name: &str ) -> bool {
+
+        self.flags.iter().any( |f| f == name )
+
+    }
+
+    fn values( &self, names: &[&str] ) -> Vec<String> {
+
+        self.options.iter().filter( |( k, _ )| names.contains( &k.as_str() ) ).map( |( _, v )| v.clone() ).collect()
+
+    }
+
+    fn value( &self, names: &[&str] ) -> Option<String> {
+
+        self.values( names ).pop()
+
+    }
+
+    fn required( &self, names: &[&str] ) -> Result<String, String> {
+
+        self.value( names ).ok_or_else( || format!( "Missing option {}", names.join( "/" ) ) )
+
+    }
+
+}
+
+fn write( path: &str, data: &[u8] ) -> Result<(), String> {
+
+    fs::write( path, data ).map_err( |e| format!( "Could not write {}: {}", path, e ) )
+
+}
+
+fn info( buffer: &[u8], args: &Args ) -> Result<(), String> {
+
+    let info = inspect::info( buffer )?;
+
+    if args.flag( "--json" ) {
+        println!( "{}", serde_json::to_string_pretty( &info ).unwrap() );
+        return Ok( () );
+    }
+
+    println!( "Version:          {}", info.version.as_deref().unwrap_or( "-" ) );
+    println!( "Reference system: {}", info.reference_system.as_deref().unwrap_or( "-" ) );
+
+    match info.transform {
+        Some( [ scale, translate ] ) => println!( "Transform:        scale {:?}, translate {:?}", scale, translate ),
+        None => println!( "Transform:        -" ),
+    }
+
+    println!( "CityObjects:      {}", info.cityobjects );
+
+    for ( co_type, count ) in &info.types {
+        println!( "  {:<24}{}", co_type, count );
+    }
+
+    println!( "Geometries:" );
+
+    for ( geometry_type, count ) in &info.geometry_types {
+        println!( "  {:<24}{}", geometry_type, count );
+    }
+
+    println!( "LoDs:" );
+
+    for ( lod, count ) in &info.lods {
+        println!( "  {:<24}{}", lod, count );
+    }
+
+    println!( "Triangles:        {} ({} other surfaces)", info.triangles, info.other_surfaces );
+    println!( "Vertices:         {} ({} unused, {} duplicates)", info.vertices.count, info.vertices.unused, info.vertices.duplicates );
+
+    if let Some( e ) = info.vertices.extent {
+        println!( "Extent:           [{}, {}, {}] - [{}, {}, {}]", e[ 0 ], e[ 1 ], e[ 2 ], e[ 3 ], e[ 4 ], e[ 5 ] );
+    }
+
+    if let Some( e ) = &info.metadata_extent {
+        println!( "Metadata extent:  {}", e );
+    }
+
+    Ok( () )
+
+}
+
+fn validate( buffer: &[u8], args: &Args ) -> Result<bool, String> {
+
+    let issues = inspect::validate( buffer )?;
+    let errors = issues.iter().filter( |i| i.severity == inspect::Severity::Error ).count();
+
+    if args.flag( "--json" ) {
+
+        println!( "{}", serde_json::to_string_pretty( &issues ).unwrap() );
+
+    } else {
+
+        for issue in &issues {
+            match &issue.cityobject {
+                Some( id ) => println!( "{:?}: {}: {}", issue.severity, id, issue.message ),
+                None => println!( "{:?}: {}", issue.severity, issue.message ),
+            }
+        }
+
+        println!( "{} errors, {} warnings", errors, issues.len() - errors );
+
+    }
+
+    Ok( errors == 0 )
+
+}
+
+fn subset( buffer: &[u8], args: &Args ) -> Result<(), String> {
+
+    let out = args.required( &[ "-o", "--output" ] )?;
+    let mut ids = args.values( &[ "--id" ] );
+
+    if let Some( expression ) = args.value( &[ "--filter" ] ) {
+
+        let expr = filter::parse( &expression ).map_err( |e| format!( "Invalid filter: {}", e ) )?;
+        ids.extend( filter::filter_ids( buffer, &expr ).map_err( |e| format!( "Error parsing CityJSON buffer: {}", e ) )? );
+
+    }
+
+    if ids.is_empty() {
+        return Err( "Select CityObjects with --id and/or --filter".to_string() );
+    }
+
+    let data = subset::subset( buffer, &ids, args.flag( "--children" ), args.flag( "--parents" ) )?;
+
+    write( &out, &data )
+
+}
+
+fn export( buffer: &[u8], args: &Args ) -> Result<(), String> {
+
+    let format = args.required( &[ "-f", "--format" ] )?;
+    let out = args.required( &[ "-o", "--output" ] )?;
+
+    let types = args.values( &[ "--type" ] );
+
+    let options = MeshOptions {
+        lod: args.value( &[ "--lod" ] ).map( serde_json::Value::String ),
+        types: if types.is_empty() { None } else { Some( types ) },
+        recenter: args.flag( "--recenter" ),
+    };
+
+    let mesh = Mesh::build( buffer, &options )?;
+
+    match format.as_str() {
+        "glb" => write( &out, &gltf::write_glb( &mesh )? ),
+        "obj" => {
+            let mtl = Path::new( &out ).with_extension( "mtl" );
+            let mtl_name = mtl.file_name().unwrap().to_string_lossy().to_string();
+            write( &out, export::write_obj( &mesh, &mtl_name ).as_bytes() )?;
+            write( &mtl.to_string_lossy(), export::write_mtl( &mesh ).as_bytes() )
+        },
+        "stl" => write( &out, &export::write_stl( &mesh ) ),
+        "ply" => write( &out, &export::write_ply( &mesh ) ),
+        "tiles" => {
+            let max_features = args.value( &[ "--max-features" ] ).unwrap_or_else( || "1000".to_string() )
+                                   .parse::<usize>().map_err( |e| format!( "Invalid --max-features: {}", e ) )?;
+            let subdivision = if args.flag( "--octree" ) { TileSubdivision::Octree } else { TileSubdivision::Quadtree };
+            let georeference = args.value( &[ "--origin" ] ).map( |o| georeference( &o ) ).transpose()?;
+
+            for ( path, data ) in tiles::write_tileset( &mesh, subdivision, max_features, georeference.as_ref() )? {
+                let path = Path::new( &out ).join( path );
+                fs::create_dir_all( path.parent().unwrap() ).map_err( |e| format!( "Could not create {}: {}", out, e ) )?;
+                write( &path.to_string_lossy(), &data )?;
+            }
+
+            Ok( () )
+        },
+        f => Err( format!( "Unknown format {}, expected glb, obj, stl, ply or tiles", f ) ),
+    }
+
+}
+
+// --origin lon,lat[,height]
+fn georeference( origin: &str ) -> Result<Georeference, String> {
+
+    let values = origin.split( ',' ).map( |v| v.trim().parse::<f64>() ).collect::<Result<Vec<f64>, _>>()
+                       .map_err( |e| format!( "Invalid --origin: {}", e ) )?;
+
+    match values[ .. ] {
+        [ longitude, latitude ] => Ok( Georeference { longitude, latitude, height: 0.0 } ),
+        [ longitude, latitude, height ] => Ok( Georeference { longitude, latitude, height } ),
+        _ => Err( "Invalid --origin: expected longitude,latitude or longitude,latitude,height".to_string() ),
+    }
+
+}
+
+fn prebuild( buffer: &[u8], args: &Args ) -> Result<(), String> {
+
+    let out = args.required( &[ "-o", "--output" ] )?;
+
+    if !args.flag( "--json" ) {
+        return write( &out, &GeometryCache::build( buffer )?.to_bytes()? );
+    }
+
+    let parsed = to_bufferattributes::parse_local( buffer, PickingIndex::Intervals ).map_err( |e| format!( "Error parsing CityJSON buffer: {}", e ) )?;
+    let vertices = to_bufferattributes::flatten_vertices( buffer ).map_err( |e| format!( "Error parsing CityJSON buffer: {}", e ) )?;
+
+    let data = json!( {
+        "triangles": parsed.triangles,
+        "groups": parsed.groups,
+        "vertices": vertices,
+        "ids": parsed.ids,
+        "intervals": parsed.index,
+    } );
+
+    write( &out, &serde_json::to_vec( &data ).unwrap() )
+
+}
+
+fn convert( buffer: &[u8], args: &Args ) -> Result<(), String> {
+
+    let out = args.required( &[ "-o", "--output" ] )?;
+
+    let format = match args.required( &[ "-f", "--format" ] )?.as_str() {
+        "json" => Format::Json,
+        "cbor" => Format::Cbor,
+        "msgpack" => Format::MessagePack,
+        f => return Err( format!( "Unknown format {}, expected json, cbor or msgpack", f ) ),
+    };
+
+    write( &out, &format::encode( buffer, format )? )
+
+}
+
+fn run() -> Result<bool, String> {
+
+    let args = Args::parse( std::env::args().skip( 1 ) )?;
+
+    if args.flag( "--help" ) || args.positional.len() < 2 {
+        println!( "{}", USAGE );
+        return Ok( args.flag( "--help" ) );
+    }
+
+    if !args.flag( "--verbose" ) {
+        logging::set_logger( Box::new( Silent ) );
+    }
+
+    let command = args.positional[ 0 ].as_str();
+    let file = &args.positional[ 1 ];
+    let buffer = fs::read( file ).map_err( |e| format!( "Could not read {}: {}", file, e ) )?;
+    let buffer = compression::decompress( buffer ).map_err( |e| format!( "Could not read {}: {}", file, e ) )?;
+
+    match command {
+        "info" => info( &buffer, &args ).map( |_| true ),
+        "validate" => validate( &buffer, &args ),
+        "subset" => subset( &buffer, &args ).map( |_| true ),
+        "export" => export( &buffer, &args ).map( |_| true ),
+        "prebuild" => prebuild( &buffer, &args ).map( |_| true ),
+        "convert" => convert( &buffer, &args ).map( |_| true ),
+        c => Err( format!( "Unknown command {}\n\n{}", c, USAGE ) ),
+    }
+
+}
+
+fn main() {
+
+    match run() {
+        Ok( true ) => {},
+        Ok( false ) => process::exit( 1 ),
+        Err( e ) => {
+            eprintln!( "{}", e );
+            process::exit( 1 );
+        },
+    }
+
+}
@@ -0,0 +1,341 @@
+use serde::Deserialize;
+
+use super::edit;
+use super::format;
+use super::hierarchy::{Hierarchy, HIERARCHY};
+use super::join;
+use super::mesh::Transform;
+use super::picking::{self, PickingIndex};
+use super::search::{self, SearchIndex};
+use super::to_bufferattributes::{self, ParsedCityObjects, CO_TYPES, IDS, INTERVALS};
+
+///// Precomputed binary geometry cache: everything that parse_cityobjects() and parse_vertices() produce (plus the extent of every
+///// CityObject), in a versioned binary format that can be loaded without parsing JSON.
+/////
+///// Layout (little endian). A 48 byte header:
+/////   0 magic "CJGC", 4 version (u32), 8 CRC-32 of everything after the header, 12 vertex coordinate count, 16 triangle index count,
+/////   20 group count, 24 interval count, 28 ID count, 32 ID bytes, 36 total length, 40 reserved (8 bytes of zeros)
+///// followed by these sections, each padded to a multiple of 8 bytes:
+/////   transform (scale and translate, 6 f64), extents (6 f64 per ID: min xyz, max xyz, real coordinates), vertices (u32, as parse_vertices()),
+/////   triangles (u32), groups (3 u32 per group: index in CO_TYPES, start, count), intervals (u32), ID offsets (ID count + 1 u32), IDs (UTF-8)
+
+pub const MAGIC: &[u8; 4] = b"CJGC";
+pub const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 48;
+
+#[derive(Debug, PartialEq)]
+pub struct GeometryCache {
+
+    pub transform: [[f64; 3]; 2],
+    pub extents: Vec<[f64; 6]>,
+    pub vertices: Vec<u32>,
+    pub triangles: Vec<u32>,
+    // ( CityObject type, start, count ), like the groups of parse_cityobjects()
+    pub groups: Vec<( String, u32, u32 )>,
+    pub intervals: Vec<u32>,
+    pub ids: Vec<String>,
+
+}
+
+/// The sections of a cache file, borrowed from its bytes after the header has been checked
+pub struct CacheView<'a> {
+
+    pub version: u32,
+    pub transform: &'a [u8],
+    pub extents: &'a [u8],
+    pub vertices: &'a [u8],
+    pub triangles: &'a [u8],
+    pub groups: &'a [u8],
+    pub intervals: &'a [u8],
+    pub id_offsets: &'a [u8],
+    pub ids: &'a [u8],
+
+}
+
+#[derive(Deserialize)]
+struct TransformOnly {
+
+    transform: Option<Transform>,
+
+}
+
+fn crc32_table() -> [u32; 256] {
+
+    let mut table = [ 0u32; 256 ];
+
+    for ( i, entry ) in table.iter_mut().enumerate() {
+
+        let mut c = i as u32;
+
+        for _ in 0..8 {
+            c = if c & 1 != 0 { 0xedb8_8320 ^ ( c >> 1 ) } else { c >> 1 };
+        }
+
+        *entry = c;
+
+    }
+
+    table
+
+}
+
+/// CRC-32 (as in zip and PNG)
+pub fn crc32( data: &[u8] ) -> u32 {
+
+    let table = crc32_table();
+
+    !data.iter().fold( !0u32, |c, b| table[ ( ( c ^ *b as u32 ) & 0xff ) as usize ] ^ ( c >> 8 ) )
+
+}
+
+fn padded( n: usize ) -> usize {
+
+    n.div_ceil( 8 ) * 8
+
+}
+
+fn read_u32( bytes: &[u8], offset: usize ) -> u32 {
+
+    u32::from_le_bytes( [ bytes[ offset ], bytes[ offset + 1 ], bytes[ offset + 2 ], bytes[ offset + 3 ] ] )
+
+}
+
+/// Decode little endian u32s
+pub fn u32s( bytes: &[u8] ) -> Vec<u32> {
+
+    bytes.chunks_exact( 4 ).map( |c| u32::from_le_bytes( [ c[ 0 ], c[ 1 ], c[ 2 ], c[ 3 ] ] ) ).collect()
+
+}
+
+/// Decode little endian f64s
+pub fn f64s( bytes: &[u8] ) -> Vec<f64> {
+
+    bytes.chunks_exact( 8 ).map( |c| f64::from_le_bytes( [ c[ 0 ], c[ 1 ], c[ 2 ], c[ 3 ], c[ 4 ], c[ 5 ], c[ 6 ], c[ 7 ] ] ) ).collect()
+
+}
+
+impl<'a> CacheView<'a> {
+
+    /// Check the magic, version, length and checksum, and find the sections
+    pub fn parse( bytes: &'a [u8] ) -> Result<CacheView<'a>, String> {
+
+        if bytes.len() < HEADER_SIZE || &bytes[ 0..4 ] != MAGIC {
+            return Err( "Not a geometry cache file".to_string() );
+        }
+
+        let version = read_u32( bytes, 4 );
+
+        if version != VERSION {
+            return Err( format!( "Unsupported geometry cache version {} (expected {})", version, VERSION ) );
+        }
+
+        // Sizes are computed in u64, so that a corrupt header can't overflow them on 32 bit (WASM)
+        let header = | i: usize | read_u32( bytes, 8 + i * 4 ) as u64;
+        let ( checksum, vertex_n, triangle_n, group_n, interval_n, id_n, id_bytes, length ) =
+            ( header( 0 ) as u32, header( 1 ), header( 2 ), header( 3 ), header( 4 ), header( 5 ), header( 6 ), header( 7 ) );
+
+        if length != bytes.len() as u64 {
+            return Err( format!( "The geometry cache has {} bytes, but should have {} (truncated?)", bytes.len(), length ) );
+        }
+
+        let sizes = [ 6 * 8, id_n * 6 * 8, vertex_n * 4, triangle_n * 4, group_n * 3 * 4, interval_n * 4, ( id_n + 1 ) * 4, id_bytes ];
+
+        if HEADER_SIZE as u64 + sizes.iter().map( |s| s.div_ceil( 8 ) * 8 ).sum::<u64>() != length {
+            return Err( "The sections of the geometry cache don't match its length".to_string() );
+        }
+
+        let sizes = sizes.map( |s| s as usize );
+
+        if crc32( &bytes[ HEADER_SIZE.. ] ) != checksum {
+            return Err( "Checksum mismatch: the geometry cache is corrupt".to_string() );
+        }
+
+        let mut offset = HEADER_SIZE;
+        let mut sections = sizes.iter().map( |size| {
+            let section = &bytes[ offset..offset + size ];
+            offset += padded( *size );
+            section
+        } );
+
+        let mut next = || sections.next().unwrap();
+
+        Ok( CacheView {
+            version,
+            transform: next(),
+            extents: next(),
+            vertices: next(),
+            triangles: next(),
+            groups: next(),
+            intervals: next(),
+            id_offsets: next(),
+            ids: next(),
+        } )
+
+    }
+
+    pub fn groups( &self ) -> Result<Vec<( String, u32, u32 )>, String> {
+
+        u32s( self.groups ).chunks_exact( 3 ).map( |g| match CO_TYPES.get( g[ 0 ] as usize ) {
+            Some( co_type ) => Ok( ( co_type.to_string(), g[ 1 ], g[ 2 ] ) ),
+            None => Err( format!( "Unknown CityObject type {} in the geometry cache", g[ 0 ] ) ),
+        } ).collect()
+
+    }
+
+    pub fn ids( &self ) -> Result<Vec<String>, String> {
+
+        let offsets = u32s( self.id_offsets );
+
+        offsets.windows( 2 ).map( |w| {
+            self.ids.get( w[ 0 ] as usize..w[ 1 ] as usize )
+                    .and_then( |id| std::str::from_utf8( id ).ok() )
+                    .map( |id| id.to_string() )
+                    .ok_or_else( || "Invalid ID table in the geometry cache".to_string() )
+        } ).collect()
+
+    }
+
+}
+
+impl GeometryCache {
+
+    /// Parse a CityJSON document into a cache, like parse_cityobjects() (with intervals) and parse_vertices() do. The picking index, joined
+    /// columns and edits of the file that is shown are left alone.
+    pub fn build( buffer: &[u8] ) -> Result<GeometryCache, String> {
+
+        let parse_error = | e: serde_json::Error | format!( "Error parsing CityJSON buffer: {}", e );
+
+        let ParsedCityObjects { triangles, groups, ids, index: intervals, .. } = to_bufferattributes::parse_local( buffer, PickingIndex::Intervals ).map_err( parse_error )?;
+        let vertices = to_bufferattributes::flatten_vertices( buffer ).map_err( parse_error )?;
+        let transform = format::from_slice::<TransformOnly>( buffer ).map_err( parse_error )?.transform
+                                   .map( |t| [ t.scale, t.translate ] ).unwrap_or( [ [ 1.0; 3 ], [ 0.0; 3 ] ] );
+
+        // Extent of every CityObject, from the vertices of its triangles
+        let extents = intervals.windows( 2 ).map( |w| {
+
+            let mut e = [ f64::INFINITY, f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY ];
+
+            for v in &triangles[ w[ 0 ] as usize * 3..w[ 1 ] as usize * 3 ] {
+                for c in 0..3 {
+                    if let Some( value ) = vertices.get( *v as usize * 3 + c ) {
+                        let value = *value as f64 * transform[ 0 ][ c ] + transform[ 1 ][ c ];
+                        e[ c ] = e[ c ].min( value );
+                        e[ c + 3 ] = e[ c + 3 ].max( value );
+                    }
+                }
+            }
+
+            e
+
+        } ).collect();
+
+        // Groups in the order of the triangles
+        let mut sorted: Vec<( String, u32, u32 )> = groups.into_iter().map( |( t, g )| ( t, g[ 0 ], g[ 1 ] ) ).collect();
+        sorted.sort_by_key( |g| g.1 );
+
+        Ok( GeometryCache { transform, extents, vertices, triangles, groups: sorted, intervals, ids } )
+
+    }
+
+    /// The cache file. Fails if it would be larger than 4 GiB, since the lengths and offsets in it are u32.
+    pub fn to_bytes( &self ) -> Result<Vec<u8>, String> {
+
+        let mut body: Vec<u8> = Vec::new();
+        let pad = | body: &mut Vec<u8> | body.resize( padded( body.len() ), 0 );
+
+        body.extend( self.transform.iter().flatten().flat_map( |v| v.to_le_bytes() ) );
+        pad( &mut body );
+        body.extend( self.extents.iter().flatten().flat_map( |v| v.to_le_bytes() ) );
+        pad( &mut body );
+        body.extend( self.vertices.iter().flat_map( |v| v.to_le_bytes() ) );
+        pad( &mut body );
+        body.extend( self.triangles.iter().flat_map( |v| v.to_le_bytes() ) );
+        pad( &mut body );
+
+        for ( co_type, start, count ) in &self.groups {
+            let index = CO_TYPES.iter().position( |t| t == co_type ).unwrap() as u32;
+            body.extend( [ index, *start, *count ].iter().flat_map( |v| v.to_le_bytes() ) );
+        }
+
+        pad( &mut body );
+        body.extend( self.intervals.iter().flat_map( |v| v.to_le_bytes() ) );
+        pad( &mut body );
+
+        // The offsets are at most the total length, which is checked below
+        let mut offset = 0usize;
+        body.extend_from_slice( &( offset as u32 ).to_le_bytes() );
+
+        for id in &self.ids {
+            offset += id.len();
+            body.extend_from_slice( &( offset as u32 ).to_le_bytes() );
+        }
+
+        pad( &mut body );
+
+        for id in &self.ids {
+            body.extend_from_slice( id.as_bytes() );
+        }
+
+        pad( &mut body );
+
+        let length = HEADER_SIZE + body.len();
+
+        if length > u32::MAX as usize {
+            return Err( format!( "The geometry cache would have {} bytes, but can have at most {}", length, u32::MAX ) );
+        }
+
+        let header = [ VERSION, crc32( &body ), self.vertices.len() as u32, self.triangles.len() as u32, self.groups.len() as u32,
+                       self.intervals.len() as u32, self.ids.len() as u32, offset as u32, length as u32, 0, 0 ];
+
+        let mut out = Vec::with_capacity( length );
+
+        out.extend_from_slice( MAGIC );
+        out.extend( header.iter().flat_map( |v| v.to_le_bytes() ) );
+        out.extend( body );
+
+        Ok( out )
+
+    }
+
+    pub fn from_bytes( bytes: &[u8] ) -> Result<GeometryCache, String> {
+
+        let view = CacheView::parse( bytes )?;
+        let transform = f64s( view.transform );
+
+        Ok( GeometryCache {
+            transform: [ [ transform[ 0 ], transform[ 1 ], transform[ 2 ] ], [ transform[ 3 ], transform[ 4 ], transform[ 5 ] ] ],
+            extents: f64s( view.extents ).chunks_exact( 6 ).map( |e| [ e[ 0 ], e[ 1 ], e[ 2 ], e[ 3 ], e[ 4 ], e[ 5 ] ] ).collect(),
+            vertices: u32s( view.vertices ),
+            triangles: u32s( view.triangles ),
+            groups: view.groups()?,
+            intervals: u32s( view.intervals ),
+            ids: view.ids()?,
+        } )
+
+    }
+
+}
+
+/// Make picking (pick(), pick_cityobject()) work with a loaded cache, as if the CityJSON file was parsed. Returns the IDs.
+/// The cache has no parents or attributes, so the hierarchy, the search index, the joined columns and the edits of the previous file are cleared.
+pub fn restore_picking( view: &CacheView ) -> Result<Vec<String>, String> {
+
+    let ids = view.ids()?;
+    let intervals = u32s( view.intervals );
+
+    if intervals.len() != ids.len() + 1 {
+        return Err( format!( "The geometry cache has {} triangle intervals for {} IDs", intervals.len(), ids.len() ) );
+    }
+
+    *IDS.lock().unwrap() = ids.clone();
+    *INTERVALS.lock().unwrap() = intervals;
+    *picking::PICKING.lock().unwrap() = PickingIndex::Intervals;
+    picking::OBJECT_INDEX.lock().unwrap().clear();
+    *HIERARCHY.lock().unwrap() = Hierarchy::default();
+    *search::SEARCH_INDEX.lock().unwrap() = SearchIndex::default();
+    join::clear_joins();
+    edit::clear_edits();
+
+    Ok( ids )
+
+}
@@ -0,0 +1,110 @@
+use std::io::Write;
+
+use flate2::write::{MultiGzDecoder, ZlibDecoder};
+
+///// Gzip and zlib compressed input (e.g. .city.json.gz). The compression is detected from the first two bytes, which can't be the start
+///// of a JSON document. A whole buffer can be decompressed at once (decompress()), or chunk by chunk into the push parser (Decoder).
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compression {
+
+    None,
+    Gzip,
+    Zlib,
+
+}
+
+/// Detect gzip (magic bytes 1f 8b) or zlib (a deflate header whose first two bytes are a multiple of 31) from the start of a file
+pub fn detect( bytes: &[u8] ) -> Compression {
+
+    match bytes {
+        [ 0x1f, 0x8b, .. ] => Compression::Gzip,
+        [ cmf, flg, .. ] if cmf & 0x0f == 8 && cmf >> 4 <= 7 && ( ( *cmf as u16 ) << 8 | *flg as u16 ).is_multiple_of( 31 ) => Compression::Zlib,
+        _ => Compression::None,
+    }
+
+}
+
+/// Decompress a buffer if it is compressed, otherwise return it as it is
+pub fn decompress( buffer: Vec<u8> ) -> Result<Vec<u8>, String> {
+
+    let mut decoder = match Decoder::new( detect( &buffer ) ) {
+        Some( decoder ) => decoder,
+        None => return Ok( buffer ),
+    };
+
+    let mut out = decoder.decode( &buffer )?;
+    out.append( &mut decoder.finish()? );
+
+    Ok( out )
+
+}
+
+enum Inner {
+
+    Gzip( MultiGzDecoder<Vec<u8>> ),
+    Zlib( ZlibDecoder<Vec<u8>> ),
+
+}
+
+/// Decompresses a file chunk by chunk. Only the output of the current chunk is kept.
+pub struct Decoder {
+
+    inner: Inner,
+
+}
+
+impl Decoder {
+
+    /// A decoder for compressed input, or None if it isn't compressed
+    pub fn new( compression: Compression ) -> Option<Decoder> {
+
+        let inner = match compression {
+            Compression::None => return None,
+            Compression::Gzip => Inner::Gzip( MultiGzDecoder::new( Vec::new() ) ),
+            Compression::Zlib => Inner::Zlib( ZlibDecoder::new( Vec::new() ) ),
+        };
+
+        Some( Decoder { inner } )
+
+    }
+
+    /// Decompress the next chunk, returning the decompressed bytes that are available
+    pub fn decode( &mut self, chunk: &[u8] ) -> Result<Vec<u8>, String> {
+
+        let res = match &mut self.inner {
+            Inner::Gzip( decoder ) => decoder.write_all( chunk ),
+            Inner::Zlib( decoder ) => decoder.write_all( chunk ),
+        };
+
+        res.map_err( |e| format!( "Could not decompress: {}", e ) )?;
+
+        Ok( self.take() )
+
+    }
+
+    /// The decompressed bytes that are left after the last chunk
+    pub fn finish( &mut self ) -> Result<Vec<u8>, String> {
+
+        let res = match &mut self.inner {
+            Inner::Gzip( decoder ) => decoder.try_finish(),
+            Inner::Zlib( decoder ) => decoder.try_finish(),
+        };
+
+        res.map_err( |e| format!( "Could not decompress: {}", e ) )?;
+
+        Ok( self.take() )
+
+    }
+
+    // Take the output so far out of the decoder
+    fn take( &mut self ) -> Vec<u8> {
+
+        match &mut self.inner {
+            Inner::Gzip( decoder ) => std::mem::take( decoder.get_mut() ),
+            Inner::Zlib( decoder ) => std::mem::take( decoder.get_mut() ),
+        }
+
+    }
+
+}
@@ -0,0 +1,296 @@
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Deserialize, Deserializer};
+use serde::de::{MapAccess, Visitor};
+use serde_json::{Map, Value};
+use serde_json::value::RawValue;
+
+use super::format::{self, Format};
+use super::join::{self, Joined};
+
+///// Attribute editing. The buffer stays as it is: edits are kept per CityObject and applied whenever CityObjects are read
+///// (like joined columns, see join.rs) until another file is parsed, and write_cityjson() writes a new document with the edits applied.
+///// For a filtered set of CityObjects, pass the ids of filter_cityobjects() to the edit functions.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edit {
+
+    Set( String, Value ),
+    Rename( String, String ),
+    Delete( String ),
+
+}
+
+// Edits per CityObject ID, in the order in which they were made
+pub type Edits = HashMap<String, Vec<Edit>>;
+
+lazy_static! {
+    pub static ref EDITS: Mutex<Edits> = Mutex::new(HashMap::new());
+}
+
+/// Apply edits to the attributes of a CityObject
+pub fn apply_to_attributes( attributes: &mut Map<String, Value>, edits: &[Edit] ) {
+
+    for edit in edits {
+
+        match edit {
+            Edit::Set( key, value ) => { attributes.insert( key.clone(), value.clone() ); },
+            Edit::Rename( from, to ) => {
+                if let Some( value ) = attributes.remove( from ) {
+                    attributes.insert( to.clone(), value );
+                }
+            },
+            Edit::Delete( key ) => { attributes.remove( key ); },
+        }
+
+    }
+
+}
+
+/// Apply the edits that were made to a CityObject
+pub fn apply_edits( id: &str, co: &mut Value ) {
+
+    let edits = EDITS.lock().unwrap();
+
+    if let ( Some( edits ), Some( co ) ) = ( edits.get( id ), co.as_object_mut() ) {
+
+        let attributes = co.entry( "attributes" ).or_insert( Value::Null );
+
+        // "attributes": null is the same as no attributes, as in write_cityjson()
+        if attributes.is_null() {
+            *attributes = Value::Object( Map::new() );
+        }
+
+        if let Some( attributes ) = attributes.as_object_mut() {
+            apply_to_attributes( attributes, edits );
+        }
+
+    }
+
+}
+
+// Value of an attribute in the output: the original JSON text, or a new value
+enum Member<'a> {
+
+    Raw( &'a RawValue ),
+    New( Value ),
+
+}
+
+// The members of a JSON object in their original order, with their values as raw JSON text
+struct OrderedMembers<'a>( Vec<( String, &'a RawValue )> );
+
+impl<'de> Deserialize<'de> for OrderedMembers<'de> {
+
+    fn deserialize<D>( deserializer: D ) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+
+        struct MembersVisitor;
+
+        impl<'de> Visitor<'de> for MembersVisitor {
+
+            type Value = OrderedMembers<'de>;
+
+            fn expecting( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+                formatter.write_str( "a JSON object" )
+            }
+
+            fn visit_map<S>( self, mut map: S ) -> Result<OrderedMembers<'de>, S::Error>
+            where
+                S: MapAccess<'de>,
+            {
+
+                let mut members = Vec::new();
+
+                while let Some( ( key, value ) ) = map.next_entry::<String, &'de RawValue>()? {
+                    members.push( ( key, value ) );
+                }
+
+                Ok( OrderedMembers( members ) )
+
+            }
+
+        }
+
+        deserializer.deserialize_map( MembersVisitor )
+
+    }
+
+}
+
+// Same as apply_to_attributes(), but keeps the order of the attributes and the original text of the values that are not changed
+fn apply_to_members<'a>( members: &mut Vec<( String, Member<'a> )>, edits: &[Edit] ) {
+
+    for edit in edits {
+
+        match edit {
+            Edit::Set( key, value ) => match members.iter_mut().find( |( k, _ )| k == key ) {
+                Some( member ) => member.1 = Member::New( value.clone() ),
+                None => members.push( ( key.clone(), Member::New( value.clone() ) ) ),
+            },
+            Edit::Rename( from, to ) => {
+                if from != to && members.iter().any( |( k, _ )| k == from ) {
+                    members.retain( |( k, _ )| k != to );
+                    if let Some( member ) = members.iter_mut().find( |( k, _ )| k == from ) {
+                        member.0 = to.clone();
+                    }
+                }
+            },
+            Edit::Delete( key ) => members.retain( |( k, _ )| k != key ),
+        }
+
+    }
+
+}
+
+fn write_members( members: &[( String, Member )] ) -> String {
+
+    let members: Vec<String> = members.iter().map( |( key, value )| {
+
+        let value = match value {
+            Member::Raw( raw ) => raw.get().to_string(),
+            Member::New( value ) => value.to_string(),
+        };
+
+        format!( "{}:{}", Value::String( key.clone() ), value )
+
+    } ).collect();
+
+    format!( "{{{}}}", members.join( "," ) )
+
+}
+
+#[derive(Deserialize)]
+struct RawDocument<'a> {
+
+    #[serde(borrow)]
+    #[serde(rename = "CityObjects")]
+    city_objects: HashMap<String, &'a RawValue>,
+
+}
+
+/// Write a CityJSON document with the edits (and optionally the joined columns) applied. Only the "attributes" of changed CityObjects
+/// are rewritten; all other bytes of the document (formatting, key order, numbers, ...) are copied from the original.
+pub fn write_cityjson( buffer: &[u8], edits: &Edits, joined: Option<&Joined> ) -> Result<Vec<u8>, serde_json::Error> {
+
+    // The original bytes are copied, so the edits can only be written into JSON text
+    if format::detect( buffer ) != Format::Json {
+        return Err( serde::de::Error::custom( "Edits can only be written into a CityJSON document in JSON, not CBOR or MessagePack" ) );
+    }
+
+    let doc: RawDocument = serde_json::from_slice( buffer )?;
+    let offset = | raw: &RawValue | raw.get().as_ptr() as usize - buffer.as_ptr() as usize;
+
+    // ( start, end, replacement ) in the original buffer
+    let mut replacements: Vec<( usize, usize, String )> = Vec::new();
+
+    for ( id, co ) in doc.city_objects {
+
+        // Joined columns are written as if they were set, before the edits
+        let mut co_edits: Vec<Edit> = joined.and_then( |j| j.get( &id ) ).into_iter().flatten()
+                                            .map( |( k, v )| Edit::Set( k.clone(), v.clone() ) ).collect();
+        co_edits.extend( edits.get( &id ).into_iter().flatten().cloned() );
+
+        if co_edits.is_empty() {
+            continue;
+        }
+
+        let co_members: OrderedMembers = serde_json::from_str( co.get() )?;
+        let attributes = co_members.0.iter().find( |( key, _ )| key == "attributes" ).map( |( _, raw )| *raw );
+
+        let mut members: Vec<( String, Member )> = match attributes {
+            Some( raw ) if raw.get() != "null" => serde_json::from_str::<OrderedMembers>( raw.get() )?.0.into_iter().map( |( k, v )| ( k, Member::Raw( v ) ) ).collect(),
+            _ => Vec::new(),
+        };
+
+        apply_to_members( &mut members, &co_edits );
+
+        match attributes {
+            Some( raw ) => replacements.push( ( offset( raw ), offset( raw ) + raw.get().len(), write_members( &members ) ) ),
+            None => {
+                // Add an "attributes" member before the closing brace of the CityObject
+                let end = offset( co ) + co.get().rfind( '}' ).unwrap();
+                let separator = if co_members.0.is_empty() { "" } else { "," };
+                replacements.push( ( end, end, format!( "{}\"attributes\":{}", separator, write_members( &members ) ) ) );
+            },
+        }
+
+    }
+
+    replacements.sort_by_key( |( start, _, _ )| *start );
+
+    let mut out = Vec::with_capacity( buffer.len() );
+    let mut position = 0;
+
+    for ( start, end, text ) in replacements {
+        out.extend_from_slice( &buffer[ position..start ] );
+        out.extend_from_slice( text.as_bytes() );
+        position = end;
+    }
+
+    out.extend_from_slice( &buffer[ position.. ] );
+
+    Ok( out )
+
+}
+
+fn add_edit( ids: &[String], edit: Edit ) {
+
+    let mut edits = EDITS.lock().unwrap();
+
+    for id in ids {
+        edits.entry( id.clone() ).or_default().push( edit.clone() );
+    }
+
+}
+
+/// Set an attribute of the given CityObjects to a value (any JSON value)
+pub fn set_attribute( ids: &[String], key: &str, value: Value ) {
+
+    add_edit( ids, Edit::Set( key.to_string(), value ) );
+
+}
+
+/// Rename an attribute of the given CityObjects. An existing attribute with the new name is replaced.
+pub fn rename_attribute( ids: &[String], from: &str, to: &str ) {
+
+    add_edit( ids, Edit::Rename( from.to_string(), to.to_string() ) );
+
+}
+
+/// Delete an attribute of the given CityObjects
+pub fn delete_attribute( ids: &[String], key: &str ) {
+
+    add_edit( ids, Edit::Delete( key.to_string() ) );
+
+}
+
+/// The IDs of the CityObjects that have been edited
+pub fn edited_ids() -> Vec<String> {
+
+    EDITS.lock().unwrap().keys().cloned().collect()
+
+}
+
+/// Forget all edits
+pub fn clear_edits() {
+
+    EDITS.lock().unwrap().clear();
+
+}
+
+/// The CityJSON document with the current edits applied. If include_joined is true, the columns of joined tables are written as attributes too.
+pub fn write_edited_cityjson( buffer: &[u8], include_joined: bool ) -> Result<Vec<u8>, String> {
+
+    log!("Writing CityJSON with edited attributes...");
+
+    let edits = EDITS.lock().unwrap();
+    let joined = join::JOINED.lock().unwrap();
+
+    write_cityjson( buffer, &edits, if include_joined { Some( &joined ) } else { None } ).map_err( |e| format!( "Error parsing CityJSON buffer: {}", e ) )
+
+}
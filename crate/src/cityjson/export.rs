@@ -0,0 +1,163 @@
+use std::fmt::Write;
+
+use super::mesh::{self, Mesh};
+
+///// Plain mesh exports (OBJ + MTL, binary STL, binary PLY) of the same triangles as parse_cityobjects(), for 3D printing and for checking
+///// the model in e.g. MeshLab. Coordinates are written as they are in CityJSON (Z-up), in real coordinates unless recentring is requested.
+
+// OBJ names can't contain whitespace
+fn obj_name( name: &str ) -> String {
+
+    name.chars().map( |c| if c.is_whitespace() { '_' } else { c } ).collect()
+
+}
+
+/// Write a mesh as OBJ, with an object and group per CityObject and a material per CityObject type. mtl_name is the file name of the MTL.
+pub fn write_obj( mesh: &Mesh, mtl_name: &str ) -> String {
+
+    let ( vertices, triangles ) = mesh.compact();
+    let mut out = String::new();
+
+    writeln!( out, "# {} CityObjects, {} triangles", mesh.features.len(), triangles.len() / 3 ).unwrap();
+    writeln!( out, "mtllib {}", mtl_name ).unwrap();
+
+    for v in &vertices {
+        writeln!( out, "v {} {} {}", v[ 0 ], v[ 1 ], v[ 2 ] ).unwrap();
+    }
+
+    let mut feature = u32::MAX;
+    let mut group = 0;
+
+    for t in 0..triangles.len() / 3 {
+
+        // Triangles are sorted by group (type), and by CityObject within a group
+        while t * 3 >= ( mesh.groups[ group ].start + mesh.groups[ group ].count ) as usize {
+            group += 1;
+        }
+
+        if mesh.triangle_features[ t ] != feature {
+
+            feature = mesh.triangle_features[ t ];
+
+            let name = obj_name( &mesh.features[ feature as usize ].id );
+
+            writeln!( out, "o {}", name ).unwrap();
+            writeln!( out, "g {}", name ).unwrap();
+            writeln!( out, "usemtl {}", mesh.groups[ group ].co_type ).unwrap();
+
+        }
+
+        // OBJ indices start at 1
+        writeln!( out, "f {} {} {}", triangles[ t * 3 ] + 1, triangles[ t * 3 + 1 ] + 1, triangles[ t * 3 + 2 ] + 1 ).unwrap();
+
+    }
+
+    out
+
+}
+
+/// Write the materials of the OBJ: one per CityObject type in the mesh, with the default colors of the viewer
+pub fn write_mtl( mesh: &Mesh ) -> String {
+
+    let mut out = String::new();
+
+    for group in &mesh.groups {
+
+        let color = mesh::type_color( &group.co_type );
+
+        writeln!( out, "newmtl {}", group.co_type ).unwrap();
+        writeln!( out, "Kd {:.4} {:.4} {:.4}", color[ 0 ] as f64 / 255.0, color[ 1 ] as f64 / 255.0, color[ 2 ] as f64 / 255.0 ).unwrap();
+        writeln!( out, "d 1.0" ).unwrap();
+        writeln!( out, "illum 1" ).unwrap();
+        writeln!( out ).unwrap();
+
+    }
+
+    out
+
+}
+
+/// Write a mesh as binary STL. STL has single precision coordinates, so recentring is recommended for real-world coordinates.
+pub fn write_stl( mesh: &Mesh ) -> Vec<u8> {
+
+    let triangle_n = mesh.triangles.len() / 3;
+    let mut out = Vec::with_capacity( 84 + triangle_n * 50 );
+
+    let mut header = format!( "Binary STL, {} CityObjects", mesh.features.len() ).into_bytes();
+    header.resize( 80, b' ' );
+
+    out.extend_from_slice( &header );
+    out.extend_from_slice( &( triangle_n as u32 ).to_le_bytes() );
+
+    for t in 0..triangle_n {
+
+        let n = mesh.normal( t );
+
+        for c in &n {
+            out.extend_from_slice( &( *c as f32 ).to_le_bytes() );
+        }
+
+        for v in &mesh.triangles[ t * 3..t * 3 + 3 ] {
+            for c in &mesh.vertices[ *v as usize ] {
+                out.extend_from_slice( &( *c as f32 ).to_le_bytes() );
+            }
+        }
+
+        // Attribute byte count
+        out.extend_from_slice( &0u16.to_le_bytes() );
+
+    }
+
+    out
+
+}
+
+/// Write a mesh as binary PLY. Every face has an object_index property, which is the index of its CityObject in the "cityobject" element
+/// (with the IDs) - the same order as the feature IDs of the GLB export.
+pub fn write_ply( mesh: &Mesh ) -> Vec<u8> {
+
+    let ( vertices, triangles ) = mesh.compact();
+    let mut header = String::new();
+
+    writeln!( header, "ply" ).unwrap();
+    writeln!( header, "format binary_little_endian 1.0" ).unwrap();
+    writeln!( header, "comment CityJSON export" ).unwrap();
+    writeln!( header, "element vertex {}", vertices.len() ).unwrap();
+    writeln!( header, "property double x" ).unwrap();
+    writeln!( header, "property double y" ).unwrap();
+    writeln!( header, "property double z" ).unwrap();
+    writeln!( header, "element face {}", triangles.len() / 3 ).unwrap();
+    writeln!( header, "property list uchar uint vertex_indices" ).unwrap();
+    writeln!( header, "property uint object_index" ).unwrap();
+    writeln!( header, "element cityobject {}", mesh.features.len() ).unwrap();
+    writeln!( header, "property list uint uchar id" ).unwrap();
+    writeln!( header, "end_header" ).unwrap();
+
+    let mut out = header.into_bytes();
+
+    for v in &vertices {
+        for c in v {
+            out.extend_from_slice( &c.to_le_bytes() );
+        }
+    }
+
+    for ( t, triangle ) in triangles.chunks( 3 ).enumerate() {
+
+        out.push( 3 );
+
+        for v in triangle {
+            out.extend_from_slice( &v.to_le_bytes() );
+        }
+
+        out.extend_from_slice( &mesh.triangle_features[ t ].to_le_bytes() );
+
+    }
+
+    for feature in &mesh.features {
+        out.extend_from_slice( &( feature.id.len() as u32 ).to_le_bytes() );
+        out.extend_from_slice( feature.id.as_bytes() );
+    }
+
+    out
+
+}
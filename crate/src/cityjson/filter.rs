@@ -0,0 +1,656 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::fmt;
+
+use super::picking::{self, TriangleInterval};
+use super::stream;
+
+///// A small expression language over the type and attributes of CityObjects, e.g.
+///// type == "Building" && measuredHeight > 20 && roofType in ["1000", "2100"]
+/////
+///// Fields: `type` and `id` refer to the CityObject itself, any other name to one of its attributes. Nested values can be reached
+///// with dots (`attributes.type` or `address.street`), and names with other characters can be quoted with backticks.
+///// Operators: == != < <= > >= in, combined with && || ! and parentheses. A field on its own is true if it exists and is not null or false.
+
+#[derive(Debug, PartialEq)]
+pub struct FilterError {
+
+    pub message: String,
+    // Character offset in the expression
+    pub position: usize,
+
+}
+
+impl fmt::Display for FilterError {
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+        write!( f, "{} at position {}", self.message, self.position )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+
+    Field( Vec<String> ),
+    Literal( Value ),
+    Op( CmpOp ),
+    In,
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CmpOp {
+
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+
+    Field( Vec<String> ),
+    Literal( Value ),
+
+}
+
+/// A parsed filter expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+
+    Or( Box<Expr>, Box<Expr> ),
+    And( Box<Expr>, Box<Expr> ),
+    Not( Box<Expr> ),
+    Compare( Operand, CmpOp, Operand ),
+    In( Operand, Vec<Value> ),
+    Truthy( Operand ),
+
+}
+
+fn tokenize( input: &str ) -> Result<Vec<( Token, usize )>, FilterError> {
+
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    let error = | message: String, position: usize | FilterError { message, position };
+
+    while i < chars.len() {
+
+        let c = chars[ i ];
+        let start = i;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let two: String = chars[ i..( i + 2 ).min( chars.len() ) ].iter().collect();
+
+        let token = match two.as_str() {
+            "==" => Some( Token::Op( CmpOp::Eq ) ),
+            "!=" => Some( Token::Op( CmpOp::Ne ) ),
+            "<=" => Some( Token::Op( CmpOp::Le ) ),
+            ">=" => Some( Token::Op( CmpOp::Ge ) ),
+            "&&" => Some( Token::And ),
+            "||" => Some( Token::Or ),
+            _ => None,
+        };
+
+        if let Some( token ) = token {
+            tokens.push( ( token, start ) );
+            i += 2;
+            continue;
+        }
+
+        let token = match c {
+            '<' => Some( Token::Op( CmpOp::Lt ) ),
+            '>' => Some( Token::Op( CmpOp::Gt ) ),
+            '!' => Some( Token::Not ),
+            '(' => Some( Token::LParen ),
+            ')' => Some( Token::RParen ),
+            '[' => Some( Token::LBracket ),
+            ']' => Some( Token::RBracket ),
+            ',' => Some( Token::Comma ),
+            _ => None,
+        };
+
+        if let Some( token ) = token {
+            tokens.push( ( token, start ) );
+            i += 1;
+            continue;
+        }
+
+        if c == '=' {
+            return Err( error( "Unexpected '=', use '==' for comparisons".to_string(), start ) );
+        }
+
+        // String literal, with " or ' and backslash escapes
+        if c == '"' || c == '\'' {
+
+            let mut s = String::new();
+            i += 1;
+
+            loop {
+
+                match chars.get( i ) {
+                    None => return Err( error( "Unterminated string".to_string(), start ) ),
+                    Some( '\\' ) => {
+                        match chars.get( i + 1 ) {
+                            Some( escaped ) => s.push( *escaped ),
+                            None => return Err( error( "Unterminated string".to_string(), start ) ),
+                        }
+                        i += 2;
+                    },
+                    Some( q ) if *q == c => {
+                        i += 1;
+                        break;
+                    },
+                    Some( other ) => {
+                        s.push( *other );
+                        i += 1;
+                    },
+                }
+
+            }
+
+            tokens.push( ( Token::Literal( Value::String( s ) ), start ) );
+            continue;
+
+        }
+
+        // Number literal
+        if c.is_ascii_digit() || ( c == '-' && chars.get( i + 1 ).map( |n| n.is_ascii_digit() || *n == '.' ).unwrap_or( false ) ) || c == '.' {
+
+            i += 1;
+
+            while i < chars.len() && ( chars[ i ].is_ascii_digit() || chars[ i ] == '.' || chars[ i ] == 'e' || chars[ i ] == 'E'
+                                       || ( ( chars[ i ] == '-' || chars[ i ] == '+' ) && ( chars[ i - 1 ] == 'e' || chars[ i - 1 ] == 'E' ) ) ) {
+                i += 1;
+            }
+
+            let text: String = chars[ start..i ].iter().collect();
+            let number = match text.parse::<i64>() {
+                Ok( integer ) => serde_json::Number::from( integer ),
+                Err( _ ) => text.parse::<f64>().ok()
+                                .and_then( serde_json::Number::from_f64 )
+                                .ok_or_else( || error( format!( "Invalid number '{}'", text ), start ) )?,
+            };
+
+            tokens.push( ( Token::Literal( Value::Number( number ) ), start ) );
+            continue;
+
+        }
+
+        // Field, keyword or backtick-quoted field, optionally with dotted path segments
+        if c.is_alphabetic() || c == '_' || c == '`' {
+
+            let mut path = Vec::new();
+            let mut quoted_first = false;
+
+            loop {
+
+                if chars.get( i ) == Some( &'`' ) {
+
+                    let segment_start = i;
+                    i += 1;
+
+                    let mut segment = String::new();
+
+                    loop {
+                        match chars.get( i ) {
+                            None => return Err( error( "Unterminated quoted field".to_string(), segment_start ) ),
+                            Some( '`' ) => {
+                                i += 1;
+                                break;
+                            },
+                            Some( other ) => {
+                                segment.push( *other );
+                                i += 1;
+                            },
+                        }
+                    }
+
+                    quoted_first |= path.is_empty();
+                    path.push( segment );
+
+                } else {
+
+                    let segment_start = i;
+
+                    while i < chars.len() && ( chars[ i ].is_alphanumeric() || chars[ i ] == '_' || chars[ i ] == ':' || chars[ i ] == '-' ) {
+                        i += 1;
+                    }
+
+                    if i == segment_start {
+                        return Err( error( "Expected a field name after '.'".to_string(), segment_start ) );
+                    }
+
+                    path.push( chars[ segment_start..i ].iter().collect() );
+
+                }
+
+                if chars.get( i ) == Some( &'.' ) {
+                    i += 1;
+                } else {
+                    break;
+                }
+
+            }
+
+            let token = if path.len() == 1 && !quoted_first {
+                match path[ 0 ].as_str() {
+                    "in" => Token::In,
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "true" => Token::Literal( Value::Bool( true ) ),
+                    "false" => Token::Literal( Value::Bool( false ) ),
+                    "null" => Token::Literal( Value::Null ),
+                    _ => Token::Field( path ),
+                }
+            } else {
+                Token::Field( path )
+            };
+
+            tokens.push( ( token, start ) );
+            continue;
+
+        }
+
+        return Err( error( format!( "Unexpected character '{}'", c ), start ) );
+
+    }
+
+    Ok( tokens )
+
+}
+
+struct Parser {
+
+    tokens: Vec<( Token, usize )>,
+    position: usize,
+    end: usize,
+
+}
+
+impl Parser {
+
+    fn peek( &self ) -> Option<&Token> {
+        self.tokens.get( self.position ).map( |( token, _ )| token )
+    }
+
+    fn offset( &self ) -> usize {
+        self.tokens.get( self.position ).map( |( _, offset )| *offset ).unwrap_or( self.end )
+    }
+
+    fn error<T>( &self, message: &str ) -> Result<T, FilterError> {
+
+        let found = match self.peek() {
+            Some( token ) => format!( "{}, found {}", message, describe( token ) ),
+            None => format!( "{}, found end of expression", message ),
+        };
+
+        Err( FilterError { message: found, position: self.offset() } )
+
+    }
+
+    fn next( &mut self ) -> Option<Token> {
+
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+
+    }
+
+    fn expect( &mut self, expected: Token, message: &str ) -> Result<(), FilterError> {
+
+        if self.peek() == Some( &expected ) {
+            self.position += 1;
+            return Ok( () );
+        }
+
+        self.error( message )
+
+    }
+
+    fn or( &mut self ) -> Result<Expr, FilterError> {
+
+        let mut left = self.and()?;
+
+        while self.peek() == Some( &Token::Or ) {
+            self.position += 1;
+            left = Expr::Or( Box::new( left ), Box::new( self.and()? ) );
+        }
+
+        Ok( left )
+
+    }
+
+    fn and( &mut self ) -> Result<Expr, FilterError> {
+
+        let mut left = self.not()?;
+
+        while self.peek() == Some( &Token::And ) {
+            self.position += 1;
+            left = Expr::And( Box::new( left ), Box::new( self.not()? ) );
+        }
+
+        Ok( left )
+
+    }
+
+    fn not( &mut self ) -> Result<Expr, FilterError> {
+
+        match self.peek() {
+            Some( Token::Not ) => {
+                self.position += 1;
+                Ok( Expr::Not( Box::new( self.not()? ) ) )
+            },
+            Some( Token::LParen ) => {
+                self.position += 1;
+                let expr = self.or()?;
+                self.expect( Token::RParen, "Expected ')'" )?;
+                Ok( expr )
+            },
+            _ => self.comparison(),
+        }
+
+    }
+
+    fn comparison( &mut self ) -> Result<Expr, FilterError> {
+
+        let left = self.operand()?;
+
+        match self.peek() {
+            Some( Token::Op( op ) ) => {
+                let op = *op;
+                self.position += 1;
+                let right = self.operand()?;
+                Ok( Expr::Compare( left, op, right ) )
+            },
+            Some( Token::In ) => {
+                self.position += 1;
+                Ok( Expr::In( left, self.list()? ) )
+            },
+            _ => Ok( Expr::Truthy( left ) ),
+        }
+
+    }
+
+    fn operand( &mut self ) -> Result<Operand, FilterError> {
+
+        match self.peek() {
+            Some( Token::Field( _ ) ) | Some( Token::Literal( _ ) ) => {},
+            _ => return self.error( "Expected a field or a value" ),
+        }
+
+        match self.next() {
+            Some( Token::Field( path ) ) => Ok( Operand::Field( path ) ),
+            Some( Token::Literal( value ) ) => Ok( Operand::Literal( value ) ),
+            _ => unreachable!(),
+        }
+
+    }
+
+    fn list( &mut self ) -> Result<Vec<Value>, FilterError> {
+
+        self.expect( Token::LBracket, "Expected '[' after 'in'" )?;
+
+        let mut values = Vec::new();
+
+        if self.peek() == Some( &Token::RBracket ) {
+            self.position += 1;
+            return Ok( values );
+        }
+
+        loop {
+
+            match self.peek() {
+                Some( Token::Literal( value ) ) => {
+                    values.push( value.clone() );
+                    self.position += 1;
+                },
+                _ => return self.error( "Expected a value in list" ),
+            }
+
+            match self.next() {
+                Some( Token::Comma ) => continue,
+                Some( Token::RBracket ) => break,
+                _ => {
+                    self.position -= 1;
+                    return self.error( "Expected ',' or ']'" );
+                },
+            }
+
+        }
+
+        Ok( values )
+
+    }
+
+}
+
+fn describe( token: &Token ) -> String {
+
+    match token {
+        Token::Field( path ) => format!( "field '{}'", path.join( "." ) ),
+        Token::Literal( value ) => format!( "value {}", value ),
+        Token::Op( op ) => format!( "'{}'", match op { CmpOp::Eq => "==", CmpOp::Ne => "!=", CmpOp::Lt => "<", CmpOp::Le => "<=", CmpOp::Gt => ">", CmpOp::Ge => ">=" } ),
+        Token::In => "'in'".to_string(),
+        Token::And => "'&&'".to_string(),
+        Token::Or => "'||'".to_string(),
+        Token::Not => "'!'".to_string(),
+        Token::LParen => "'('".to_string(),
+        Token::RParen => "')'".to_string(),
+        Token::LBracket => "'['".to_string(),
+        Token::RBracket => "']'".to_string(),
+        Token::Comma => "','".to_string(),
+    }
+
+}
+
+/// Parse a filter expression
+pub fn parse( input: &str ) -> Result<Expr, FilterError> {
+
+    let tokens = tokenize( input )?;
+
+    if tokens.is_empty() {
+        return Err( FilterError { message: "Empty expression".to_string(), position: 0 } );
+    }
+
+    let mut parser = Parser { tokens, position: 0, end: input.chars().count() };
+    let expr = parser.or()?;
+
+    if parser.peek().is_some() {
+        return parser.error( "Expected '&&', '||' or end of expression" );
+    }
+
+    Ok( expr )
+
+}
+
+/// Parse a single field (e.g. `measuredHeight` or `address.street`), with the same syntax as in expressions
+pub fn parse_field( input: &str ) -> Result<Vec<String>, FilterError> {
+
+    match parse( input )? {
+        Expr::Truthy( Operand::Field( path ) ) => Ok( path ),
+        _ => Err( FilterError { message: format!( "Expected a single field, found '{}'", input ), position: 0 } ),
+    }
+
+}
+
+/// Look up a field of a CityObject. Missing fields are None.
+pub fn resolve<'a>( path: &[String], id: &'a Value, co: &'a Value ) -> Option<&'a Value> {
+
+    let ( mut current, rest ) = match path[ 0 ].as_str() {
+        "id" if path.len() == 1 => return Some( id ),
+        "type" if path.len() == 1 => return co.get( "type" ),
+        "attributes" if path.len() > 1 => ( co.get( "attributes" )?, &path[ 1.. ] ),
+        "address" => ( co.get( "address" ).or_else( || co.get( "attributes" )?.get( "address" ) )?, &path[ 1.. ] ),
+        _ => ( co.get( "attributes" )?, path ),
+    };
+
+    for segment in rest {
+
+        current = match current {
+            Value::Object( map ) => map.get( segment )?,
+            // CityJSON 1.1 addresses are arrays, index them or take the first one
+            Value::Array( items ) => match segment.parse::<usize>() {
+                Ok( index ) => items.get( index )?,
+                Err( _ ) => items.first()?.get( segment )?,
+            },
+            _ => return None,
+        };
+
+    }
+
+    Some( current )
+
+}
+
+// Numbers are often stored as strings in CityJSON attributes, so a number is compared with a numeric string as a number
+pub fn as_number( value: &Value ) -> Option<f64> {
+
+    match value {
+        Value::Number( n ) => n.as_f64(),
+        Value::String( s ) => s.trim().parse().ok(),
+        _ => None,
+    }
+
+}
+
+fn compare( a: &Value, b: &Value ) -> Option<Ordering> {
+
+    match ( a, b ) {
+        ( Value::String( a ), Value::String( b ) ) => Some( a.cmp( b ) ),
+        ( Value::Number( _ ), _ ) | ( _, Value::Number( _ ) ) => as_number( a )?.partial_cmp( &as_number( b )? ),
+        ( Value::Bool( a ), Value::Bool( b ) ) => Some( a.cmp( b ) ),
+        ( Value::Null, Value::Null ) => Some( Ordering::Equal ),
+        _ => None,
+    }
+
+}
+
+fn equals( a: Option<&Value>, b: Option<&Value> ) -> bool {
+
+    match ( a, b ) {
+        ( None, None ) | ( None, Some( Value::Null ) ) | ( Some( Value::Null ), None ) => true,
+        ( Some( a ), Some( b ) ) => compare( a, b ) == Some( Ordering::Equal ) || a == b,
+        _ => false,
+    }
+
+}
+
+impl Expr {
+
+    /// Evaluate the expression for one CityObject
+    pub fn matches( &self, id: &str, co: &Value ) -> bool {
+
+        let id = Value::String( id.to_string() );
+
+        self.eval( &id, co )
+
+    }
+
+    fn eval( &self, id: &Value, co: &Value ) -> bool {
+
+        let value = | operand: &'_ Operand | -> Option<Value> {
+            match operand {
+                Operand::Field( path ) => resolve( path, id, co ).cloned(),
+                Operand::Literal( value ) => Some( value.clone() ),
+            }
+        };
+
+        match self {
+            Expr::Or( a, b ) => a.eval( id, co ) || b.eval( id, co ),
+            Expr::And( a, b ) => a.eval( id, co ) && b.eval( id, co ),
+            Expr::Not( a ) => !a.eval( id, co ),
+            Expr::Compare( a, op, b ) => {
+
+                let ( a, b ) = ( value( a ), value( b ) );
+
+                match op {
+                    CmpOp::Eq => equals( a.as_ref(), b.as_ref() ),
+                    CmpOp::Ne => !equals( a.as_ref(), b.as_ref() ),
+                    _ => {
+                        // Ordering against a missing value or between different types is never true
+                        let ordering = match ( a, b ) {
+                            ( Some( a ), Some( b ) ) => compare( &a, &b ),
+                            _ => None,
+                        };
+                        match ( op, ordering ) {
+                            ( CmpOp::Lt, Some( o ) ) => o == Ordering::Less,
+                            ( CmpOp::Le, Some( o ) ) => o != Ordering::Greater,
+                            ( CmpOp::Gt, Some( o ) ) => o == Ordering::Greater,
+                            ( CmpOp::Ge, Some( o ) ) => o != Ordering::Less,
+                            _ => false,
+                        }
+                    },
+                }
+
+            },
+            Expr::In( a, list ) => {
+                let a = value( a );
+                list.iter().any( |item| equals( a.as_ref(), Some( item ) ) )
+            },
+            Expr::Truthy( a ) => !matches!( value( a ), None | Some( Value::Null ) | Some( Value::Bool( false ) ) ),
+        }
+
+    }
+
+}
+
+/// Stream over the CityObjects in a CityJSON document and return the IDs of the ones that match the expression, in file order
+pub fn filter_ids( buffer: &[u8], expr: &Expr ) -> Result<Vec<String>, serde_json::Error> {
+
+    let mut ids = Vec::new();
+
+    stream::for_each_cityobject( buffer, |id, co| {
+
+        if expr.matches( &id, &co ) {
+            ids.push( id );
+        }
+
+    } )?;
+
+    Ok( ids )
+
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FilterResult {
+
+    pub ids: Vec<String>,
+    // Triangle intervals of the matching CityObjects that have triangles
+    pub intervals: Vec<TriangleInterval>,
+
+}
+
+/// The CityObjects that match a filter expression, e.g. `type == "Building" && measuredHeight > 20`, with their triangle intervals
+pub fn filter_cityobjects( buffer: &[u8], expression: &str ) -> Result<FilterResult, String> {
+
+    log!("Filtering CityObjects with {}...", expression);
+
+    let expr = parse( expression ).map_err( |e| format!( "Invalid filter: {}", e ) )?;
+    let ids = filter_ids( buffer, &expr ).map_err( |e| format!( "Error parsing CityJSON buffer: {}", e ) )?;
+
+    let intervals = picking::triangle_ranges( &ids );
+
+    log!("{} CityObjects match", ids.len());
+
+    Ok( FilterResult { ids, intervals } )
+
+}
@@ -0,0 +1,134 @@
+use serde::de::{Deserialize, DeserializeSeed, Deserializer};
+use serde_cbor::ser::IoWrite;
+use std::cell::Cell;
+use std::fmt;
+use std::marker::PhantomData;
+
+///// CityJSON encoded as CBOR or MessagePack instead of JSON text. The data model is the same, so the Serde deserializers and visitors of
+///// the core work on all three; this module detects the encoding from the first byte and runs them with the right Serde deserializer.
+///// Errors of the binary formats are converted into serde_json::Error, which the rest of the core returns.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+
+    Json,
+    Cbor,
+    MessagePack,
+
+}
+
+// The format of the buffer that is being deserialized on this thread, for the visitors that treat JSON differently (see current())
+thread_local! {
+    static CURRENT: Cell<Format> = const { Cell::new(Format::Json) };
+}
+
+/// Detect the encoding from the start of a document, which is a map: a CBOR map (major type 5, or the self-describe tag 55799),
+/// a MessagePack map (fixmap, map 16 or map 32), or otherwise JSON text
+pub fn detect( buffer: &[u8] ) -> Format {
+
+    match buffer {
+        [ 0xa0..=0xbf, .. ] | [ 0xd9, 0xd9, 0xf7, .. ] => Format::Cbor,
+        [ 0x80..=0x8f, .. ] | [ 0xde, .. ] | [ 0xdf, .. ] => Format::MessagePack,
+        _ => Format::Json,
+    }
+
+}
+
+/// The format of the buffer that is being deserialized on this thread (JSON outside of deserialize_seed())
+pub fn current() -> Format {
+
+    CURRENT.with( |c| c.get() )
+
+}
+
+// Restores the format of an outer deserialize_seed()
+struct Restore( Format );
+
+impl Drop for Restore {
+
+    fn drop( &mut self ) {
+
+        CURRENT.with( |c| c.set( self.0 ) );
+
+    }
+
+}
+
+fn error( e: impl fmt::Display ) -> serde_json::Error {
+
+    <serde_json::Error as serde::de::Error>::custom( e )
+
+}
+
+/// Run a DeserializeSeed over a buffer in any of the formats
+pub fn deserialize_seed<'de, S: DeserializeSeed<'de>>( buffer: &'de [u8], seed: S ) -> Result<S::Value, serde_json::Error> {
+
+    let format = detect( buffer );
+    let _restore = Restore( CURRENT.with( |c| c.replace( format ) ) );
+
+    match format {
+        Format::Json => {
+
+            let mut deserializer = serde_json::Deserializer::from_slice( buffer );
+            let value = seed.deserialize( &mut deserializer )?;
+
+            deserializer.end()?;
+
+            Ok( value )
+
+        },
+        Format::Cbor => {
+
+            let mut deserializer = serde_cbor::Deserializer::from_slice( buffer );
+            let value = seed.deserialize( &mut deserializer ).map_err( error )?;
+
+            deserializer.end().map_err( error )?;
+
+            Ok( value )
+
+        },
+        Format::MessagePack => {
+
+            let mut deserializer = rmp_serde::Deserializer::from_read_ref( buffer );
+
+            seed.deserialize( &mut deserializer ).map_err( error )
+
+        },
+    }
+
+}
+
+/// Like serde_json::from_slice(), for a buffer in any of the formats
+pub fn from_slice<'de, T: Deserialize<'de>>( buffer: &'de [u8] ) -> Result<T, serde_json::Error> {
+
+    deserialize_seed( buffer, PhantomData )
+
+}
+
+// Write the values of a deserializer in a format, keeping the order of the members
+fn transcode<'de, D: Deserializer<'de>>( deserializer: D, format: Format ) -> Result<Vec<u8>, String> {
+
+    let mut out = Vec::new();
+
+    match format {
+        Format::Json => serde_transcode::transcode( deserializer, &mut serde_json::Serializer::new( &mut out ) ).map_err( |e| e.to_string() )?,
+        Format::Cbor => serde_transcode::transcode( deserializer, &mut serde_cbor::Serializer::new( IoWrite::new( &mut out ) ) ).map_err( |e| e.to_string() )?,
+        Format::MessagePack => serde_transcode::transcode( deserializer, &mut rmp_serde::Serializer::new( &mut out ) ).map_err( |e| e.to_string() )?,
+    }
+
+    Ok( out )
+
+}
+
+/// Re-encode a CityJSON document (in any of the formats) as JSON, CBOR or MessagePack
+pub fn encode( buffer: &[u8], format: Format ) -> Result<Vec<u8>, String> {
+
+    let res = match detect( buffer ) {
+        Format::Json => transcode( &mut serde_json::Deserializer::from_slice( buffer ), format ),
+        Format::Cbor => transcode( &mut serde_cbor::Deserializer::from_slice( buffer ), format ),
+        Format::MessagePack => transcode( &mut rmp_serde::Deserializer::from_read_ref( buffer ), format ),
+    };
+
+    res.map_err( |e| format!( "Error converting CityJSON buffer: {}", e ) )
+
+}
@@ -1,13 +1,15 @@
 use lazy_static::lazy_static;
 use std::sync::Mutex;
-use wasm_bindgen::prelude::*;
 use serde::{Serialize, Deserialize, Deserializer};
-use serde::de::{self, Visitor, MapAccess};
-use serde_json::{Value, json};
+use serde::de::{Visitor, MapAccess};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
-use super::{WasmMemBuffer};
-use super::to_bufferattributes;
+use super::stream;
+use super::format;
+use super::join;
+use super::edit;
 
 
 #[derive(Serialize, Deserialize)]
@@ -85,8 +87,8 @@ where
 
 }
 
-#[wasm_bindgen]
-pub fn get_attributes( buf: &WasmMemBuffer, selected_id: String ) -> wasm_bindgen::JsValue {
+/// The CityObject with the given ID, with joined columns and attribute edits applied. An empty object if the ID doesn't exist.
+pub fn get_attributes( buffer: &[u8], selected_id: &str ) -> Result<serde_json::Value, serde_json::Error> {
 
     log!("Getting attributes of {}...", selected_id);
 
@@ -94,71 +96,48 @@ pub fn get_attributes( buf: &WasmMemBuffer, selected_id: String ) -> wasm_bindge
     let mut co_id = CO_ID.lock().unwrap();
 
     // Update it to the selected ID
-    *co_id = selected_id;
+    *co_id = selected_id.to_string();
 
     // Unlock it
     drop(co_id);
 
     // Retrieve selected CityObject
-    let out: CityObject = serde_json::from_slice(&buf.buffer).expect("Error getting attributes");
+    let mut out: CityObject = format::from_slice( buffer )?;
 
-    JsValue::from_serde(&out.attributes).expect("Could not convert serde_json::Value into JsValue (attributes)")
+    // Add the columns of joined tables and apply attribute edits
+    join::apply_joined( selected_id, &mut out.attributes );
+    edit::apply_edits( selected_id, &mut out.attributes );
 
-}
-
-#[wasm_bindgen]
-pub fn get_interval_and_id( query: u32 ) -> wasm_bindgen::JsValue {
-
-    log!("Getting CityObject ID and triangle interval from clicked triangle index...");
-
-    let intervals = &to_bufferattributes::INTERVALS.lock().unwrap();
-    let ids = &to_bufferattributes::IDS.lock().unwrap();
-
-    let mut position = intervals.len() / 2;
-    let mut step = position / 2;
-
-    loop {
-        
-        if step == 0 {
-
-            if query >= intervals[ position ] && query < intervals[ position + 1 ] {
-
-                let res = format!("{} {} {}", &ids[ position ], intervals[ position ], intervals[ position + 1 ] - 1 );
-
-                return wasm_bindgen::JsValue::from_str( &res );
-
-            }
+    Ok( out.attributes )
 
-            log!("ID corresponding to triangle index not found!");
-            return wasm_bindgen::JsValue::from_str("");
+}
 
-        } else if query >= intervals[ position + 1 ] {
+// Keep only the given attribute keys of a CityObject, everything else of the CityObject is left as it is
+fn project( mut co: serde_json::Value, keys: Option<&[String]> ) -> serde_json::Value {
 
-            position += step;
+    if let ( Some( keys ), Some( attributes ) ) = ( keys, co.get_mut( "attributes" ).and_then( |a| a.as_object_mut() ) ) {
+        attributes.retain( |key, _| keys.contains( key ) );
+    }
 
-        } else if query < intervals[ position ] {
+    co
 
-            position -= step;
+}
 
-        } else {
+/// Retrieve several CityObjects in one pass over the buffer. The output has the same order as ids, with null for IDs that don't exist.
+/// If keys is given, only those attributes are kept.
+pub fn get_attributes_of( buffer: &[u8], ids: &[String], keys: Option<&[String]> ) -> Result<Vec<serde_json::Value>, serde_json::Error> {
 
-            let res = format!("{} {} {}", &ids[ position ], intervals[ position ], intervals[ position + 1 ] - 1 );
+    let mut found: HashMap<&str, serde_json::Value> = HashMap::with_capacity( ids.len() );
+    let wanted: HashSet<&str> = ids.iter().map( |id| id.as_str() ).collect();
 
-            return wasm_bindgen::JsValue::from_str( &res );
+    stream::for_each_cityobject( buffer, |id, co| {
 
+        if let Some( id ) = wanted.get( id.as_str() ) {
+            found.insert( id, project( co, keys ) );
         }
 
-        // Ceil division to make sure step does not get too low, but if it was 1 it needs to be floored, otherwise it will be 1 forever
-        if step == 1 {
-
-            step = 0;
-
-        } else {
+    } )?;
 
-            step = (step as f32 / 2.0).ceil() as usize;
+    Ok( ids.iter().map( |id| found.get( id.as_str() ).cloned().unwrap_or( serde_json::Value::Null ) ).collect() )
 
-        }
-
-    }
-
-}
\ No newline at end of file
+}
@@ -0,0 +1,343 @@
+use serde_json::{json, Map, Value};
+
+use super::mesh::{self, Mesh};
+
+///// glTF 2.0 (binary, GLB) export of the model. One primitive with its own material per group (CityObject type), flat normals, and
+///// the CityObjects as features in the style of EXT_mesh_features: every vertex has a feature ID (_FEATURE_ID_0) that points into an
+///// EXT_structural_metadata property table with the CityObject ID, type and attributes. Feature IDs and triangles are in the same order
+///// as in parse_cityobjects(), so picking results can be mapped between the viewer and the export.
+///// The model is converted to glTF's Y-up axes and recentred around the origin (for f32 precision); the center is the node translation.
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const UNSIGNED_SHORT: u32 = 5123;
+
+// Feature IDs up to this fit in an UNSIGNED_SHORT. glTF doesn't allow UNSIGNED_INT for vertex attributes, so larger IDs are written
+// as FLOAT, which is exact up to MAX_FEATURES.
+const MAX_SHORT_FEATURES: usize = 1 << 16;
+const MAX_FEATURES: usize = 1 << 24;
+
+// Binary chunk of the GLB, with bufferViews aligned to 4 bytes
+#[derive(Default)]
+struct BinaryBuffer {
+
+    data: Vec<u8>,
+    views: Vec<Value>,
+
+}
+
+impl BinaryBuffer {
+
+    fn push_view( &mut self, bytes: &[u8], target: Option<u32> ) -> usize {
+
+        self.push_strided_view( bytes, target, None )
+
+    }
+
+    fn push_strided_view( &mut self, bytes: &[u8], target: Option<u32>, stride: Option<usize> ) -> usize {
+
+        while !self.data.len().is_multiple_of( 4 ) {
+            self.data.push( 0 );
+        }
+
+        let mut view = json!( { "buffer": 0, "byteOffset": self.data.len(), "byteLength": bytes.len() } );
+
+        if let Some( target ) = target {
+            view[ "target" ] = json!( target );
+        }
+
+        if let Some( stride ) = stride {
+            view[ "byteStride" ] = json!( stride );
+        }
+
+        self.data.extend_from_slice( bytes );
+        self.views.push( view );
+
+        self.views.len() - 1
+
+    }
+
+}
+
+fn f32_bytes( values: &[f32] ) -> Vec<u8> {
+
+    values.iter().flat_map( |v| v.to_le_bytes() ).collect()
+
+}
+
+fn u32_bytes( values: &[u32] ) -> Vec<u8> {
+
+    values.iter().flat_map( |v| v.to_le_bytes() ).collect()
+
+}
+
+// sRGB color to linear baseColorFactor
+fn linear( color: [u8; 3] ) -> Vec<f64> {
+
+    color.iter().map( |c| ( *c as f64 / 255.0 ).powf( 2.2 ) ).chain( std::iter::once( 1.0 ) ).collect()
+
+}
+
+/// Make a property ID for EXT_structural_metadata out of an attribute name (identifiers must match ^[a-zA-Z_][a-zA-Z0-9_]*$)
+pub fn property_id( name: &str, taken: &[String] ) -> String {
+
+    let mut id: String = name.chars().map( |c| if c.is_ascii_alphanumeric() { c } else { '_' } ).collect();
+
+    if !id.starts_with( |c: char| c.is_ascii_alphabetic() || c == '_' ) {
+        id.insert( 0, '_' );
+    }
+
+    let base = id.clone();
+    let mut n = 2;
+
+    while taken.contains( &id ) {
+        id = format!( "{}_{}", base, n );
+        n += 1;
+    }
+
+    id
+
+}
+
+// Text of an attribute value in a STRING property
+fn value_string( value: Option<&Value> ) -> String {
+
+    match value {
+        None | Some( Value::Null ) => String::new(),
+        Some( Value::String( s ) ) => s.clone(),
+        Some( value ) => value.to_string(),
+    }
+
+}
+
+// Write a STRING property (values and UINT32 string offsets) and return its JSON
+fn string_property( buffer: &mut BinaryBuffer, strings: &[String] ) -> Value {
+
+    let mut offsets = Vec::with_capacity( strings.len() + 1 );
+    let mut values = Vec::new();
+
+    offsets.push( 0 );
+
+    for s in strings {
+        values.extend_from_slice( s.as_bytes() );
+        offsets.push( values.len() as u32 );
+    }
+
+    let values = buffer.push_view( &values, None );
+    let offsets = buffer.push_view( &u32_bytes( &offsets ), None );
+
+    json!( { "values": values, "stringOffsets": offsets, "stringOffsetType": "UINT32" } )
+
+}
+
+// Schema class and property table with the ID, type and attributes of the features
+fn property_table( mesh: &Mesh, buffer: &mut BinaryBuffer ) -> ( Value, Value ) {
+
+    let mut class_properties = Map::new();
+    let mut table_properties = Map::new();
+    let mut taken: Vec<String> = vec![ "id".to_string(), "type".to_string() ];
+
+    let ids: Vec<String> = mesh.features.iter().map( |f| f.id.clone() ).collect();
+    let types: Vec<String> = mesh.features.iter().map( |f| f.co_type.clone() ).collect();
+
+    class_properties.insert( "id".to_string(), json!( { "type": "STRING", "name": "CityObject ID", "required": true } ) );
+    table_properties.insert( "id".to_string(), string_property( buffer, &ids ) );
+    class_properties.insert( "type".to_string(), json!( { "type": "STRING", "name": "CityObject type", "required": true } ) );
+    table_properties.insert( "type".to_string(), string_property( buffer, &types ) );
+
+    // All attribute names that occur
+    let mut names: Vec<&String> = Vec::new();
+
+    for feature in &mesh.features {
+        for name in feature.attributes.keys() {
+            if !names.contains( &name ) {
+                names.push( name );
+            }
+        }
+    }
+
+    for name in names {
+
+        let id = property_id( name, &taken );
+        let values: Vec<Option<&Value>> = mesh.features.iter().map( |f| f.attributes.get( name ) ).collect();
+
+        // Attributes that are a number for every CityObject become FLOAT64 properties, all others STRING properties
+        let numbers: Option<Vec<f64>> = values.iter().map( |v| v.and_then( |v| v.as_f64() ) ).collect();
+
+        match numbers {
+            Some( numbers ) => {
+                let bytes: Vec<u8> = numbers.iter().flat_map( |v| v.to_le_bytes() ).collect();
+                class_properties.insert( id.clone(), json!( { "type": "SCALAR", "componentType": "FLOAT64", "name": name, "required": true } ) );
+                table_properties.insert( id.clone(), json!( { "values": buffer.push_view( &bytes, None ) } ) );
+            },
+            None => {
+                let strings: Vec<String> = values.iter().map( |v| value_string( *v ) ).collect();
+                class_properties.insert( id.clone(), json!( { "type": "STRING", "name": name, "noData": "" } ) );
+                table_properties.insert( id.clone(), string_property( buffer, &strings ) );
+            },
+        }
+
+        taken.push( id );
+
+    }
+
+    let schema = json!( { "id": "cityjson", "classes": { "CityObject": { "name": "CityObject", "properties": class_properties } } } );
+    let table = json!( { "name": "CityObjects", "class": "CityObject", "count": mesh.features.len(), "properties": table_properties } );
+
+    ( schema, table )
+
+}
+
+/// Write a mesh as GLB. A mesh without triangles can't be written, since glTF doesn't allow empty buffers and accessors.
+pub fn write_glb( mesh: &Mesh ) -> Result<Vec<u8>, String> {
+
+    if mesh.triangles.is_empty() {
+        return Err( "There are no triangles to export".to_string() );
+    }
+
+    if mesh.features.len() > MAX_FEATURES {
+        return Err( format!( "Can't export more than {} CityObjects to glTF", MAX_FEATURES ) );
+    }
+
+    let center = mesh.center();
+    let triangle_n = mesh.triangles.len() / 3;
+
+    // Flat shading: three vertices per triangle, so that every vertex has the normal and the feature ID of its triangle
+    let mut positions: Vec<f32> = Vec::with_capacity( triangle_n * 9 );
+    let mut normals: Vec<f32> = Vec::with_capacity( triangle_n * 9 );
+    let mut feature_ids: Vec<u32> = Vec::with_capacity( triangle_n * 3 );
+    let mut min = [ f32::INFINITY; 3 ];
+    let mut max = [ f32::NEG_INFINITY; 3 ];
+
+    for t in 0..triangle_n {
+
+        let n = mesh.normal( t );
+
+        for v in &mesh.triangles[ t * 3..t * 3 + 3 ] {
+
+            let v = mesh.vertices[ *v as usize ];
+            // Z-up to Y-up: ( x, y, z ) -> ( x, z, -y )
+            let p = [ ( v[ 0 ] - center[ 0 ] ) as f32, ( v[ 2 ] - center[ 2 ] ) as f32, ( center[ 1 ] - v[ 1 ] ) as f32 ];
+
+            for i in 0..3 {
+                min[ i ] = min[ i ].min( p[ i ] );
+                max[ i ] = max[ i ].max( p[ i ] );
+            }
+
+            positions.extend_from_slice( &p );
+            normals.extend_from_slice( &[ n[ 0 ] as f32, n[ 2 ] as f32, -n[ 1 ] as f32 ] );
+            feature_ids.push( mesh.triangle_features[ t ] );
+
+        }
+
+    }
+
+    let indices: Vec<u32> = ( 0..mesh.triangles.len() as u32 ).collect();
+
+    let mut buffer = BinaryBuffer::default();
+    let mut accessors = Vec::new();
+
+    let positions_view = buffer.push_view( &f32_bytes( &positions ), Some( ARRAY_BUFFER ) );
+    let normals_view = buffer.push_view( &f32_bytes( &normals ), Some( ARRAY_BUFFER ) );
+
+    // Vertex attributes are aligned to 4 bytes, so UNSIGNED_SHORT feature IDs are padded with a stride of 4
+    let ( feature_ids_view, feature_id_type ) = if mesh.features.len() <= MAX_SHORT_FEATURES {
+        let bytes: Vec<u8> = feature_ids.iter().flat_map( |f| {
+            let b = ( *f as u16 ).to_le_bytes();
+            [ b[ 0 ], b[ 1 ], 0, 0 ]
+        } ).collect();
+        ( buffer.push_strided_view( &bytes, Some( ARRAY_BUFFER ), Some( 4 ) ), UNSIGNED_SHORT )
+    } else {
+        let floats: Vec<f32> = feature_ids.iter().map( |f| *f as f32 ).collect();
+        ( buffer.push_view( &f32_bytes( &floats ), Some( ARRAY_BUFFER ) ), FLOAT )
+    };
+
+    let indices_view = buffer.push_view( &u32_bytes( &indices ), Some( ELEMENT_ARRAY_BUFFER ) );
+
+    let vertex_n = feature_ids.len();
+
+    accessors.push( json!( { "bufferView": positions_view, "componentType": FLOAT, "count": vertex_n, "type": "VEC3", "min": min, "max": max } ) );
+    accessors.push( json!( { "bufferView": normals_view, "componentType": FLOAT, "count": vertex_n, "type": "VEC3" } ) );
+    accessors.push( json!( { "bufferView": feature_ids_view, "componentType": feature_id_type, "count": vertex_n, "type": "SCALAR" } ) );
+
+    let mut materials = Vec::new();
+    let mut primitives = Vec::new();
+
+    for group in &mesh.groups {
+
+        accessors.push( json!( { "bufferView": indices_view, "byteOffset": group.start * 4, "componentType": UNSIGNED_INT, "count": group.count, "type": "SCALAR" } ) );
+
+        materials.push( json!( {
+            "name": group.co_type,
+            "pbrMetallicRoughness": { "baseColorFactor": linear( mesh::type_color( &group.co_type ) ), "metallicFactor": 0.0, "roughnessFactor": 1.0 },
+            "doubleSided": true,
+        } ) );
+
+        let first = group.start as usize / 3;
+        let last = ( group.start + group.count ) as usize / 3;
+        let mut features: Vec<u32> = mesh.triangle_features[ first..last ].to_vec();
+        features.dedup();
+
+        primitives.push( json!( {
+            "attributes": { "POSITION": 0, "NORMAL": 1, "_FEATURE_ID_0": 2 },
+            "indices": accessors.len() - 1,
+            "material": materials.len() - 1,
+            "mode": 4,
+            "extensions": { "EXT_mesh_features": { "featureIds": [ { "featureCount": features.len(), "attribute": 0, "propertyTable": 0 } ] } },
+        } ) );
+
+    }
+
+    let ( schema, table ) = property_table( mesh, &mut buffer );
+
+    let gltf = json!( {
+        "asset": { "version": "2.0", "generator": "rust-parcel CityJSON export" },
+        "extensionsUsed": [ "EXT_mesh_features", "EXT_structural_metadata" ],
+        "extensions": { "EXT_structural_metadata": { "schema": schema, "propertyTables": [ table ] } },
+        "scene": 0,
+        "scenes": [ { "nodes": [ 0 ] } ],
+        "nodes": [ { "name": "CityObjects", "mesh": 0, "translation": [ center[ 0 ], center[ 2 ], -center[ 1 ] ] } ],
+        "meshes": [ { "name": "CityObjects", "primitives": primitives } ],
+        "materials": materials,
+        "accessors": accessors,
+        "bufferViews": buffer.views,
+        "buffers": [ { "byteLength": buffer.data.len() } ],
+    } );
+
+    Ok( glb( &serde_json::to_vec( &gltf ).unwrap(), buffer.data ) )
+
+}
+
+/// Pack the glTF JSON and the binary buffer into a GLB container
+pub fn glb( json: &[u8], mut bin: Vec<u8> ) -> Vec<u8> {
+
+    let mut json = json.to_vec();
+
+    while !json.len().is_multiple_of( 4 ) {
+        json.push( b' ' );
+    }
+
+    while !bin.len().is_multiple_of( 4 ) {
+        bin.push( 0 );
+    }
+
+    let length = 12 + 8 + json.len() + 8 + bin.len();
+    let mut out = Vec::with_capacity( length );
+
+    out.extend_from_slice( b"glTF" );
+    out.extend_from_slice( &2u32.to_le_bytes() );
+    out.extend_from_slice( &( length as u32 ).to_le_bytes() );
+
+    out.extend_from_slice( &( json.len() as u32 ).to_le_bytes() );
+    out.extend_from_slice( b"JSON" );
+    out.extend_from_slice( &json );
+
+    out.extend_from_slice( &( bin.len() as u32 ).to_le_bytes() );
+    out.extend_from_slice( b"BIN\0" );
+    out.extend_from_slice( &bin );
+
+    out
+
+}
@@ -0,0 +1,187 @@
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::picking;
+
+/// Parent/child relationships between the CityObjects of the last parsed file, from their "parents", "children" and "members" members
+#[derive(Default)]
+pub struct Hierarchy {
+
+    // All CityObject IDs in file order, also the ones without geometry
+    pub ids: Vec<String>,
+    pub types: HashMap<String, String>,
+    pub parents: HashMap<String, Vec<String>>,
+    pub children: HashMap<String, Vec<String>>,
+    // Members of CityObjectGroups. These are not children: a Building in a group is still a root.
+    pub members: HashMap<String, Vec<String>>,
+
+}
+
+lazy_static! {
+    pub static ref HIERARCHY: Mutex<Hierarchy> = Mutex::new(Hierarchy::default());
+}
+
+fn push_unique( map: &mut HashMap<String, Vec<String>>, key: &str, value: &str ) {
+
+    let values = map.entry( key.to_string() ).or_default();
+
+    if !values.iter().any( |v| v == value ) {
+        values.push( value.to_string() );
+    }
+
+}
+
+fn str_array( co: &serde_json::Value, member: &str ) -> Vec<String> {
+
+    co.get( member )
+      .and_then( |m| m.as_array() )
+      .map( |m| m.iter().filter_map( |v| v.as_str() ).map( |v| v.to_string() ).collect() )
+      .unwrap_or_default()
+
+}
+
+impl Hierarchy {
+
+    /// Record a CityObject and its relationships. A relation may be stated by the parent ("children"), the child ("parents") or both.
+    pub fn add( &mut self, id: &str, co: &serde_json::Value ) {
+
+        let co_type = co[ "type" ].as_str().unwrap_or_default();
+
+        self.ids.push( id.to_string() );
+        self.types.insert( id.to_string(), co_type.to_string() );
+
+        for parent in str_array( co, "parents" ) {
+
+            push_unique( &mut self.parents, id, &parent );
+            push_unique( &mut self.children, &parent, id );
+
+        }
+
+        // CityJSON 1.0 stores group members in "members", 1.1 in "children"
+        if co_type == "CityObjectGroup" {
+
+            for member in str_array( co, "members" ).into_iter().chain( str_array( co, "children" ) ) {
+                push_unique( &mut self.members, id, &member );
+            }
+
+        } else {
+
+            for child in str_array( co, "children" ) {
+
+                push_unique( &mut self.children, id, &child );
+                push_unique( &mut self.parents, &child, id );
+
+            }
+
+        }
+
+    }
+
+    /// To be called once all CityObjects have been added. Objects that name a CityObjectGroup as their parent
+    /// (which can only be known once the type of the group has been read) become members of that group instead.
+    pub fn finalize( &mut self ) {
+
+        let types = &self.types;
+        let is_group = | id: &str | types.get( id ).map( |t| t == "CityObjectGroup" ).unwrap_or( false );
+
+        let mut members = Vec::new();
+
+        for ( child, parents ) in self.parents.iter_mut() {
+
+            parents.retain( |parent| {
+
+                if is_group( parent ) {
+                    members.push( ( parent.clone(), child.clone() ) );
+                    return false;
+                }
+
+                true
+
+            } );
+
+        }
+
+        self.parents.retain( |_, parents| !parents.is_empty() );
+        self.children.retain( |parent, _| !is_group( parent ) );
+
+        for ( group, member ) in members {
+            push_unique( &mut self.members, &group, &member );
+        }
+
+    }
+
+    /// CityObjects without parents, in file order
+    pub fn roots( &self ) -> Vec<String> {
+
+        self.ids.iter().filter( |id| !self.parents.contains_key( *id ) ).cloned().collect()
+
+    }
+
+    pub fn children_of( &self, id: &str ) -> Vec<String> {
+
+        self.children.get( id ).cloned().unwrap_or_default()
+
+    }
+
+    pub fn members_of( &self, id: &str ) -> Vec<String> {
+
+        self.members.get( id ).cloned().unwrap_or_default()
+
+    }
+
+    /// All ancestors of a CityObject, nearest first. A CityObject can have several parents, so this is a breadth-first traversal.
+    pub fn parent_chain( &self, id: &str ) -> Vec<String> {
+
+        traverse( id, &[ &self.parents ] )
+
+    }
+
+    /// The CityObject itself followed by all its descendants. For a CityObjectGroup, its members and their descendants are included.
+    pub fn descendants( &self, id: &str ) -> Vec<String> {
+
+        let mut out = vec![ id.to_string() ];
+        out.append( &mut traverse( id, &[ &self.members, &self.children ] ) );
+
+        out
+
+    }
+
+}
+
+// Breadth-first traversal over one or more relations, guarded against cycles
+fn traverse( id: &str, relations: &[ &HashMap<String, Vec<String>> ] ) -> Vec<String> {
+
+    let mut out = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+
+    seen.insert( id.to_string() );
+    queue.push_back( id.to_string() );
+
+    while let Some( current ) = queue.pop_front() {
+
+        for next in relations.iter().filter_map( |relation| relation.get( &current ) ).flatten() {
+
+            if seen.insert( next.clone() ) {
+                out.push( next.clone() );
+                queue.push_back( next.clone() );
+            }
+
+        }
+
+    }
+
+    out
+
+}
+
+/// The triangle intervals of a CityObject and all its descendants (or group members), so that they can be selected together.
+/// CityObjects without triangles are skipped.
+pub fn descendant_intervals( id: &str ) -> Vec<picking::TriangleInterval> {
+
+    let descendants = HIERARCHY.lock().unwrap().descendants( id );
+
+    picking::triangle_ranges( &descendants )
+
+}
@@ -0,0 +1,375 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::mesh::{lod_string, Transform};
+use super::stream;
+use super::format;
+use super::to_bufferattributes::CO_TYPES;
+
+///// Inspection of a CityJSON file: a summary (info()) and a check of what the viewer needs (validate()). Used by the command-line tool
+///// and available in the viewer through get_info() and validate_cityjson().
+
+// Everything but the CityObjects
+#[derive(Deserialize)]
+struct Header {
+
+    #[serde(rename = "type")]
+    doc_type: Option<String>,
+    version: Option<String>,
+    metadata: Option<Value>,
+    transform: Option<Transform>,
+    vertices: Option<Vec<Vec<f64>>>,
+    #[serde(rename = "geometry-templates")]
+    geometry_templates: Option<Value>,
+
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct VertexStats {
+
+    pub count: usize,
+    // Vertices that are not used by any geometry
+    pub unused: usize,
+    // Vertices with the same coordinates as an earlier vertex
+    pub duplicates: usize,
+    // [minx, miny, minz, maxx, maxy, maxz] in real coordinates
+    pub extent: Option<[f64; 6]>,
+
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct Info {
+
+    pub version: Option<String>,
+    pub reference_system: Option<String>,
+    pub transform: Option<[[f64; 3]; 2]>,
+    pub metadata_extent: Option<Value>,
+    pub cityobjects: usize,
+    pub types: BTreeMap<String, usize>,
+    // Amount of geometries per geometry type and per LoD
+    pub geometry_types: BTreeMap<String, usize>,
+    pub lods: BTreeMap<String, usize>,
+    // Surfaces that are triangles (and are shown by the viewer), and surfaces that are not
+    pub triangles: usize,
+    pub other_surfaces: usize,
+    pub vertices: VertexStats,
+
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Severity {
+
+    Error,
+    Warning,
+
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Issue {
+
+    pub severity: Severity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cityobject: Option<String>,
+    pub message: String,
+
+}
+
+// Nesting depth of the surfaces in the boundaries of a geometry type, None for geometries without surfaces
+fn surface_depth( geometry_type: &str ) -> Option<usize> {
+
+    match geometry_type {
+        "MultiSurface" | "CompositeSurface" => Some( 1 ),
+        "Solid" => Some( 2 ),
+        "MultiSolid" | "CompositeSolid" => Some( 3 ),
+        _ => None,
+    }
+
+}
+
+// The surfaces (arrays of rings) of a geometry
+fn surfaces<'a>( boundaries: &'a Value, depth: usize, out: &mut Vec<&'a Value> ) {
+
+    if depth == 0 {
+        out.push( boundaries );
+    } else if let Some( items ) = boundaries.as_array() {
+        items.iter().for_each( |b| surfaces( b, depth - 1, out ) );
+    }
+
+}
+
+// All vertex indices in boundaries
+fn indices( boundaries: &Value, out: &mut Vec<u64> ) {
+
+    match boundaries {
+        Value::Array( items ) => items.iter().for_each( |b| indices( b, out ) ),
+        Value::Number( n ) => out.extend( n.as_u64() ),
+        _ => {},
+    }
+
+}
+
+fn read_header( buffer: &[u8] ) -> Result<Header, String> {
+
+    format::from_slice( buffer ).map_err( |e| format!( "Error parsing CityJSON buffer: {}", e ) )
+
+}
+
+fn real( v: &[f64], transform: Option<&Transform> ) -> [f64; 3] {
+
+    let mut p = [ 0.0; 3 ];
+
+    for ( i, c ) in p.iter_mut().enumerate() {
+        let value = v.get( i ).cloned().unwrap_or( 0.0 );
+        *c = match transform {
+            Some( t ) => value * t.scale[ i ] + t.translate[ i ],
+            None => value,
+        };
+    }
+
+    p
+
+}
+
+/// Summarize a CityJSON document
+pub fn info( buffer: &[u8] ) -> Result<Info, String> {
+
+    let header = read_header( buffer )?;
+    let vertices = header.vertices.unwrap_or_default();
+
+    let mut info = Info {
+        version: header.version,
+        reference_system: header.metadata.as_ref().and_then( |m| m.get( "referenceSystem" ) ).and_then( |r| r.as_str() ).map( |r| r.to_string() ),
+        transform: header.transform.as_ref().map( |t| [ t.scale, t.translate ] ),
+        metadata_extent: header.metadata.as_ref().and_then( |m| m.get( "geographicalExtent" ) ).cloned(),
+        ..Default::default()
+    };
+
+    let mut used = vec![ false; vertices.len() ];
+
+    stream::for_each_cityobject( buffer, |_, co| {
+
+        info.cityobjects += 1;
+
+        let co_type = co.get( "type" ).and_then( |t| t.as_str() ).unwrap_or( "(no type)" );
+        *info.types.entry( co_type.to_string() ).or_default() += 1;
+
+        for geometry in co.get( "geometry" ).and_then( |g| g.as_array() ).into_iter().flatten() {
+
+            let geometry_type = geometry.get( "type" ).and_then( |t| t.as_str() ).unwrap_or( "(no type)" );
+            *info.geometry_types.entry( geometry_type.to_string() ).or_default() += 1;
+
+            let lod = geometry.get( "lod" ).map( lod_string ).unwrap_or_else( || "(no lod)".to_string() );
+            *info.lods.entry( lod ).or_default() += 1;
+
+            let boundaries = &geometry[ "boundaries" ];
+            let mut vs = Vec::new();
+            indices( boundaries, &mut vs );
+
+            // The boundaries of a GeometryInstance are its reference point
+            for v in vs {
+                if let Some( used ) = used.get_mut( v as usize ) {
+                    *used = true;
+                }
+            }
+
+            if let Some( depth ) = surface_depth( geometry_type ) {
+
+                let mut out = Vec::new();
+                surfaces( boundaries, depth, &mut out );
+
+                for surface in out {
+                    if surface[ 0 ].as_array().map( |r| r.len() == 3 ).unwrap_or( false ) {
+                        info.triangles += 1;
+                    } else {
+                        info.other_surfaces += 1;
+                    }
+                }
+
+            }
+
+        }
+
+    } ).map_err( |e| format!( "Error parsing CityJSON buffer: {}", e ) )?;
+
+    let mut seen = HashSet::new();
+    let mut extent: Option<[f64; 6]> = None;
+
+    for v in &vertices {
+
+        if !seen.insert( v.iter().map( |c| c.to_bits() ).collect::<Vec<u64>>() ) {
+            info.vertices.duplicates += 1;
+        }
+
+        let p = real( v, header.transform.as_ref() );
+        let e = extent.get_or_insert( [ p[ 0 ], p[ 1 ], p[ 2 ], p[ 0 ], p[ 1 ], p[ 2 ] ] );
+
+        for i in 0..3 {
+            e[ i ] = e[ i ].min( p[ i ] );
+            e[ i + 3 ] = e[ i + 3 ].max( p[ i ] );
+        }
+
+    }
+
+    info.vertices.count = vertices.len();
+    info.vertices.unused = used.iter().filter( |u| !**u ).count();
+    info.vertices.extent = extent;
+
+    Ok( info )
+
+}
+
+/// Check a CityJSON document for what the viewer (and the exporters) need: a valid structure, known CityObject types, vertex indices
+/// that exist, consistent parent/child references, and triangulated surfaces. Errors break parsing, warnings mean that something is not shown.
+pub fn validate( buffer: &[u8] ) -> Result<Vec<Issue>, String> {
+
+    let header = read_header( buffer )?;
+
+    let mut issues = Vec::new();
+    let mut issue = | severity: Severity, cityobject: Option<&str>, message: String | issues.push( Issue { severity, cityobject: cityobject.map( |c| c.to_string() ), message } );
+
+    if header.doc_type.as_deref() != Some( "CityJSON" ) {
+        issue( Severity::Error, None, format!( "\"type\" must be \"CityJSON\", not {:?}", header.doc_type ) );
+    }
+
+    if header.version.is_none() {
+        issue( Severity::Warning, None, "The document has no \"version\"".to_string() );
+    }
+
+    let vertices = match &header.vertices {
+        Some( vertices ) => vertices,
+        None => {
+            issue( Severity::Error, None, "The document has no \"vertices\"".to_string() );
+            return Ok( issues );
+        },
+    };
+
+    if let Some( ( i, _ ) ) = vertices.iter().enumerate().find( |( _, v )| v.len() != 3 ) {
+        issue( Severity::Error, None, format!( "Vertex {} does not have 3 coordinates", i ) );
+    }
+
+    // The viewer reads the vertices as integers, so they have to be transformed (see parse_vertices())
+    if header.transform.is_none() || vertices.iter().flatten().any( |c| c.fract() != 0.0 || *c < 0.0 ) {
+        issue( Severity::Warning, None, "The vertices are not non-negative integers with a \"transform\", the viewer can't read them".to_string() );
+    }
+
+    let template_n = header.geometry_templates.as_ref().and_then( |t| t.get( "templates" ) ).and_then( |t| t.as_array() ).map( |t| t.len() ).unwrap_or( 0 );
+
+    let mut ids = HashSet::new();
+    let mut parents: HashMap<String, Vec<String>> = HashMap::new();
+    let mut children: HashMap<String, Vec<String>> = HashMap::new();
+    let mut has_cityobjects = false;
+
+    stream::for_each_cityobject( buffer, |id, co| {
+
+        has_cityobjects = true;
+
+        let co_type = co.get( "type" ).and_then( |t| t.as_str() );
+
+        match co_type {
+            None => issue( Severity::Error, Some( &id ), "The CityObject has no \"type\"".to_string() ),
+            Some( t ) if !CO_TYPES.contains( &t ) => issue( Severity::Error, Some( &id ), format!( "Type {} is not supported by the viewer", t ) ),
+            _ => {},
+        }
+
+        let references = | key: &str | co.get( key ).and_then( |r| r.as_array() ).into_iter().flatten().filter_map( |r| r.as_str() ).map( |r| r.to_string() ).collect::<Vec<String>>();
+        parents.insert( id.clone(), references( "parents" ) );
+        children.insert( id.clone(), references( "children" ) );
+
+        let mut other_surfaces = 0;
+        let mut holes = 0;
+
+        for ( g, geometry ) in co.get( "geometry" ).and_then( |g| g.as_array() ).into_iter().flatten().enumerate() {
+
+            let geometry_type = geometry.get( "type" ).and_then( |t| t.as_str() ).unwrap_or( "" );
+            let boundaries = &geometry[ "boundaries" ];
+
+            if !boundaries.is_array() {
+                issue( Severity::Error, Some( &id ), format!( "Geometry {} has no \"boundaries\"", g ) );
+                continue;
+            }
+
+            if geometry.get( "lod" ).is_none() {
+                issue( Severity::Warning, Some( &id ), format!( "Geometry {} has no \"lod\"", g ) );
+            }
+
+            let mut vs = Vec::new();
+            indices( boundaries, &mut vs );
+
+            if let Some( v ) = vs.iter().find( |v| **v as usize >= vertices.len() ) {
+                issue( Severity::Error, Some( &id ), format!( "Geometry {} refers to vertex {}, which does not exist", g, v ) );
+            }
+
+            match geometry_type {
+                "GeometryInstance" => {
+                    let template = geometry.get( "template" ).and_then( |t| t.as_u64() );
+                    if template.map( |t| t as usize >= template_n ).unwrap_or( true ) {
+                        issue( Severity::Error, Some( &id ), format!( "Geometry {} refers to a geometry template that does not exist", g ) );
+                    }
+                },
+                "MultiPoint" | "MultiLineString" => {},
+                t => match surface_depth( t ) {
+                    Some( depth ) => {
+
+                        let mut out = Vec::new();
+                        surfaces( boundaries, depth, &mut out );
+
+                        for surface in out {
+                            if surface[ 0 ].as_array().map( |r| r.len() != 3 ).unwrap_or( true ) {
+                                other_surfaces += 1;
+                            }
+                            if surface.as_array().map( |s| s.len() > 1 ).unwrap_or( false ) {
+                                holes += 1;
+                            }
+                        }
+
+                    },
+                    None => issue( Severity::Error, Some( &id ), format!( "Geometry {} has unknown type {:?}", g, t ) ),
+                },
+            }
+
+        }
+
+        if other_surfaces > 0 {
+            issue( Severity::Warning, Some( &id ), format!( "{} surfaces are not triangles and are not shown", other_surfaces ) );
+        }
+
+        if holes > 0 {
+            issue( Severity::Warning, Some( &id ), format!( "{} surfaces have holes, which are ignored", holes ) );
+        }
+
+        ids.insert( id );
+
+    } ).map_err( |e| format!( "Error parsing CityJSON buffer: {}", e ) )?;
+
+    if !has_cityobjects {
+        issue( Severity::Error, None, "The document has no \"CityObjects\"".to_string() );
+    }
+
+    // References, sorted for a stable report
+    let mut sorted: Vec<&String> = ids.iter().collect();
+    sorted.sort();
+
+    for id in sorted {
+
+        for parent in &parents[ id ] {
+            if !ids.contains( parent ) {
+                issue( Severity::Error, Some( id ), format!( "Parent {} does not exist", parent ) );
+            } else if !children[ parent ].contains( id ) {
+                issue( Severity::Warning, Some( id ), format!( "Parent {} does not list this CityObject as a child", parent ) );
+            }
+        }
+
+        for child in &children[ id ] {
+            if !ids.contains( child ) {
+                issue( Severity::Error, Some( id ), format!( "Child {} does not exist", child ) );
+            } else if !parents[ child ].contains( id ) {
+                issue( Severity::Warning, Some( id ), format!( "Child {} does not list this CityObject as a parent", child ) );
+            }
+        }
+
+    }
+
+    Ok( issues )
+
+}
@@ -0,0 +1,295 @@
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use std::collections::{HashMap, HashSet};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::filter;
+use super::stream;
+
+///// Joining external tables (CSV or JSON) onto CityObjects, by CityObject ID or by the value of an attribute.
+///// Joined columns are added to the "attributes" of the CityObjects whenever the CityObjects are read (get_attributes(), filters,
+///// thematic coloring, ...), and replace original attributes with the same name. The buffer itself is not changed. Parsing another
+///// file clears them. The search index is built while parsing, so joined columns can't be searched.
+
+/// Format of a table to join
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TableFormat {
+
+    // CSV with a header row. The delimiter (, ; or tab) is detected from the header.
+    Csv,
+    // A JSON array of objects, or an object with one object per key
+    Json,
+
+}
+
+// Joined columns per CityObject ID
+pub type Joined = HashMap<String, Map<String, Value>>;
+
+lazy_static! {
+    pub static ref JOINED: Mutex<Joined> = Mutex::new(HashMap::new());
+}
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct JoinReport {
+
+    pub rows: u32,
+    pub matched_rows: u32,
+    // Amount of CityObjects that received joined columns
+    pub matched_objects: u32,
+    pub columns: Vec<String>,
+    // Keys of the rows that did not match any CityObject (or had no key)
+    pub unmatched: Vec<String>,
+    // Keys that occur in more than one row. The columns of later rows replace those of earlier rows.
+    pub duplicates: Vec<String>,
+
+}
+
+/// Add the joined columns to the attributes of a CityObject
+pub fn apply_joined( id: &str, co: &mut Value ) {
+
+    let joined = JOINED.lock().unwrap();
+
+    if let Some( columns ) = joined.get( id ) {
+        merge_attributes( co, columns );
+    }
+
+}
+
+fn merge_attributes( co: &mut Value, columns: &Map<String, Value> ) {
+
+    if let Some( co ) = co.as_object_mut() {
+
+        let attributes = co.entry( "attributes" ).or_insert( Value::Null );
+
+        // "attributes": null is the same as no attributes
+        if attributes.is_null() {
+            *attributes = Value::Object( Map::new() );
+        }
+
+        if let Some( attributes ) = attributes.as_object_mut() {
+            for ( key, value ) in columns {
+                attributes.insert( key.clone(), value.clone() );
+            }
+        }
+
+    }
+
+}
+
+/// Parse CSV into rows of column -> value. Values are kept as strings (so that e.g. leading zeros of identifiers are preserved),
+/// empty values become null. Quoted values may contain delimiters, newlines and doubled quotes.
+pub fn parse_csv( text: &str ) -> Result<Vec<Map<String, Value>>, String> {
+
+    let text = text.trim_start_matches( '\u{feff}' );
+
+    // Detect the delimiter from the header line
+    let header_line = text.lines().next().unwrap_or( "" );
+    let delimiter = [ ',', ';', '\t' ].iter().cloned().max_by_key( |d| header_line.matches( *d ).count() ).unwrap();
+
+    let mut records: Vec<Vec<String>> = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while let Some( c ) = chars.next() {
+
+        if quoted {
+
+            match c {
+                '"' if chars.peek() == Some( &'"' ) => { field.push( '"' ); chars.next(); },
+                '"' => quoted = false,
+                '\n' => { line += 1; field.push( c ); },
+                _ => field.push( c ),
+            }
+
+            continue;
+
+        }
+
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            '\r' => {},
+            '\n' => {
+                record.push( std::mem::take( &mut field ) );
+                records.push( std::mem::take( &mut record ) );
+                line += 1;
+            },
+            _ if c == delimiter => record.push( std::mem::take( &mut field ) ),
+            _ => field.push( c ),
+        }
+
+    }
+
+    if quoted {
+        return Err( format!( "Unterminated quoted value on line {}", line ) );
+    }
+
+    if !field.is_empty() || !record.is_empty() {
+        record.push( field );
+        records.push( record );
+    }
+
+    // Skip empty lines
+    records.retain( |r| !( r.len() == 1 && r[ 0 ].is_empty() ) );
+
+    let mut records = records.into_iter();
+    let header = records.next().ok_or_else( || "The CSV has no header".to_string() )?;
+
+    records.enumerate().map( |( i, r )| {
+
+        if r.len() > header.len() {
+            return Err( format!( "Row {} has {} values, but the header has {} columns", i + 1, r.len(), header.len() ) );
+        }
+
+        Ok( header.iter().cloned().zip( r.into_iter().map( |v| if v.is_empty() { Value::Null } else { Value::String( v ) } ) ).collect() )
+
+    } ).collect()
+
+}
+
+/// Parse a JSON table: an array of objects, or an object with an object per key (the key is added as column table_key if it is missing)
+pub fn parse_json_table( text: &str, table_key: &str ) -> Result<Vec<Map<String, Value>>, String> {
+
+    let value: Value = serde_json::from_str( text ).map_err( |e| format!( "Invalid JSON: {}", e ) )?;
+
+    match value {
+        Value::Array( rows ) => rows.into_iter().enumerate().map( |( i, row )| match row {
+            Value::Object( row ) => Ok( row ),
+            _ => Err( format!( "Row {} is not an object", i + 1 ) ),
+        } ).collect(),
+        Value::Object( rows ) => rows.into_iter().map( |( key, row )| match row {
+            Value::Object( mut row ) => {
+                row.entry( table_key.to_string() ).or_insert( Value::String( key ) );
+                Ok( row )
+            },
+            _ => Err( format!( "Row {} is not an object", key ) ),
+        } ).collect(),
+        _ => Err( "Expected an array or an object of rows".to_string() ),
+    }
+
+}
+
+// String form of a key, so that e.g. 123 in the table matches "123" in the CityJSON
+fn key_string( value: &Value ) -> Option<String> {
+
+    match value {
+        Value::String( s ) => Some( s.clone() ),
+        Value::Number( n ) => Some( n.to_string() ),
+        Value::Bool( b ) => Some( b.to_string() ),
+        _ => None,
+    }
+
+}
+
+/// Join rows onto CityObjects. table_key is the column with the key, join_on the attribute (field syntax as in filters) of the CityObjects
+/// to match it with, or None to match with the CityObject IDs. Returns the joined columns per CityObject ID and a report.
+pub fn join_rows( buffer: &[u8], rows: Vec<Map<String, Value>>, table_key: &str, join_on: Option<&str> ) -> Result<( Joined, JoinReport ), String> {
+
+    let mut report = JoinReport { rows: rows.len() as u32, ..Default::default() };
+
+    // Key value -> CityObject IDs
+    let mut targets: HashMap<String, Vec<String>> = HashMap::new();
+
+    match join_on {
+
+        Some( attribute ) => {
+
+            let path = filter::parse_field( attribute ).map_err( |e| format!( "Invalid attribute: {}", e ) )?;
+
+            stream::for_each_cityobject( buffer, |id, co| {
+
+                let id_value = Value::String( id.clone() );
+
+                if let Some( key ) = filter::resolve( &path, &id_value, &co ).and_then( key_string ) {
+                    targets.entry( key ).or_default().push( id );
+                }
+
+            } ).map_err( |e| format!( "Error parsing CityJSON buffer: {}", e ) )?;
+
+        },
+        None => {
+
+            stream::for_each_cityobject( buffer, |id, _| {
+                targets.insert( id.clone(), vec![ id ] );
+            } ).map_err( |e| format!( "Error parsing CityJSON buffer: {}", e ) )?;
+
+        },
+
+    }
+
+    let mut joined: Joined = HashMap::new();
+    let mut keys = HashSet::new();
+
+    for ( i, mut row ) in rows.into_iter().enumerate() {
+
+        let key = match row.remove( table_key ).as_ref().and_then( key_string ) {
+            Some( key ) => key,
+            None => {
+                report.unmatched.push( format!( "(row {} has no {})", i + 1, table_key ) );
+                continue;
+            },
+        };
+
+        if !keys.insert( key.clone() ) && !report.duplicates.contains( &key ) {
+            report.duplicates.push( key.clone() );
+        }
+
+        for column in row.keys() {
+            if !report.columns.contains( column ) {
+                report.columns.push( column.clone() );
+            }
+        }
+
+        match targets.get( &key ) {
+            Some( ids ) => {
+                report.matched_rows += 1;
+                for id in ids {
+                    joined.entry( id.clone() ).or_default().extend( row.clone() );
+                }
+            },
+            None => report.unmatched.push( key ),
+        }
+
+    }
+
+    report.matched_objects = joined.len() as u32;
+
+    Ok( ( joined, report ) )
+
+}
+
+/// Join a table (CSV or JSON text) onto the CityObjects. table_key is the column in the table with the key, join_on the attribute of
+/// the CityObjects to match it with (None for the CityObject ID). Joined columns are added to the attributes everywhere attributes are
+/// used, except in the search index. Several tables can be joined after each other.
+pub fn join_table( buffer: &[u8], data: &str, format: TableFormat, table_key: &str, join_on: Option<&str> ) -> Result<JoinReport, String> {
+
+    log!("Joining table on {}...", join_on.unwrap_or("CityObject ID"));
+
+    let rows = match format {
+        TableFormat::Csv => parse_csv( data ),
+        TableFormat::Json => parse_json_table( data, table_key ),
+    }?;
+
+    let ( joined, report ) = join_rows( buffer, rows, table_key, join_on )?;
+
+    let mut all = JOINED.lock().unwrap();
+
+    for ( id, columns ) in joined {
+        all.entry( id ).or_default().extend( columns );
+    }
+
+    log!("{} of {} rows joined", report.matched_rows, report.rows);
+
+    Ok( report )
+
+}
+
+/// Remove all joined columns
+pub fn clear_joins() {
+
+    JOINED.lock().unwrap().clear();
+
+}
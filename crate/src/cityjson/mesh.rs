@@ -0,0 +1,373 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use serde_json::{Map, Value};
+
+use super::stream;
+use super::format;
+use super::to_bufferattributes::{self, CO_TYPES};
+
+///// A triangle mesh of the whole model with the CityObjects as features, for the exporters. Triangles and groups are in the same order
+///// as the ones of parse_cityobjects(), so triangle i of an export is triangle i in the viewer. Vertices are in real coordinates.
+
+#[derive(Deserialize, Default)]
+pub struct Transform {
+
+    pub scale: [f64; 3],
+    pub translate: [f64; 3],
+
+}
+
+#[derive(Deserialize)]
+struct Vertices {
+
+    vertices: Vec<[f64; 3]>,
+    transform: Option<Transform>,
+    metadata: Option<Value>,
+
+}
+
+/// Options for the exporters, in JS { lod: "2.2", types: [ "Building" ], recenter: true } (all optional)
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct MeshOptions {
+
+    // Only geometries with this LoD (a number or a string, e.g. 2 or "2.2"). All geometries if not set.
+    pub lod: Option<Value>,
+    // Only CityObjects of these types. All types if not set.
+    pub types: Option<Vec<String>>,
+    // Move the center of the model to the origin
+    pub recenter: bool,
+
+}
+
+impl MeshOptions {
+
+    fn lod( &self ) -> Option<String> {
+
+        self.lod.as_ref().map( lod_string )
+
+    }
+
+}
+
+// An LoD as a string, so that 2 and "2" are the same
+pub(crate) fn lod_string( lod: &Value ) -> String {
+
+    match lod {
+        Value::String( s ) => s.clone(),
+        lod => lod.to_string(),
+    }
+
+}
+
+pub struct Feature {
+
+    pub id: String,
+    pub co_type: String,
+    pub attributes: Map<String, Value>,
+
+}
+
+pub struct Group {
+
+    pub co_type: String,
+    // Start and count in the triangles vector, like the groups of parse_cityobjects()
+    pub start: u32,
+    pub count: u32,
+
+}
+
+#[derive(Default)]
+pub struct Mesh {
+
+    // Real coordinates (the transform is applied)
+    pub vertices: Vec<[f64; 3]>,
+    // Flat vertex indices, three per triangle
+    pub triangles: Vec<u32>,
+    // Index in features per triangle
+    pub triangle_features: Vec<u32>,
+    // CityObjects with triangles
+    pub features: Vec<Feature>,
+    pub groups: Vec<Group>,
+    // What was subtracted from the vertices when recentring
+    pub offset: [f64; 3],
+    // metadata.referenceSystem of the file, e.g. "urn:ogc:def:crs:EPSG::7415"
+    pub reference_system: Option<String>,
+
+}
+
+/// Default color of a CityObject type, the same as the default object_colors of the viewer
+pub fn type_color( co_type: &str ) -> [u8; 3] {
+
+    match co_type {
+        "Building" | "BuildingPart" | "BuildingInstallation" => [ 0x74, 0x97, 0xdf ],
+        "CityObjectGroup" | "LandUse" => [ 0xff, 0xff, 0xb3 ],
+        "CityFurniture" | "GenericCityObject" => [ 0xcc, 0x00, 0x00 ],
+        "PlantCover" | "SolitaryVegetationObject" => [ 0x39, 0xac, 0x39 ],
+        "Railway" => [ 0x00, 0x00, 0x00 ],
+        "TINRelief" => [ 0xff, 0xdb, 0x99 ],
+        "WaterBody" => [ 0x4d, 0xa6, 0xff ],
+        _ => [ 0x99, 0x99, 0x99 ],
+    }
+
+}
+
+/// Read the vertices of a CityJSON document in real coordinates
+pub fn read_vertices( buffer: &[u8] ) -> Result<Vec<[f64; 3]>, serde_json::Error> {
+
+    Ok( read_document( buffer )?.0 )
+
+}
+
+// The vertices in real coordinates and the reference system
+fn read_document( buffer: &[u8] ) -> Result<( Vec<[f64; 3]>, Option<String> ), serde_json::Error> {
+
+    let doc: Vertices = format::from_slice( buffer )?;
+    let transform = doc.transform.unwrap_or( Transform { scale: [ 1.0; 3 ], translate: [ 0.0; 3 ] } );
+    let reference_system = doc.metadata.as_ref().and_then( |m| m.get( "referenceSystem" ) ).and_then( |r| r.as_str() ).map( |r| r.to_string() );
+
+    let vertices = doc.vertices.into_iter().map( |v| [
+        v[ 0 ] * transform.scale[ 0 ] + transform.translate[ 0 ],
+        v[ 1 ] * transform.scale[ 1 ] + transform.translate[ 1 ],
+        v[ 2 ] * transform.scale[ 2 ] + transform.translate[ 2 ],
+    ] ).collect();
+
+    Ok( ( vertices, reference_system ) )
+
+}
+
+impl Mesh {
+
+    /// Build the mesh of a CityJSON document. Attributes include joined columns and edits.
+    pub fn build( buffer: &[u8], options: &MeshOptions ) -> Result<Mesh, String> {
+
+        let parse_error = | e: serde_json::Error | format!( "Error parsing CityJSON buffer: {}", e );
+        let lod = options.lod();
+
+        // Features and their triangles per CityObject type, merged in the order of CO_TYPES afterwards
+        let mut per_type: Vec<Vec<( Feature, Vec<u32> )>> = CO_TYPES.iter().map( |_| Vec::new() ).collect();
+
+        stream::for_each_cityobject( buffer, |id, mut co| {
+
+            let co_type = match co.get( "type" ).and_then( |t| t.as_str() ).and_then( |t| CO_TYPES.iter().position( |c| *c == t ) ) {
+                Some( co_type ) => co_type,
+                None => return,
+            };
+
+            if options.types.as_ref().map( |types| !types.iter().any( |t| t == CO_TYPES[ co_type ] ) ).unwrap_or( false ) {
+                return;
+            }
+
+            if let ( Some( lod ), Some( geometries ) ) = ( &lod, co.get_mut( "geometry" ).and_then( |g| g.as_array_mut() ) ) {
+                geometries.retain( |g| g.get( "lod" ).map( lod_string ).as_ref() == Some( lod ) );
+            }
+
+            let triangles = to_bufferattributes::cityobject_triangles( &id, &co );
+
+            if triangles.is_empty() {
+                return;
+            }
+
+            let attributes = match co.get_mut( "attributes" ).map( Value::take ) {
+                Some( Value::Object( attributes ) ) => attributes,
+                _ => Map::new(),
+            };
+
+            per_type[ co_type ].push( ( Feature { id, co_type: CO_TYPES[ co_type ].to_string(), attributes }, triangles ) );
+
+        } ).map_err( parse_error )?;
+
+        let ( vertices, reference_system ) = read_document( buffer ).map_err( parse_error )?;
+        let mut mesh = Mesh { vertices, reference_system, ..Default::default() };
+
+        for ( co_type, features ) in CO_TYPES.iter().zip( per_type ) {
+
+            if features.is_empty() {
+                continue;
+            }
+
+            let start = mesh.triangles.len() as u32;
+
+            for ( feature, triangles ) in features {
+
+                if let Some( v ) = triangles.iter().find( |v| **v as usize >= mesh.vertices.len() ) {
+                    return Err( format!( "CityObject {} refers to vertex {}, which does not exist", feature.id, v ) );
+                }
+
+                let index = mesh.features.len() as u32;
+
+                mesh.triangle_features.extend( std::iter::repeat_n( index, triangles.len() / 3 ) );
+                mesh.triangles.extend( triangles );
+                mesh.features.push( feature );
+
+            }
+
+            mesh.groups.push( Group { co_type: co_type.to_string(), start, count: mesh.triangles.len() as u32 - start } );
+
+        }
+
+        if options.recenter {
+
+            let center = mesh.center();
+
+            for v in mesh.vertices.iter_mut() {
+                for i in 0..3 {
+                    v[ i ] -= center[ i ];
+                }
+            }
+
+            mesh.offset = center;
+
+        }
+
+        Ok( mesh )
+
+    }
+
+    /// Center of the bounding box of the vertices that are used by triangles
+    pub fn center( &self ) -> [f64; 3] {
+
+        let mut min = [ f64::INFINITY; 3 ];
+        let mut max = [ f64::NEG_INFINITY; 3 ];
+
+        for v in &self.triangles {
+
+            let v = self.vertices[ *v as usize ];
+
+            for i in 0..3 {
+                min[ i ] = min[ i ].min( v[ i ] );
+                max[ i ] = max[ i ].max( v[ i ] );
+            }
+
+        }
+
+        if self.triangles.is_empty() {
+            return [ 0.0; 3 ];
+        }
+
+        [ ( min[ 0 ] + max[ 0 ] ) / 2.0, ( min[ 1 ] + max[ 1 ] ) / 2.0, ( min[ 2 ] + max[ 2 ] ) / 2.0 ]
+
+    }
+
+    /// Triangle range [start, end) per feature (the triangles of a feature are consecutive)
+    pub fn feature_ranges( &self ) -> Vec<( usize, usize )> {
+
+        let mut ranges = vec![ ( 0, 0 ); self.features.len() ];
+
+        for ( t, feature ) in self.triangle_features.iter().enumerate() {
+
+            let range = &mut ranges[ *feature as usize ];
+
+            if range.0 == range.1 {
+                range.0 = t;
+            }
+
+            range.1 = t + 1;
+
+        }
+
+        ranges
+
+    }
+
+    /// Bounding box [min, max] per feature
+    pub fn feature_bounds( &self ) -> Vec<[[f64; 3]; 2]> {
+
+        let mut bounds = vec![ [ [ f64::INFINITY; 3 ], [ f64::NEG_INFINITY; 3 ] ]; self.features.len() ];
+
+        for ( i, v ) in self.triangles.iter().enumerate() {
+
+            let b = &mut bounds[ self.triangle_features[ i / 3 ] as usize ];
+            let v = self.vertices[ *v as usize ];
+
+            for c in 0..3 {
+                b[ 0 ][ c ] = b[ 0 ][ c ].min( v[ c ] );
+                b[ 1 ][ c ] = b[ 1 ][ c ].max( v[ c ] );
+            }
+
+        }
+
+        bounds
+
+    }
+
+    /// A new mesh with only the given features (indices in features, ascending), with compacted vertices
+    pub fn select( &self, features: &[u32], ranges: &[( usize, usize )] ) -> Mesh {
+
+        let mut mesh = Mesh { offset: self.offset, reference_system: self.reference_system.clone(), ..Default::default() };
+        let mut new_index: HashMap<u32, u32> = HashMap::new();
+
+        for ( i, f ) in features.iter().enumerate() {
+
+            let feature = &self.features[ *f as usize ];
+            let ( start, end ) = ranges[ *f as usize ];
+
+            if mesh.groups.last().map( |g| g.co_type != feature.co_type ).unwrap_or( true ) {
+                mesh.groups.push( Group { co_type: feature.co_type.clone(), start: mesh.triangles.len() as u32, count: 0 } );
+            }
+
+            for v in &self.triangles[ start * 3..end * 3 ] {
+
+                let next = new_index.len() as u32;
+                let v = *new_index.entry( *v ).or_insert_with( || {
+                    mesh.vertices.push( self.vertices[ *v as usize ] );
+                    next
+                } );
+
+                mesh.triangles.push( v );
+
+            }
+
+            mesh.triangle_features.extend( std::iter::repeat_n( i as u32, end - start ) );
+            mesh.groups.last_mut().unwrap().count += ( ( end - start ) * 3 ) as u32;
+            mesh.features.push( Feature { id: feature.id.clone(), co_type: feature.co_type.clone(), attributes: feature.attributes.clone() } );
+
+        }
+
+        mesh
+
+    }
+
+    /// The vertices that are used by triangles, in order of first use, and the triangles renumbered to them
+    pub fn compact( &self ) -> ( Vec<[f64; 3]>, Vec<u32> ) {
+
+        let mut new_index = vec![ u32::MAX; self.vertices.len() ];
+        let mut vertices = Vec::new();
+
+        let triangles = self.triangles.iter().map( |v| {
+
+            let v = *v as usize;
+
+            if new_index[ v ] == u32::MAX {
+                new_index[ v ] = vertices.len() as u32;
+                vertices.push( self.vertices[ v ] );
+            }
+
+            new_index[ v ]
+
+        } ).collect();
+
+        ( vertices, triangles )
+
+    }
+
+    /// Normal of a triangle (not normalized for degenerate triangles)
+    pub fn normal( &self, triangle: usize ) -> [f64; 3] {
+
+        let v = | i: usize | self.vertices[ self.triangles[ triangle * 3 + i ] as usize ];
+        let ( a, b, c ) = ( v( 0 ), v( 1 ), v( 2 ) );
+
+        let u = [ b[ 0 ] - a[ 0 ], b[ 1 ] - a[ 1 ], b[ 2 ] - a[ 2 ] ];
+        let w = [ c[ 0 ] - a[ 0 ], c[ 1 ] - a[ 1 ], c[ 2 ] - a[ 2 ] ];
+        let n = [ u[ 1 ] * w[ 2 ] - u[ 2 ] * w[ 1 ], u[ 2 ] * w[ 0 ] - u[ 0 ] * w[ 2 ], u[ 0 ] * w[ 1 ] - u[ 1 ] * w[ 0 ] ];
+        let length = ( n[ 0 ] * n[ 0 ] + n[ 1 ] * n[ 1 ] + n[ 2 ] * n[ 2 ] ).sqrt();
+
+        if length > 0.0 {
+            [ n[ 0 ] / length, n[ 1 ] / length, n[ 2 ] / length ]
+        } else {
+            n
+        }
+
+    }
+
+}
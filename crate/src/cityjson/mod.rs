@@ -1,4 +1,26 @@
-mod to_bufferattributes;
-mod get_attributes;
+///// Platform-independent core: everything works on the bytes of a CityJSON file (&[u8]) and returns Rust types, so that it can be used
+///// natively (the command-line tool, tests) as well as from the viewer through the wasm-bindgen layer in wasm/.
 
-use super::{WasmMemBuffer};
\ No newline at end of file
+pub mod to_bufferattributes;
+pub mod get_attributes;
+pub mod picking;
+pub mod hierarchy;
+pub mod stream;
+pub mod filter;
+pub mod thematic;
+pub mod stats;
+pub mod search;
+pub mod join;
+pub mod edit;
+pub mod subset;
+pub mod mesh;
+pub mod gltf;
+pub mod export;
+pub mod tiles;
+pub mod inspect;
+pub mod cache;
+pub mod progress;
+pub mod model;
+pub mod push;
+pub mod compression;
+pub mod format;
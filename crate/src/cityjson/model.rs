@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use super::get_attributes;
+use super::picking::{self, PickedCityObject, PickingIndex};
+use super::progress::{self, CancelToken};
+use super::to_bufferattributes::{self, ParsedCityObjects};
+
+///// A parsed file as a value instead of global state: it keeps the bytes of the file and its own picking index, so that picking and
+///// attribute queries keep working on this file after another file has been parsed. Parsing a model doesn't replace the global state
+///// (picking index, joined columns, edits) of the file that is shown. This is what a Web Worker holds on to after it has sent the
+///// geometry to the main thread.
+
+/// The output of a parse that can be handed over (e.g. transferred to the main thread) once
+pub struct Geometry {
+
+    pub triangles: Vec<u32>,
+    // [start, count] per CityObject type
+    pub groups: HashMap<String, Vec<u32>>,
+    pub vertices: Vec<u32>,
+
+}
+
+pub struct CityModel {
+
+    buffer: Vec<u8>,
+    picking: PickingIndex,
+    ids: Vec<String>,
+    // Triangle intervals for PickingIndex::Intervals, one object index per triangle for PickingIndex::Dense
+    index: Vec<u32>,
+    parents: HashMap<String, Vec<String>>,
+    geometry: Option<Geometry>,
+
+}
+
+impl CityModel {
+
+    /// Parse the CityObjects and the vertices of a file, with an optional progress callback and cancellation token (see triangulate_with_progress())
+    pub fn parse( buffer: Vec<u8>, picking: PickingIndex, callback: Option<progress::Callback>, token: Option<CancelToken> ) -> Result<CityModel, String> {
+
+        let parse = || to_bufferattributes::parse_local( &buffer, picking );
+
+        let ParsedCityObjects { triangles, groups, ids, index, hierarchy } = match ( callback, token ) {
+            ( None, None ) => parse().map_err( |e| format!( "Error parsing CityJSON buffer: {}", e ) )?,
+            ( callback, token ) => progress::with_progress( &buffer, callback, token, parse )?,
+        };
+
+        let vertices = to_bufferattributes::flatten_vertices( &buffer ).map_err( |e| format!( "Error parsing vertices: {}", e ) )?;
+
+        Ok( CityModel {
+            buffer,
+            picking,
+            ids,
+            index,
+            parents: hierarchy.parents,
+            geometry: Some( Geometry { triangles, groups, vertices } ),
+        } )
+
+    }
+
+    /// Hand over the triangles, groups and vertices. Returns None if they were already taken.
+    pub fn take_geometry( &mut self ) -> Option<Geometry> {
+
+        self.geometry.take()
+
+    }
+
+    /// The CityObject of a clicked triangle, with its first and last triangle and its parents (like picking::pick_cityobject())
+    pub fn pick( &self, query: u32 ) -> Option<PickedCityObject> {
+
+        let ( position, start, end ) = match self.picking {
+            PickingIndex::Dense => {
+                let ( object, first, last ) = picking::lookup_dense( &self.index, query )?;
+                ( object as usize, first, last )
+            },
+            PickingIndex::Intervals => {
+                let position = picking::find_interval( &self.index, query )?;
+                ( position, self.index[ position ], self.index[ position + 1 ] - 1 )
+            },
+        };
+
+        let id = self.ids[ position ].clone();
+        let parents = self.parents.get( &id ).cloned().unwrap_or_default();
+
+        Some( PickedCityObject { id, start, end, parents } )
+
+    }
+
+    /// The attributes of a CityObject (see get_attributes::get_attributes())
+    pub fn attributes( &self, id: &str ) -> Result<serde_json::Value, String> {
+
+        get_attributes::get_attributes( &self.buffer, id ).map_err( |e| e.to_string() )
+
+    }
+
+    /// The attributes of several CityObjects, optionally only some keys (see get_attributes::get_attributes_of())
+    pub fn attributes_of( &self, ids: &[String], keys: Option<&[String]> ) -> Result<Vec<serde_json::Value>, String> {
+
+        get_attributes::get_attributes_of( &self.buffer, ids, keys ).map_err( |e| e.to_string() )
+
+    }
+
+    /// The IDs of the parsed CityObjects, in the order of the picking index
+    pub fn ids( &self ) -> &[String] {
+
+        &self.ids
+
+    }
+
+    pub fn buffer( &self ) -> &[u8] {
+
+        &self.buffer
+
+    }
+
+}
@@ -0,0 +1,184 @@
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use std::collections::HashMap;
+use serde::Serialize;
+
+use super::to_bufferattributes::{IDS, INTERVALS};
+use super::hierarchy::HIERARCHY;
+
+/// How the picking index (clicked triangle -> CityObject ID) is stored in WASM memory
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PickingIndex {
+
+    // Sorted triangle intervals with an aligned vector of IDs, searched with a binary search. Costs memory per CityObject.
+    Intervals,
+    // One object index per triangle, pointing into the ID table. Costs memory per triangle, but lookups are O(1).
+    Dense,
+
+}
+
+// The storage that was chosen for the last parsed file, read by the deserializer and by get_interval_and_id()
+lazy_static! {
+    pub static ref PICKING: Mutex<PickingIndex> = Mutex::new(PickingIndex::Intervals);
+}
+
+// Dense picking index: for every triangle, the index of its CityObject ID in IDS
+lazy_static! {
+    pub static ref OBJECT_INDEX: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct PickedCityObject {
+
+    pub id: String,
+    pub start: u32,
+    pub end: u32,
+    pub parents: Vec<String>,
+
+}
+
+/// Find the interval to which a triangle belongs, in intervals as [0, end of 1st object, end of 2nd object, ...].
+/// Returns the index of the interval, i.e. the index of the CityObject ID.
+pub fn find_interval( intervals: &[u32], query: u32 ) -> Option<usize> {
+
+    // Index of the first interval bound that lies after the queried triangle. Zero-length intervals
+    // are skipped automatically, since the interval before that bound is the last one starting at or before the query.
+    let end = intervals.partition_point( |&bound| bound <= query );
+
+    if end == 0 || end >= intervals.len() {
+        return None;
+    }
+
+    Some( end - 1 )
+
+}
+
+/// Find the CityObject ID and its first and last triangle for a clicked triangle, using the picking index of the last parsed file
+pub fn pick( query: u32 ) -> Option<( String, u32, u32 )> {
+
+    let ids = IDS.lock().unwrap();
+
+    if *PICKING.lock().unwrap() == PickingIndex::Dense {
+
+        let object_index = OBJECT_INDEX.lock().unwrap();
+        let ( object, first, last ) = lookup_dense( &object_index, query )?;
+
+        return Some( ( ids[ object as usize ].clone(), first, last ) );
+
+    }
+
+    let intervals = INTERVALS.lock().unwrap();
+    let position = find_interval( &intervals, query )?;
+
+    Some( ( ids[ position ].clone(), intervals[ position ], intervals[ position + 1 ] - 1 ) )
+
+}
+
+/// First and last triangle of every CityObject that has triangles, in the order of the triangles
+pub fn all_triangle_ranges() -> Vec<TriangleInterval> {
+
+    let ids = IDS.lock().unwrap();
+
+    if *PICKING.lock().unwrap() == PickingIndex::Dense {
+
+        let object_index = OBJECT_INDEX.lock().unwrap();
+
+        return ids.iter().enumerate().filter_map( |( i, id )| {
+            let ( start, end ) = dense_range( &object_index, i as u32 )?;
+            Some( TriangleInterval { id: id.clone(), start, end } )
+        } ).collect();
+
+    }
+
+    let intervals = INTERVALS.lock().unwrap();
+
+    ids.iter().enumerate().map( |( i, id )| TriangleInterval { id: id.clone(), start: intervals[ i ], end: intervals[ i + 1 ] - 1 } ).collect()
+
+}
+
+/// Expand triangle intervals ([0, end of 1st object, end of 2nd object, ...]) into one object index per triangle
+pub fn intervals_to_object_index( intervals: &[u32] ) -> Vec<u32> {
+
+    let triangles_n = *intervals.last().unwrap_or(&0) as usize;
+    let mut object_index = Vec::with_capacity( triangles_n );
+
+    for ( i, bounds ) in intervals.windows( 2 ).enumerate() {
+
+        object_index.resize( bounds[ 1 ] as usize, i as u32 );
+
+    }
+
+    object_index
+
+}
+
+/// Look up a triangle in the dense index. Returns the object index and the first and last triangle of that object.
+pub fn lookup_dense( object_index: &[u32], query: u32 ) -> Option<( u32, u32, u32 )> {
+
+    let object = *object_index.get( query as usize )?;
+    let ( first, last ) = dense_range( object_index, object )?;
+
+    Some( ( object, first, last ) )
+
+}
+
+// The triangles of a CityObject are contiguous and CityObjects are stored in order of their index, so the dense index is sorted
+fn dense_range( object_index: &[u32], object: u32 ) -> Option<( u32, u32 )> {
+
+    let first = object_index.partition_point( |&o| o < object );
+    let end = object_index.partition_point( |&o| o <= object );
+
+    if first == end {
+        return None;
+    }
+
+    Some( ( first as u32, end as u32 - 1 ) )
+
+}
+
+/// First and last triangle of a CityObject
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct TriangleInterval {
+
+    pub id: String,
+    pub start: u32,
+    pub end: u32,
+
+}
+
+/// Find the first and last triangle of CityObjects by their IDs, using the picking index of the last parsed file.
+/// IDs of CityObjects without triangles are skipped.
+pub fn triangle_ranges( query_ids: &[String] ) -> Vec<TriangleInterval> {
+
+    let ids = IDS.lock().unwrap();
+    let positions: HashMap<&str, usize> = ids.iter().enumerate().map( |( i, id )| ( id.as_str(), i ) ).collect();
+
+    let dense = *PICKING.lock().unwrap() == PickingIndex::Dense;
+    let intervals = INTERVALS.lock().unwrap();
+    let object_index = OBJECT_INDEX.lock().unwrap();
+
+    query_ids.iter().filter_map( |id| {
+
+        let position = *positions.get( id.as_str() )?;
+
+        let ( start, end ) = if dense {
+            dense_range( &object_index, position as u32 )?
+        } else {
+            ( intervals[ position ], intervals[ position + 1 ] - 1 )
+        };
+
+        Some( TriangleInterval { id: id.clone(), start, end } )
+
+    } ).collect()
+
+}
+
+/// Like pick(), but also returns the parents of the clicked CityObject (e.g. the Building of a BuildingPart)
+pub fn pick_cityobject( query: u32 ) -> Option<PickedCityObject> {
+
+    let ( id, start, end ) = pick( query )?;
+    let parents = HIERARCHY.lock().unwrap().parents.get( &id ).cloned().unwrap_or_default();
+
+    Some( PickedCityObject { id, start, end, parents } )
+
+}
@@ -0,0 +1,174 @@
+use serde::Serialize;
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+///// Progress reporting and cancellation for long parses. The reporter of the running parse is kept per thread (like the other state
+///// that can't be passed to a Serde deserializer, see picking::PICKING), and the CityObjects deserializer calls step() after every
+///// CityObject: the cancellation token is checked every time, the callback is called for every percent of the file and when the parse
+///// is done.
+
+/// Error message of a cancelled parse
+pub const CANCELLED: &str = "Parsing cancelled";
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+
+    // CityObjects processed so far
+    pub objects: u32,
+    // Bytes of the buffer consumed so far, and the size of the buffer
+    pub bytes: usize,
+    pub total: usize,
+
+}
+
+/// Cancels a running parse. Clones share their state, so a parse can be cancelled from another thread or from the progress callback.
+#[derive(Clone, Default, Debug)]
+pub struct CancelToken( Arc<AtomicBool> );
+
+impl CancelToken {
+
+    pub fn new() -> Self {
+
+        Self::default()
+
+    }
+
+    pub fn cancel( &self ) {
+
+        self.0.store( true, Ordering::Relaxed );
+
+    }
+
+    pub fn is_cancelled( &self ) -> bool {
+
+        self.0.load( Ordering::Relaxed )
+
+    }
+
+}
+
+/// Called with the progress. Returning false cancels the parse.
+pub type Callback = Box<dyn FnMut( Progress ) -> bool>;
+
+struct Reporter {
+
+    callback: Option<Callback>,
+    token: CancelToken,
+    // Address and length of the buffer, to compute the consumed bytes from the position of a CityObject
+    start: usize,
+    total: usize,
+    reported: usize,
+    objects: u32,
+
+}
+
+thread_local! {
+    static REPORTER: RefCell<Option<Reporter>> = const { RefCell::new(None) };
+}
+
+// Removes the reporter when the parse is done, also when it fails or panics
+struct Installed;
+
+impl Drop for Installed {
+
+    fn drop( &mut self ) {
+
+        REPORTER.with( |r| r.borrow_mut().take() );
+
+    }
+
+}
+
+/// Run a parse of buffer with a progress callback and/or a cancellation token. Returns CANCELLED as the error if the parse was cancelled.
+pub fn with_progress<T, E: ToString>( buffer: &[u8], callback: Option<Callback>, token: Option<CancelToken>, parse: impl FnOnce() -> Result<T, E> ) -> Result<T, String> {
+
+    let token = token.unwrap_or_default();
+
+    REPORTER.with( |r| *r.borrow_mut() = Some( Reporter { callback, token: token.clone(), start: buffer.as_ptr() as usize, total: buffer.len(), reported: 0, objects: 0 } ) );
+
+    let _installed = Installed;
+
+    let res = parse();
+
+    if token.is_cancelled() {
+        return Err( CANCELLED.to_string() );
+    }
+
+    let res = res.map_err( |e| e.to_string() )?;
+
+    REPORTER.with( |r| {
+
+        if let Some( reporter ) = r.borrow_mut().as_mut() {
+            let ( objects, total ) = ( reporter.objects, reporter.total );
+            report( reporter, Progress { objects, bytes: total, total } );
+        }
+
+    } );
+
+    Ok( res )
+
+}
+
+/// Whether a parse with progress is running, i.e. whether step() needs to be called
+pub fn tracking() -> bool {
+
+    REPORTER.with( |r| r.borrow().is_some() )
+
+}
+
+/// Report that a CityObject ending at end (a slice of the buffer) has been processed. Returns false if the parse should stop.
+pub fn step( objects: u32, end: &str ) -> bool {
+
+    REPORTER.with( |r| {
+
+        let mut r = r.borrow_mut();
+        let reporter = match r.as_mut() {
+            Some( reporter ) => reporter,
+            None => return true,
+        };
+
+        let bytes = ( end.as_ptr() as usize + end.len() ).saturating_sub( reporter.start ).min( reporter.total );
+
+        reporter.objects = objects;
+
+        if bytes >= reporter.reported + reporter.total / 100 {
+            reporter.reported = bytes;
+            report( reporter, Progress { objects, bytes, total: reporter.total } );
+        }
+
+        !reporter.token.is_cancelled()
+
+    } )
+
+}
+
+/// Like step(), for input where it isn't known where a CityObject ends (CBOR, MessagePack): only counts the CityObject and checks the
+/// cancellation token. The progress is reported when the parse is done.
+pub fn count( objects: u32 ) -> bool {
+
+    REPORTER.with( |r| {
+
+        match r.borrow_mut().as_mut() {
+            Some( reporter ) => {
+                reporter.objects = objects;
+                !reporter.token.is_cancelled()
+            },
+            None => true,
+        }
+
+    } )
+
+}
+
+fn report( reporter: &mut Reporter, progress: Progress ) {
+
+    if let Some( callback ) = reporter.callback.as_mut() {
+
+        if !callback( progress ) {
+            reporter.token.cancel();
+        }
+
+    }
+
+}
@@ -0,0 +1,715 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+
+use super::compression::{self, Decoder};
+use super::format::{self, Format};
+
+use super::picking::PickingIndex;
+use super::to_bufferattributes::{self, CityObjectsBuilder, ThreeGroups};
+
+///// Push-based parsing: the file is given in chunks as they arrive (e.g. from a fetch stream) instead of as one buffer. A small
+///// state machine walks over the structure of the document; every CityObject and every vertex is parsed with Serde as soon as its last
+///// byte has arrived, and the bytes before it are released. Only the current chunk and an incomplete CityObject are kept in memory.
+///// Other members of the document (metadata, appearance, ...) are skipped without keeping them.
+/////
+///// The result is the same as that of to_bufferattributes::triangulate() and flatten_vertices() on the whole file, and the picking index
+///// etc. are stored globally the same way. Since the file itself is not kept, queries on the buffer (e.g. get_attributes()) need the file.
+/////
+///// For files whose output doesn't fit in WASM memory either (wasm32 can address at most 2-4 GB), a parser with a batch size hands out the
+///// triangles and vertices in batches of about that many indices (next_batch()) and doesn't keep them. The picking index then follows the
+///// order of the batches. The file can be pushed in segments of any size, e.g. slices of a File in JS or WasmMemBuffers (push_owned()).
+/////
+///// CityJSON Text Sequences (CityJSONSeq, .city.jsonl) are read the same way: the first document is the header, and every following one
+///// must be a CityJSONFeature. The vertex indices of a feature are local to its own vertices, so they are offset by the vertices of the
+///// features before it. All features are merged into the same triangles, groups and picking index as a single document.
+/////
+///// Gzip and zlib compressed input is detected from the first two bytes and decompressed chunk by chunk, so the decompressed text
+///// doesn't have to be in memory as a whole either. pending() and released() count decompressed bytes.
+
+// Where the parser is in the document
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Level {
+
+    Document,
+    CityObjects,
+
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum State {
+
+    // Before the opening brace of the document
+    Start,
+    // Expecting a key (or the end) of the document or of "CityObjects"
+    Key( Level ),
+    // In a key that started at self.start
+    InKey( Level ),
+    Colon( Level, String ),
+    Value( Level, String ),
+    // In a value that is skipped
+    Skip,
+    // In the "type" of a document, which started at self.start
+    Type,
+    // In a CityObject that started at self.start
+    CityObject( String ),
+    // Expecting a vertex (or the end) of "vertices"
+    Vertices,
+    // In a vertex that started at self.start
+    Vertex,
+    // After the closing brace of the document
+    End,
+    // After an error
+    Failed( String ),
+
+}
+
+// Finds the end of a JSON value whose bytes are given one at a time
+#[derive(Default)]
+struct ValueScan {
+
+    depth: u32,
+    string: bool,
+    escape: bool,
+    // Numbers, true, false and null, which end before the next delimiter
+    scalar: bool,
+
+}
+
+enum Scan {
+
+    More,
+    // The byte was the last one of the value
+    Done,
+    // The value ended before this byte
+    DoneBefore,
+
+}
+
+impl ValueScan {
+
+    fn feed( &mut self, b: u8 ) -> Result<Scan, String> {
+
+        if self.string {
+
+            if self.escape {
+                self.escape = false;
+            } else if b == b'\\' {
+                self.escape = true;
+            } else if b == b'"' {
+                self.string = false;
+                if self.depth == 0 {
+                    return Ok( Scan::Done );
+                }
+            }
+
+            return Ok( Scan::More );
+
+        }
+
+        if self.scalar {
+
+            return Ok( match b {
+                b',' | b'}' | b']' | b' ' | b'\t' | b'\n' | b'\r' => Scan::DoneBefore,
+                _ => Scan::More,
+            } );
+
+        }
+
+        match b {
+            b'"' => self.string = true,
+            b'{' | b'[' => self.depth += 1,
+            b'}' | b']' => {
+
+                if self.depth == 0 {
+                    return Err( format!( "Unexpected '{}'", b as char ) );
+                }
+
+                self.depth -= 1;
+
+                if self.depth == 0 {
+                    return Ok( Scan::Done );
+                }
+
+            },
+            b' ' | b'\t' | b'\n' | b'\r' | b',' | b':' => (),
+            _ => {
+                if self.depth == 0 {
+                    self.scalar = true;
+                }
+            },
+        }
+
+        Ok( Scan::More )
+
+    }
+
+}
+
+/// The triangles and vertices that arrived since the previous batch. The groups ([start, count] per CityObject type) are within these
+/// triangles; vertices are appended to those of the previous batches.
+#[derive(Default, Debug)]
+pub struct Batch {
+
+    pub triangles: Vec<u32>,
+    pub groups: HashMap<String, Vec<u32>>,
+    pub vertices: Vec<u32>,
+
+}
+
+pub struct PushParser {
+
+    state: State,
+    // Bytes that were pushed but not released yet, from self.released on in the file
+    pending: Vec<u8>,
+    released: usize,
+    // Position in pending up to which the bytes were scanned, and the start of the key or value that is kept
+    pos: usize,
+    start: usize,
+    scan: ValueScan,
+    builder: CityObjectsBuilder,
+    vertices: Vec<u32>,
+    // Amount of vertex coordinates that take_batch() already returned
+    vertices_taken: usize,
+    // With a batch size, the batches that are full and were not taken yet
+    batch_size: Option<usize>,
+    batches: VecDeque<Batch>,
+    // Documents started (more than one for a CityJSONSeq), all vertex coordinates so far, and the first vertex of the current document
+    documents: u32,
+    coordinates: usize,
+    vertex_offset: u64,
+    // The first bytes, until there are enough to detect the compression, and the decoder for compressed input
+    head: Option<Vec<u8>>,
+    decoder: Option<Decoder>,
+
+}
+
+fn is_whitespace( b: u8 ) -> bool {
+
+    matches!( b, b' ' | b'\t' | b'\n' | b'\r' )
+
+}
+
+impl PushParser {
+
+    pub fn new( picking: PickingIndex ) -> Self {
+
+        PushParser {
+            state: State::Start,
+            pending: Vec::new(),
+            released: 0,
+            pos: 0,
+            start: 0,
+            scan: ValueScan::default(),
+            builder: CityObjectsBuilder::new( picking ),
+            vertices: Vec::new(),
+            vertices_taken: 0,
+            batch_size: None,
+            batches: VecDeque::new(),
+            documents: 0,
+            coordinates: 0,
+            vertex_offset: 0,
+            head: Some( Vec::new() ),
+            decoder: None,
+        }
+
+    }
+
+    /// A parser that doesn't keep the triangles and vertices, but hands them out with next_batch() as soon as a batch has about
+    /// batch_size triangle indices or vertex coordinates
+    pub fn with_batch_size( picking: PickingIndex, batch_size: usize ) -> Self {
+
+        PushParser { batch_size: Some( batch_size.max( 1 ) ), ..PushParser::new( picking ) }
+
+    }
+
+    /// Process the next chunk of the file. After an error, every call returns that error.
+    pub fn push( &mut self, chunk: &[u8] ) -> Result<(), String> {
+
+        self.input( Cow::Borrowed( chunk ) )
+
+    }
+
+    /// Same as push(), but takes the segment, so that it isn't copied if nothing of the previous segment is kept
+    pub fn push_owned( &mut self, segment: Vec<u8> ) -> Result<(), String> {
+
+        self.input( Cow::Owned( segment ) )
+
+    }
+
+    fn input( &mut self, chunk: Cow<[u8]> ) -> Result<(), String> {
+
+        if let State::Failed( e ) = &self.state {
+            return Err( e.clone() );
+        }
+
+        let mut chunk = chunk;
+
+        // Detect the compression once there are two bytes
+        if let Some( head ) = self.head.as_mut() {
+
+            head.extend_from_slice( &chunk );
+
+            if head.len() < 2 {
+                return Ok( () );
+            }
+
+            self.decoder = Decoder::new( compression::detect( head ) );
+            chunk = Cow::Owned( self.head.take().unwrap() );
+
+        }
+
+        let chunk = match self.decoder.as_mut() {
+            Some( decoder ) => match decoder.decode( &chunk ) {
+                Ok( out ) => Cow::Owned( out ),
+                Err( e ) => return Err( self.fail( e ) ),
+            },
+            None => chunk,
+        };
+
+        match chunk {
+            Cow::Owned( segment ) if self.pending.is_empty() => self.pending = segment,
+            chunk => self.pending.extend_from_slice( &chunk ),
+        }
+
+        self.advance()
+
+    }
+
+    // Keep the error, so that every following call returns it
+    fn fail( &mut self, e: String ) -> String {
+
+        let e = format!( "Error parsing CityJSON stream at byte {}: {}", self.released + self.pos, e );
+        self.state = State::Failed( e.clone() );
+
+        e
+
+    }
+
+    fn advance( &mut self ) -> Result<(), String> {
+
+        let res = self.process();
+
+        // Release everything before the key or value that is not complete yet
+        let keep = match self.state {
+            State::InKey( _ ) | State::Type | State::CityObject( _ ) | State::Vertex => self.start,
+            _ => self.pos,
+        };
+
+        self.pending.drain( ..keep );
+        self.released += keep;
+        self.pos -= keep;
+        self.start -= keep.min( self.start );
+
+        res.map_err( |e| self.fail( e ) )
+
+    }
+
+    /// Bytes that are kept because the CityObject (or key, or vertex) they belong to is not complete yet
+    pub fn pending( &self ) -> usize {
+
+        self.pending.len()
+
+    }
+
+    /// Bytes that were processed and released
+    pub fn released( &self ) -> usize {
+
+        self.released
+
+    }
+
+    /// CityObjects that were parsed so far
+    pub fn objects( &self ) -> u32 {
+
+        self.builder.objects()
+
+    }
+
+    /// The triangles and vertices that were parsed since the previous call, e.g. to show the geometry while the file is downloading.
+    /// With a batch size, this is next_batch() (or an empty batch).
+    pub fn take_batch( &mut self ) -> Batch {
+
+        if self.batch_size.is_some() {
+            return self.next_batch().unwrap_or_default();
+        }
+
+        let ( triangles, groups ) = self.builder.take_batch();
+        let vertices = self.vertices[ self.vertices_taken.. ].to_vec();
+
+        self.vertices_taken = self.vertices.len();
+
+        Batch { triangles, groups, vertices }
+
+    }
+
+    /// With a batch size: the next full batch. Triangles index into the vertices of all batches, and the triangles of a batch come
+    /// after those of the previous batches in the picking index.
+    pub fn next_batch( &mut self ) -> Option<Batch> {
+
+        self.batches.pop_front()
+
+    }
+
+    // Queue a batch if the triangles or the vertices reached the batch size
+    fn check_batch( &mut self ) {
+
+        let size = match self.batch_size {
+            Some( size ) => size,
+            None => return,
+        };
+
+        if self.builder.pending() >= size || self.vertices.len() >= size {
+
+            let ( triangles, groups ) = self.builder.drain_batch();
+
+            self.batches.push_back( Batch { triangles, groups, vertices: std::mem::take( &mut self.vertices ) } );
+
+        }
+
+    }
+
+    /// Check that the whole document was pushed, and merge the CityObjects like triangulate() does (storing the picking index globally).
+    /// Returns the triangles, groups and vertices of the whole file; with a batch size, those that are not in a batch yet (the last batch).
+    pub fn finish( mut self ) -> Result<ThreeGroups, String> {
+
+        // A file of less than two bytes, or the end of the compressed data
+        if let Some( head ) = self.head.take() {
+
+            self.pending.extend( head );
+            self.advance()?;
+
+        } else if let Some( decoder ) = self.decoder.as_mut() {
+
+            match decoder.finish() {
+                Ok( rest ) => self.pending.extend( rest ),
+                Err( e ) => return Err( self.fail( e ) ),
+            }
+
+            self.advance()?;
+
+        }
+
+        match self.state {
+            State::End => (),
+            State::Failed( e ) => return Err( e ),
+            _ => return Err( "Error parsing CityJSON stream: the document is incomplete".to_string() ),
+        }
+
+        if !self.batches.is_empty() {
+            return Err( format!( "Error parsing CityJSON stream: {} batches were not taken", self.batches.len() ) );
+        }
+
+        let mut res = match self.batch_size {
+            Some( _ ) => self.builder.finish_drained(),
+            None => self.builder.finish(),
+        };
+
+        res.vertices = self.vertices;
+
+        log!("Rust: CityJSON stream parsed");
+
+        Ok( res )
+
+    }
+
+    fn start_document( &mut self ) {
+
+        self.documents += 1;
+        self.vertex_offset = ( self.coordinates / 3 ) as u64;
+        self.state = State::Key( Level::Document );
+
+    }
+
+    // Advance over pending as far as possible
+    fn process( &mut self ) -> Result<(), String> {
+
+        while self.pos < self.pending.len() {
+
+            let b = self.pending[ self.pos ];
+
+            match std::mem::replace( &mut self.state, State::Start ) {
+
+                State::Start => {
+
+                    self.state = State::Start;
+
+                    if !is_whitespace( b ) {
+                        if format::detect( &[ b ] ) != Format::Json {
+                            return Err( "CBOR and MessagePack documents can't be parsed in chunks, parse the whole buffer instead".to_string() );
+                        }
+                        if b != b'{' {
+                            return Err( "a CityJSON document is an object".to_string() );
+                        }
+                        self.start_document();
+                    }
+
+                    self.pos += 1;
+
+                },
+                State::Key( level ) => {
+
+                    self.state = State::Key( level );
+
+                    match b {
+                        b'"' => {
+                            self.start = self.pos;
+                            self.scan = ValueScan::default();
+                            self.scan.feed( b )?;
+                            self.state = State::InKey( level );
+                        },
+                        b'}' if level == Level::Document => self.state = State::End,
+                        b'}' => self.state = State::Key( Level::Document ),
+                        b',' => (),
+                        b if is_whitespace( b ) => (),
+                        b => return Err( format!( "expected a key, found '{}'", b as char ) ),
+                    }
+
+                    self.pos += 1;
+
+                },
+                State::InKey( level ) => {
+
+                    self.state = State::InKey( level );
+                    self.pos += 1;
+
+                    if let Scan::Done = self.scan.feed( b )? {
+                        let key: String = serde_json::from_slice( &self.pending[ self.start..self.pos ] ).map_err( |e| e.to_string() )?;
+                        self.state = State::Colon( level, key );
+                    }
+
+                },
+                State::Colon( level, key ) => {
+
+                    match b {
+                        b':' => self.state = State::Value( level, key ),
+                        b if is_whitespace( b ) => self.state = State::Colon( level, key ),
+                        b => return Err( format!( "expected ':', found '{}'", b as char ) ),
+                    }
+
+                    self.pos += 1;
+
+                },
+                State::Value( level, key ) => {
+
+                    if is_whitespace( b ) {
+                        self.state = State::Value( level, key );
+                        self.pos += 1;
+                        continue;
+                    }
+
+                    self.start = self.pos;
+                    self.scan = ValueScan::default();
+
+                    // Don't advance: the first byte of the value is scanned in the next state (except for the opening brace/bracket
+                    // of "CityObjects" and "vertices", whose members are handled one by one)
+                    self.state = match ( level, key.as_str() ) {
+                        ( Level::Document, "CityObjects" ) if b == b'{' => { self.pos += 1; State::Key( Level::CityObjects ) },
+                        ( Level::Document, "vertices" ) if b == b'[' => { self.pos += 1; State::Vertices },
+                        ( Level::Document, "CityObjects" ) | ( Level::Document, "vertices" ) => return Err( format!( "\"{}\" has the wrong type", key ) ),
+                        ( Level::Document, "type" ) => State::Type,
+                        ( Level::Document, _ ) => State::Skip,
+                        ( Level::CityObjects, _ ) => State::CityObject( key ),
+                    };
+
+                },
+                State::Skip => {
+
+                    self.state = State::Skip;
+
+                    match self.scan.feed( b )? {
+                        Scan::More => self.pos += 1,
+                        Scan::Done => { self.pos += 1; self.state = State::Key( Level::Document ); },
+                        Scan::DoneBefore => self.state = State::Key( Level::Document ),
+                    }
+
+                },
+                State::Type => {
+
+                    self.state = State::Type;
+                    self.pos += 1;
+
+                    match self.scan.feed( b )? {
+                        Scan::Done => {
+
+                            let doc_type: String = serde_json::from_slice( &self.pending[ self.start..self.pos ] ).map_err( |e| e.to_string() )?;
+
+                            // After the header of a CityJSONSeq, every document is a feature
+                            if self.documents > 1 && doc_type != "CityJSONFeature" {
+                                return Err( format!( "expected a CityJSONFeature after the first document, found \"{}\"", doc_type ) );
+                            }
+
+                            self.state = State::Key( Level::Document );
+
+                        },
+                        Scan::DoneBefore => return Err( "\"type\" is not a string".to_string() ),
+                        Scan::More => (),
+                    }
+
+                },
+                State::CityObject( key ) => {
+
+                    self.pos += 1;
+
+                    match self.scan.feed( b )? {
+                        Scan::Done => {
+
+                            let mut value: serde_json::Value = serde_json::from_slice( &self.pending[ self.start..self.pos ] ).map_err( |e| e.to_string() )?;
+
+                            if !value.is_object() {
+                                return Err( format!( "CityObject \"{}\" is not an object", key ) );
+                            }
+
+                            if self.vertex_offset > 0 {
+                                offset_vertices( &mut value, self.vertex_offset );
+                            }
+
+                            self.builder.add( &key, &value )?;
+                            self.state = State::Key( Level::CityObjects );
+                            self.check_batch();
+
+                        },
+                        Scan::DoneBefore => return Err( format!( "CityObject \"{}\" is not an object", key ) ),
+                        Scan::More => self.state = State::CityObject( key ),
+                    }
+
+                },
+                State::Vertices => {
+
+                    self.state = State::Vertices;
+
+                    match b {
+                        b'[' => {
+                            self.start = self.pos;
+                            self.scan = ValueScan::default();
+                            self.scan.feed( b )?;
+                            self.state = State::Vertex;
+                        },
+                        b']' => self.state = State::Key( Level::Document ),
+                        b',' => (),
+                        b if is_whitespace( b ) => (),
+                        b => return Err( format!( "expected a vertex, found '{}'", b as char ) ),
+                    }
+
+                    self.pos += 1;
+
+                },
+                State::Vertex => {
+
+                    self.state = State::Vertex;
+                    self.pos += 1;
+
+                    if let Scan::Done = self.scan.feed( b )? {
+
+                        let vertex: Vec<u32> = serde_json::from_slice( &self.pending[ self.start..self.pos ] ).map_err( |e| e.to_string() )?;
+
+                        self.coordinates += vertex.len();
+                        self.vertices.extend( vertex );
+                        self.state = State::Vertices;
+                        self.check_batch();
+
+                    }
+
+                },
+                State::End => {
+
+                    self.state = State::End;
+
+                    // The next document of a CityJSONSeq
+                    if b == b'{' {
+                        self.start_document();
+                    } else if !is_whitespace( b ) {
+                        return Err( "trailing characters after the document".to_string() );
+                    }
+
+                    self.pos += 1;
+
+                },
+                State::Failed( e ) => {
+
+                    self.state = State::Failed( e.clone() );
+                    return Err( e );
+
+                },
+
+            }
+
+        }
+
+        Ok( () )
+
+    }
+
+}
+
+// Add the vertices of the previous documents to the vertex indices in the boundaries of a CityObject of a CityJSONFeature
+fn offset_vertices( co: &mut serde_json::Value, offset: u64 ) {
+
+    fn offset_all( value: &mut serde_json::Value, offset: u64 ) {
+
+        if let Some( i ) = value.as_u64() {
+            *value = ( i + offset ).into();
+        } else if let Some( values ) = value.as_array_mut() {
+            values.iter_mut().for_each( |v| offset_all( v, offset ) );
+        }
+
+    }
+
+    if let Some( geometries ) = co.get_mut( "geometry" ).and_then( |g| g.as_array_mut() ) {
+
+        for geometry in geometries {
+            if let Some( boundaries ) = geometry.get_mut( "boundaries" ) {
+                offset_all( boundaries, offset );
+            }
+        }
+
+    }
+
+}
+
+/// Parse a whole buffer: a CityJSON document or a CityJSONSeq. Like triangulate() and flatten_vertices() together, but also for sequences.
+/// A CBOR or MessagePack document is parsed with triangulate() and flatten_vertices() themselves.
+pub fn parse_buffer( buffer: &[u8], picking: PickingIndex ) -> Result<ThreeGroups, String> {
+
+    if format::detect( buffer ) != Format::Json {
+
+        let mut groups = to_bufferattributes::triangulate( buffer, picking ).map_err( |e| format!( "Error parsing CityJSON buffer: {}", e ) )?;
+        groups.vertices = to_bufferattributes::flatten_vertices( buffer ).map_err( |e| format!( "Error parsing CityJSON buffer: {}", e ) )?;
+
+        return Ok( groups );
+
+    }
+
+    let mut parser = PushParser::new( picking );
+
+    parser.push( buffer )?;
+    parser.finish()
+
+}
+
+/// Parse a file that is given as a list of segments (e.g. read from disk in pieces), with a batch size so that neither the file nor the
+/// output is kept as a whole: on_batch() gets every batch, including the last one. Segments are dropped once they are processed.
+pub fn parse_segments<I, F>( segments: I, picking: PickingIndex, batch_size: usize, mut on_batch: F ) -> Result<(), String>
+where
+    I: IntoIterator<Item = Vec<u8>>,
+    F: FnMut( Batch ),
+{
+
+    let mut parser = PushParser::with_batch_size( picking, batch_size );
+
+    for segment in segments {
+
+        parser.push_owned( segment )?;
+
+        while let Some( batch ) = parser.next_batch() {
+            on_batch( batch );
+        }
+
+    }
+
+    let last = parser.finish()?;
+
+    on_batch( Batch { triangles: last.triangles, groups: last.groups, vertices: last.vertices } );
+
+    Ok( () )
+
+}
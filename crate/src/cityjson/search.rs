@@ -0,0 +1,265 @@
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use std::collections::HashMap;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::filter;
+use super::picking;
+
+///// Search index over CityObject IDs and selected (string) attributes, built while parsing the CityObjects.
+///// Supports (case-insensitive) prefix search on whole values and on the words in them, and substring search.
+
+/// How a search query is matched against the indexed values
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SearchMode {
+
+    // The value or one of its words starts with the query
+    Prefix,
+    // The value contains the query
+    Substring,
+
+}
+
+#[derive(Default)]
+pub struct SearchIndex {
+
+    ids: Vec<String>,
+    fields: Vec<String>,
+    // Parsed fields, None for fields that are not valid field names
+    paths: Vec<Option<Vec<String>>>,
+    // ( lowercase value, original value, index in ids, index in fields )
+    values: Vec<( String, String, u32, u32 )>,
+    // Lowercase values and the words in them, sorted, with their index in values. Used for prefix search.
+    terms: Vec<( String, u32 )>,
+
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SearchResult {
+
+    pub id: String,
+    // Field in which the best match was found ("id" for the CityObject ID)
+    pub field: String,
+    pub value: String,
+    // 4: exact match, 3: value starts with the query, 2: a word starts with the query, 1: value contains the query. IDs score 0.5 higher.
+    pub score: f32,
+    // Triangle interval, if the CityObject has triangles
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<u32>,
+
+}
+
+// Fields to index while parsing. None means that no index is built.
+lazy_static! {
+    pub static ref SEARCH_FIELDS: Mutex<Option<Vec<String>>> = Mutex::new(None);
+}
+
+lazy_static! {
+    pub static ref SEARCH_INDEX: Mutex<SearchIndex> = Mutex::new(SearchIndex::default());
+}
+
+// Split a lowercase value into words, for word prefix search
+fn words( value: &str ) -> impl Iterator<Item = &str> {
+
+    value.split( |c: char| !c.is_alphanumeric() ).filter( |w| !w.is_empty() )
+
+}
+
+// Collect all strings (and numbers) in a value, e.g. all fields of an address
+fn collect_strings( value: &Value, out: &mut Vec<String> ) {
+
+    match value {
+        Value::String( s ) => out.push( s.clone() ),
+        Value::Number( n ) => out.push( n.to_string() ),
+        Value::Array( items ) => items.iter().for_each( |v| collect_strings( v, out ) ),
+        Value::Object( map ) => map.values().for_each( |v| collect_strings( v, out ) ),
+        _ => {},
+    }
+
+}
+
+impl SearchIndex {
+
+    /// Create an empty index that indexes the given fields (e.g. "address" or "identificatie") next to the IDs.
+    /// Fields have the same syntax as in filter expressions.
+    pub fn new( fields: &[String] ) -> Self {
+
+        let mut all = vec![ "id".to_string() ];
+        all.extend( fields.iter().filter( |f| f.as_str() != "id" ).cloned() );
+
+        let paths = all.iter().map( |f| filter::parse_field( f ).ok() ).collect();
+
+        SearchIndex { fields: all, paths, ..Default::default() }
+
+    }
+
+    fn push( &mut self, value: String, doc: u32, field: u32 ) {
+
+        let lower = value.to_lowercase();
+        let index = self.values.len() as u32;
+
+        self.terms.push( ( lower.clone(), index ) );
+
+        for ( i, word ) in words( &lower ).enumerate() {
+
+            // A first word at the very start of the value is already found through the value itself. Not so after leading
+            // punctuation or spaces, e.g. the "main" of "(main) street".
+            if i == 0 && lower.starts_with( word ) {
+                continue;
+            }
+
+            self.terms.push( ( word.to_string(), index ) );
+
+        }
+
+        self.values.push( ( lower, value, doc, field ) );
+
+    }
+
+    /// Index one CityObject
+    pub fn add( &mut self, id: &str, co: &Value ) {
+
+        let doc = self.ids.len() as u32;
+        let id_value = Value::String( id.to_string() );
+
+        self.ids.push( id.to_string() );
+
+        for field in 0..self.paths.len() {
+
+            let mut strings = Vec::new();
+
+            if let Some( value ) = self.paths[ field ].as_ref().and_then( |path| filter::resolve( path, &id_value, co ) ) {
+                collect_strings( value, &mut strings );
+            }
+
+            for s in strings {
+                self.push( s, doc, field as u32 );
+            }
+
+        }
+
+    }
+
+    /// To be called once all CityObjects have been added
+    pub fn finish( &mut self ) {
+
+        self.terms.sort_unstable();
+        self.terms.dedup();
+
+    }
+
+    fn score( &self, value: u32, query: &str, mode: SearchMode ) -> Option<f32> {
+
+        let ( lower, _, _, field ) = &self.values[ value as usize ];
+
+        let score = if lower == query {
+            4.0
+        } else if lower.starts_with( query ) {
+            3.0
+        } else if words( lower ).any( |w| w.starts_with( query ) ) {
+            2.0
+        } else if mode == SearchMode::Substring && lower.contains( query ) {
+            1.0
+        } else {
+            return None;
+        };
+
+        Some( if *field == 0 { score + 0.5 } else { score } )
+
+    }
+
+    /// Search the index. Returns at most limit CityObjects (0 for all), best match first, then shortest value, then by ID.
+    pub fn search( &self, query: &str, mode: SearchMode, limit: usize ) -> Vec<SearchResult> {
+
+        let query = query.trim().to_lowercase();
+
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        // Candidate values: a range of the sorted terms for prefix search, all values for substring search
+        let candidates: Vec<u32> = match mode {
+            SearchMode::Prefix => {
+                let start = self.terms.partition_point( |( term, _ )| term.as_str() < query.as_str() );
+                self.terms[ start.. ].iter().take_while( |( term, _ )| term.starts_with( &query ) ).map( |( _, value )| *value ).collect()
+            },
+            SearchMode::Substring => ( 0..self.values.len() as u32 ).collect(),
+        };
+
+        // Best scoring value per CityObject
+        let mut best: HashMap<u32, ( f32, u32 )> = HashMap::new();
+
+        for value in candidates {
+
+            if let Some( score ) = self.score( value, &query, mode ) {
+
+                let doc = self.values[ value as usize ].2;
+                let entry = best.entry( doc ).or_insert( ( score, value ) );
+
+                let shorter = self.values[ value as usize ].0.len() < self.values[ entry.1 as usize ].0.len();
+
+                if score > entry.0 || ( score == entry.0 && shorter ) {
+                    *entry = ( score, value );
+                }
+
+            }
+
+        }
+
+        let mut ranked: Vec<( f32, u32 )> = best.into_values().collect();
+
+        ranked.sort_by( |a, b| {
+            let ( va, vb ) = ( &self.values[ a.1 as usize ], &self.values[ b.1 as usize ] );
+            b.0.partial_cmp( &a.0 ).unwrap()
+               .then( va.0.len().cmp( &vb.0.len() ) )
+               .then( self.ids[ va.2 as usize ].cmp( &self.ids[ vb.2 as usize ] ) )
+        } );
+
+        if limit > 0 {
+            ranked.truncate( limit );
+        }
+
+        ranked.into_iter().map( |( score, value )| {
+
+            let ( _, original, doc, field ) = &self.values[ value as usize ];
+
+            SearchResult { id: self.ids[ *doc as usize ].clone(), field: self.fields[ *field as usize ].clone(), value: original.clone(), score, start: None, end: None }
+
+        } ).collect()
+
+    }
+
+}
+
+/// Build a search index while parsing the next file, over the CityObject IDs and the given fields, e.g. [ "address", "identificatie" ].
+/// Must be called before parsing. Pass None to stop building an index.
+pub fn set_search_fields( fields: Option<Vec<String>> ) {
+
+    *SEARCH_FIELDS.lock().unwrap() = fields;
+
+}
+
+/// Search the index of the last parsed file, best match first, with start/end the triangle interval of the CityObject if it has triangles.
+/// A limit of 0 returns all matches.
+pub fn search_cityobjects( query: &str, mode: SearchMode, limit: usize ) -> Vec<SearchResult> {
+
+    let mut results = SEARCH_INDEX.lock().unwrap().search( query, mode, limit );
+
+    let ids: Vec<String> = results.iter().map( |r| r.id.clone() ).collect();
+    let intervals: HashMap<String, ( u32, u32 )> = picking::triangle_ranges( &ids ).into_iter().map( |i| ( i.id, ( i.start, i.end ) ) ).collect();
+
+    for result in results.iter_mut() {
+
+        if let Some( ( start, end ) ) = intervals.get( &result.id ) {
+            result.start = Some( *start );
+            result.end = Some( *end );
+        }
+
+    }
+
+    results
+
+}
@@ -0,0 +1,141 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+use super::stream;
+
+///// Attribute schema inference: which attributes exist, with which types, and how their values are distributed.
+///// Computed in one pass over "CityObjects", for all CityObjects and per CityObject type.
+
+#[derive(Serialize, Default, Debug)]
+pub struct AttributeStats {
+
+    // Amount of CityObjects that have this attribute
+    pub count: u32,
+    // Amount of values per JSON type ("string", "number", "boolean", "array", "object", "null")
+    pub types: BTreeMap<&'static str, u32>,
+    pub nulls: u32,
+    // Strings that can be read as a number, which the filter expressions compare as numbers
+    pub numeric_strings: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean: Option<f64>,
+    // Most frequent string values with their counts, most frequent first
+    pub top_values: Vec<( String, u32 )>,
+    pub distinct_values: u32,
+
+    #[serde(skip)]
+    sum: f64,
+    #[serde(skip)]
+    numbers: u32,
+    #[serde(skip)]
+    strings: HashMap<String, u32>,
+
+}
+
+#[derive(Serialize, Default, Debug)]
+pub struct AttributeSchema {
+
+    // Amount of CityObjects per type
+    pub object_counts: BTreeMap<String, u32>,
+    // Statistics per attribute over all CityObjects
+    pub attributes: BTreeMap<String, AttributeStats>,
+    // Statistics per CityObject type and per attribute
+    pub by_type: BTreeMap<String, BTreeMap<String, AttributeStats>>,
+
+}
+
+fn json_type( value: &Value ) -> &'static str {
+
+    match value {
+        Value::Null => "null",
+        Value::Bool( _ ) => "boolean",
+        Value::Number( _ ) => "number",
+        Value::String( _ ) => "string",
+        Value::Array( _ ) => "array",
+        Value::Object( _ ) => "object",
+    }
+
+}
+
+impl AttributeStats {
+
+    fn add( &mut self, value: &Value ) {
+
+        self.count += 1;
+        *self.types.entry( json_type( value ) ).or_default() += 1;
+
+        match value {
+            Value::Null => self.nulls += 1,
+            Value::Number( n ) => {
+                if let Some( x ) = n.as_f64() {
+                    self.min = Some( self.min.map_or( x, |m| m.min( x ) ) );
+                    self.max = Some( self.max.map_or( x, |m| m.max( x ) ) );
+                    self.sum += x;
+                    self.numbers += 1;
+                }
+            },
+            Value::String( s ) => {
+                if s.trim().parse::<f64>().is_ok() {
+                    self.numeric_strings += 1;
+                }
+                *self.strings.entry( s.clone() ).or_default() += 1;
+            },
+            _ => {},
+        }
+
+    }
+
+    fn finish( &mut self, top_n: usize ) {
+
+        if self.numbers > 0 {
+            self.mean = Some( self.sum / self.numbers as f64 );
+        }
+
+        let mut values: Vec<( String, u32 )> = self.strings.drain().collect();
+        values.sort_by( |a, b| b.1.cmp( &a.1 ).then( a.0.cmp( &b.0 ) ) );
+
+        self.distinct_values = values.len() as u32;
+        values.truncate( top_n );
+        self.top_values = values;
+
+    }
+
+}
+
+/// Scan the CityObjects and compute the statistics of every attribute, keeping the top_n most frequent string values per attribute
+pub fn attribute_schema( buffer: &[u8], top_n: usize ) -> Result<AttributeSchema, serde_json::Error> {
+
+    let mut schema = AttributeSchema::default();
+
+    stream::for_each_cityobject( buffer, |_, co| {
+
+        let co_type = co.get( "type" ).and_then( |t| t.as_str() ).unwrap_or( "" ).to_string();
+
+        *schema.object_counts.entry( co_type.clone() ).or_default() += 1;
+
+        if let Some( attributes ) = co.get( "attributes" ).and_then( |a| a.as_object() ) {
+
+            let by_type = schema.by_type.entry( co_type ).or_default();
+
+            for ( key, value ) in attributes {
+
+                schema.attributes.entry( key.clone() ).or_default().add( value );
+                by_type.entry( key.clone() ).or_default().add( value );
+
+            }
+
+        }
+
+    } )?;
+
+    for stats in schema.attributes.values_mut().chain( schema.by_type.values_mut().flat_map( |a| a.values_mut() ) ) {
+        stats.finish( top_n );
+    }
+
+    Ok( schema )
+
+}
@@ -0,0 +1,113 @@
+use serde::Deserializer;
+use serde::de::{DeserializeSeed, IgnoredAny, MapAccess, Visitor};
+use std::fmt;
+
+use super::format;
+use super::join;
+use super::edit;
+
+///// Streaming over "CityObjects" with a callback. Works like to_bufferattributes::deserialize_cityobjects(), but the state is passed
+///// with a DeserializeSeed (like in to_bufferattributes::deserialize_vertices()) instead of through globals, so it can be reused by every query.
+
+/// Call f( id, cityobject ) for every CityObject in a CityJSON document, one CityObject at a time. All other members of the document are skipped.
+/// Joined columns (see join::join_table()) and attribute edits (see edit.rs) are included in the attributes.
+pub fn for_each_cityobject<F>( buffer: &[u8], f: F ) -> Result<(), serde_json::Error>
+where
+    F: FnMut( String, serde_json::Value ),
+{
+
+    format::deserialize_seed( buffer, DocumentSeed( f ) )
+
+}
+
+// Visits the top-level CityJSON object and hands "CityObjects" to CityObjectsSeed
+struct DocumentSeed<F>( F );
+
+impl<'de, F> DeserializeSeed<'de> for DocumentSeed<F>
+where
+    F: FnMut( String, serde_json::Value ),
+{
+    type Value = ();
+
+    fn deserialize<D>( self, deserializer: D ) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map( self )
+    }
+}
+
+impl<'de, F> Visitor<'de> for DocumentSeed<F>
+where
+    F: FnMut( String, serde_json::Value ),
+{
+    type Value = ();
+
+    fn expecting( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+        formatter.write_str( "a CityJSON object" )
+    }
+
+    fn visit_map<S>( mut self, mut map: S ) -> Result<(), S::Error>
+    where
+        S: MapAccess<'de>,
+    {
+
+        while let Some( key ) = map.next_key::<String>()? {
+
+            if key == "CityObjects" {
+                map.next_value_seed( CityObjectsSeed( &mut self.0 ) )?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+
+        }
+
+        Ok( () )
+
+    }
+}
+
+struct CityObjectsSeed<'a, F>( &'a mut F );
+
+impl<'de, 'a, F> DeserializeSeed<'de> for CityObjectsSeed<'a, F>
+where
+    F: FnMut( String, serde_json::Value ),
+{
+    type Value = ();
+
+    fn deserialize<D>( self, deserializer: D ) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map( self )
+    }
+}
+
+impl<'de, 'a, F> Visitor<'de> for CityObjectsSeed<'a, F>
+where
+    F: FnMut( String, serde_json::Value ),
+{
+    type Value = ();
+
+    fn expecting( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+        formatter.write_str( "a key/value entry" )
+    }
+
+    fn visit_map<S>( self, mut map: S ) -> Result<(), S::Error>
+    where
+        S: MapAccess<'de>,
+    {
+
+        while let Some( ( key, mut value ) ) = map.next_entry::<String, serde_json::Value>()? {
+
+            join::apply_joined( &key, &mut value );
+            edit::apply_edits( &key, &mut value );
+
+            ( self.0 )( key, value );
+
+        }
+
+        Ok( () )
+
+    }
+}
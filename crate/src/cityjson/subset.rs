@@ -0,0 +1,448 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use serde::Deserializer;
+use serde::de::{DeserializeSeed, MapAccess, Visitor};
+use serde_json::{Map, Value};
+
+use super::edit;
+use super::format;
+use super::hierarchy::Hierarchy;
+use super::stream;
+
+///// Export of a subset of the CityObjects as a standalone CityJSON document. Vertices, geometry templates, materials, textures and
+///// texture coordinates are limited to the ones that are used by the subset and renumbered, the transform is kept as it is,
+///// and the geographicalExtent in the metadata is updated. Attribute edits (see edit.rs) are applied.
+
+// Maps original indices to new, compact indices, in order of first use
+#[derive(Default)]
+struct Renumber {
+
+    map: HashMap<u64, u64>,
+    order: Vec<u64>,
+
+}
+
+impl Renumber {
+
+    fn get( &mut self, old: u64 ) -> u64 {
+
+        if let Some( new ) = self.map.get( &old ) {
+            return *new;
+        }
+
+        let new = self.order.len() as u64;
+        self.map.insert( old, new );
+        self.order.push( old );
+        new
+
+    }
+
+}
+
+#[derive(Default)]
+struct Renumbers {
+
+    vertices: Renumber,
+    templates: Renumber,
+    template_vertices: Renumber,
+    materials: Renumber,
+    textures: Renumber,
+    texture_vertices: Renumber,
+
+}
+
+// Renumber every integer in a (nested) array, e.g. the vertex indices in boundaries
+fn renumber_all( value: &mut Value, renumber: &mut Renumber ) {
+
+    match value {
+        Value::Number( n ) => {
+            if let Some( old ) = n.as_u64() {
+                *value = Value::from( renumber.get( old ) );
+            }
+        },
+        Value::Array( items ) => items.iter_mut().for_each( |v| renumber_all( v, renumber ) ),
+        _ => {},
+    }
+
+}
+
+// Texture values are nested arrays that end in rings [texture index, uv index, uv index, ...] (or [null])
+fn renumber_texture_values( value: &mut Value, textures: &mut Renumber, uvs: &mut Renumber ) {
+
+    if let Value::Array( items ) = value {
+
+        if items.iter().any( |v| v.is_array() ) {
+            items.iter_mut().for_each( |v| renumber_texture_values( v, textures, uvs ) );
+            return;
+        }
+
+        for ( i, item ) in items.iter_mut().enumerate() {
+            if let Some( old ) = item.as_u64() {
+                *item = Value::from( if i == 0 { textures.get( old ) } else { uvs.get( old ) } );
+            }
+        }
+
+    }
+
+}
+
+fn renumber_geometry( geometry: &mut Value, renumbers: &mut Renumbers, template: bool ) {
+
+    let is_instance = geometry.get( "type" ).and_then( |t| t.as_str() ) == Some( "GeometryInstance" );
+
+    if let Some( geometry ) = geometry.as_object_mut() {
+
+        if is_instance {
+            if let Some( t ) = geometry.get_mut( "template" ) {
+                renumber_all( t, &mut renumbers.templates );
+            }
+        }
+
+        if let Some( boundaries ) = geometry.get_mut( "boundaries" ) {
+            renumber_all( boundaries, if template { &mut renumbers.template_vertices } else { &mut renumbers.vertices } );
+        }
+
+        if let Some( themes ) = geometry.get_mut( "material" ).and_then( |m| m.as_object_mut() ) {
+            for theme in themes.values_mut().filter_map( |t| t.as_object_mut() ) {
+                for key in [ "values", "value" ] {
+                    if let Some( values ) = theme.get_mut( key ) {
+                        renumber_all( values, &mut renumbers.materials );
+                    }
+                }
+            }
+        }
+
+        if let Some( themes ) = geometry.get_mut( "texture" ).and_then( |t| t.as_object_mut() ) {
+            for theme in themes.values_mut().filter_map( |t| t.as_object_mut() ) {
+                if let Some( values ) = theme.get_mut( "values" ) {
+                    renumber_texture_values( values, &mut renumbers.textures, &mut renumbers.texture_vertices );
+                }
+            }
+        }
+
+    }
+
+}
+
+// Pick the elements of an array in the new order
+fn pick( array: Option<&Value>, renumber: &Renumber, name: &str ) -> Result<Vec<Value>, String> {
+
+    let array = array.and_then( |a| a.as_array() );
+
+    renumber.order.iter().map( |old| {
+        array.and_then( |a| a.get( *old as usize ) ).cloned().ok_or_else( || format!( "{} {} does not exist", name, old ) )
+    } ).collect()
+
+}
+
+// CityObjects by ID, in the order of the document
+type CityObjects = Vec<( String, Value )>;
+
+// Deserializes the CityJSON document, but keeps only the wanted CityObjects. These are returned separately, in their original order.
+struct SubsetDocument<'a>( &'a HashSet<String> );
+
+impl<'de, 'a> DeserializeSeed<'de> for SubsetDocument<'a> {
+
+    type Value = ( Map<String, Value>, CityObjects );
+
+    fn deserialize<D>( self, deserializer: D ) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map( self )
+    }
+
+}
+
+impl<'de, 'a> Visitor<'de> for SubsetDocument<'a> {
+
+    type Value = ( Map<String, Value>, CityObjects );
+
+    fn expecting( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+        formatter.write_str( "a CityJSON object" )
+    }
+
+    fn visit_map<S>( self, mut map: S ) -> Result<Self::Value, S::Error>
+    where
+        S: MapAccess<'de>,
+    {
+
+        let mut doc = Map::new();
+        let mut city_objects = None;
+
+        while let Some( key ) = map.next_key::<String>()? {
+
+            if key == "CityObjects" {
+                city_objects = Some( map.next_value_seed( SubsetCityObjects( self.0 ) )? );
+            } else {
+                doc.insert( key, map.next_value::<Value>()? );
+            }
+
+        }
+
+        let city_objects = city_objects.ok_or_else( || serde::de::Error::missing_field( "CityObjects" ) )?;
+
+        Ok( ( doc, city_objects ) )
+
+    }
+
+}
+
+struct SubsetCityObjects<'a>( &'a HashSet<String> );
+
+impl<'de, 'a> DeserializeSeed<'de> for SubsetCityObjects<'a> {
+
+    type Value = CityObjects;
+
+    fn deserialize<D>( self, deserializer: D ) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map( self )
+    }
+
+}
+
+impl<'de, 'a> Visitor<'de> for SubsetCityObjects<'a> {
+
+    type Value = CityObjects;
+
+    fn expecting( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
+        formatter.write_str( "a key/value entry" )
+    }
+
+    fn visit_map<S>( self, mut map: S ) -> Result<Self::Value, S::Error>
+    where
+        S: MapAccess<'de>,
+    {
+
+        let mut city_objects = Vec::new();
+
+        while let Some( key ) = map.next_key::<String>()? {
+
+            if self.0.contains( &key ) {
+                city_objects.push( ( key, map.next_value::<Value>()? ) );
+            } else {
+                map.next_value::<serde::de::IgnoredAny>()?;
+            }
+
+        }
+
+        Ok( city_objects )
+
+    }
+
+}
+
+// Write members as a JSON object in the given order, which a serde_json Map would sort
+fn write_object<V: fmt::Display>( members: &[( String, V )] ) -> String {
+
+    let members: Vec<String> = members.iter().map( |( key, value )| format!( "{}:{}", Value::String( key.clone() ), value ) ).collect();
+
+    format!( "{{{}}}", members.join( "," ) )
+
+}
+
+/// Compute the [minx, miny, minz, maxx, maxy, maxz] extent of vertices, in real coordinates
+pub fn extent( vertices: &[Value], transform: Option<&Value> ) -> Option<[f64; 6]> {
+
+    let scale = transform.and_then( |t| t.get( "scale" ) );
+    let translate = transform.and_then( |t| t.get( "translate" ) );
+    let component = | v: Option<&Value>, i: usize, default: f64 | v.and_then( |v| v.get( i ) ).and_then( |v| v.as_f64() ).unwrap_or( default );
+
+    let mut out: Option<[f64; 6]> = None;
+
+    for vertex in vertices {
+
+        let mut p = [ 0.0; 3 ];
+
+        for ( i, c ) in p.iter_mut().enumerate() {
+            *c = component( Some( vertex ), i, 0.0 ) * component( scale, i, 1.0 ) + component( translate, i, 0.0 );
+        }
+
+        let e = out.get_or_insert( [ p[ 0 ], p[ 1 ], p[ 2 ], p[ 0 ], p[ 1 ], p[ 2 ] ] );
+
+        for i in 0..3 {
+            e[ i ] = e[ i ].min( p[ i ] );
+            e[ i + 3 ] = e[ i + 3 ].max( p[ i ] );
+        }
+
+    }
+
+    out
+
+}
+
+/// Expand a selection of CityObject IDs with their descendants and/or ancestors
+pub fn expand_selection( buffer: &[u8], ids: &[String], include_children: bool, include_parents: bool ) -> Result<HashSet<String>, serde_json::Error> {
+
+    let mut selection: HashSet<String> = ids.iter().cloned().collect();
+
+    if !include_children && !include_parents {
+        return Ok( selection );
+    }
+
+    let mut hierarchy = Hierarchy::default();
+
+    stream::for_each_cityobject( buffer, |id, co| hierarchy.add( &id, &co ) )?;
+    hierarchy.finalize();
+
+    for id in ids {
+
+        if include_children {
+            selection.extend( hierarchy.descendants( id ) );
+        }
+
+        if include_parents {
+            selection.extend( hierarchy.parent_chain( id ) );
+        }
+
+    }
+
+    Ok( selection )
+
+}
+
+/// Write the CityObjects with the given IDs (optionally with their children and parents) as a new CityJSON document
+pub fn subset( buffer: &[u8], ids: &[String], include_children: bool, include_parents: bool ) -> Result<Vec<u8>, String> {
+
+    let parse_error = | e: serde_json::Error | format!( "Error parsing CityJSON buffer: {}", e );
+
+    let selection = expand_selection( buffer, ids, include_children, include_parents ).map_err( parse_error )?;
+
+    let ( mut doc, mut city_objects ) = format::deserialize_seed( buffer, SubsetDocument( &selection ) ).map_err( parse_error )?;
+
+    let mut renumbers = Renumbers::default();
+
+    for ( id, co ) in city_objects.iter_mut() {
+
+        edit::apply_edits( id, co );
+
+        // Drop references to CityObjects outside of the subset
+        if let Some( co ) = co.as_object_mut() {
+
+            for member in [ "parents", "children", "members" ] {
+
+                if let Some( references ) = co.get_mut( member ).and_then( |r| r.as_array_mut() ) {
+
+                    references.retain( |r| r.as_str().map( |r| selection.contains( r ) ).unwrap_or( false ) );
+
+                    if references.is_empty() {
+                        co.remove( member );
+                    }
+
+                }
+
+            }
+
+        }
+
+        if let Some( geometries ) = co.get_mut( "geometry" ).and_then( |g| g.as_array_mut() ) {
+            for geometry in geometries {
+                renumber_geometry( geometry, &mut renumbers, false );
+            }
+        }
+
+    }
+
+    // Geometry templates used by the subset. These can refer to materials and textures too.
+    let mut templates = doc.remove( "geometry-templates" );
+    let mut new_templates = Vec::new();
+
+    if let Some( templates ) = templates.as_mut() {
+
+        let mut i = 0;
+
+        // Templates don't refer to other templates, so renumbers.templates.order doesn't grow while doing this
+        while i < renumbers.templates.order.len() {
+
+            let old = renumbers.templates.order[ i ] as usize;
+            let mut template = templates.get( "templates" ).and_then( |t| t.get( old ) ).cloned()
+                                        .ok_or_else( || format!( "Geometry template {} does not exist", old ) )?;
+
+            renumber_geometry( &mut template, &mut renumbers, true );
+            new_templates.push( template );
+            i += 1;
+
+        }
+
+    }
+
+    let vertices = pick( doc.get( "vertices" ), &renumbers.vertices, "Vertex" )?;
+
+    // Assemble the new document, in the usual order of CityJSON members, with the values as JSON text
+    let mut out: Vec<( String, String )> = Vec::new();
+
+    out.push( ( "type".to_string(), doc.remove( "type" ).unwrap_or_else( || Value::from( "CityJSON" ) ).to_string() ) );
+
+    if let Some( version ) = doc.remove( "version" ) {
+        out.push( ( "version".to_string(), version.to_string() ) );
+    }
+
+    let transform = doc.remove( "transform" );
+
+    if let Some( transform ) = &transform {
+        out.push( ( "transform".to_string(), transform.to_string() ) );
+    }
+
+    let mut metadata = doc.remove( "metadata" ).unwrap_or_else( || Value::Object( Map::new() ) );
+
+    // The extent of the original document doesn't apply to the subset, even if the subset has no vertices at all
+    if let Some( metadata ) = metadata.as_object_mut() {
+        match extent( &vertices, transform.as_ref() ) {
+            Some( e ) => { metadata.insert( "geographicalExtent".to_string(), Value::from( e.to_vec() ) ); },
+            None => { metadata.remove( "geographicalExtent" ); },
+        }
+    }
+
+    out.push( ( "metadata".to_string(), metadata.to_string() ) );
+    out.push( ( "CityObjects".to_string(), write_object( &city_objects ) ) );
+    out.push( ( "vertices".to_string(), Value::from( vertices ).to_string() ) );
+    doc.remove( "vertices" );
+
+    if let Some( mut appearance ) = doc.remove( "appearance" ) {
+
+        let materials = pick( appearance.get( "materials" ), &renumbers.materials, "Material" )?;
+        let textures = pick( appearance.get( "textures" ), &renumbers.textures, "Texture" )?;
+        let texture_vertices = pick( appearance.get( "vertices-texture" ), &renumbers.texture_vertices, "Texture vertex" )?;
+
+        if let Some( appearance ) = appearance.as_object_mut() {
+
+            for ( key, values ) in [ ( "materials", materials ), ( "textures", textures ), ( "vertices-texture", texture_vertices ) ] {
+                if values.is_empty() {
+                    appearance.remove( key );
+                } else {
+                    appearance.insert( key.to_string(), Value::from( values ) );
+                }
+            }
+
+            if !appearance.is_empty() {
+                out.push( ( "appearance".to_string(), Value::Object( appearance.clone() ).to_string() ) );
+            }
+
+        }
+
+    }
+
+    if let Some( templates ) = templates {
+
+        if !new_templates.is_empty() {
+
+            let template_vertices = pick( templates.get( "vertices-templates" ), &renumbers.template_vertices, "Template vertex" )?;
+
+            let mut geometry_templates = Map::new();
+            geometry_templates.insert( "templates".to_string(), Value::from( new_templates ) );
+            geometry_templates.insert( "vertices-templates".to_string(), Value::from( template_vertices ) );
+
+            out.push( ( "geometry-templates".to_string(), Value::Object( geometry_templates ).to_string() ) );
+
+        }
+
+    }
+
+    // Everything else (e.g. "extensions") is copied as it is
+    out.extend( doc.into_iter().map( |( key, value )| ( key, value.to_string() ) ) );
+
+    Ok( write_object( &out ).into_bytes() )
+
+}
@@ -0,0 +1,394 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::filter;
+use super::picking;
+use super::stream;
+use super::to_bufferattributes;
+
+///// Thematic coloring: CityObjects are divided into classes by the value of one attribute, and every class gets a color from a palette.
+///// CityObjects for which the attribute is missing or null (or not a number, for numeric classifications) end up in a "No data" class.
+
+/// How attribute values are divided into classes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Classification {
+
+    // Classes of equal width between the minimum and the maximum value
+    EqualInterval,
+    // Classes with (about) the same amount of CityObjects
+    Quantile,
+    // One class per distinct value, most frequent first
+    Categorical,
+
+}
+
+// Color of the "No data" class
+pub const NO_DATA_COLOR: [u8; 3] = [ 204, 204, 204 ];
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct LegendEntry {
+
+    pub label: String,
+    pub color: String,
+    // Bounds of numeric classes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    // Value of categorical classes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    // Amount of CityObjects in the class
+    pub count: u32,
+
+}
+
+// Attribute value per CityObject ID
+type Values = Vec<( String, Option<Value> )>;
+// Triangles (flat vertex indices) per CityObject ID
+type Triangles = Vec<( String, Vec<u32> )>;
+
+pub struct Thematic {
+
+    pub legend: Vec<LegendEntry>,
+    // Index in the legend per CityObject ID
+    pub classes: HashMap<String, usize>,
+    colors: Vec<[u8; 3]>,
+
+}
+
+#[derive(Serialize, Debug)]
+pub struct ThematicResult {
+
+    pub legend: Vec<LegendEntry>,
+    // [start, count, legend index] of consecutive triangles with the same class, in the same units as the groups of parse_cityobjects(), so they can be used with BufferGeometry.addGroup()
+    pub groups: Vec<[u32; 3]>,
+    pub classes: HashMap<String, usize>,
+
+}
+
+#[derive(Debug)]
+pub struct VertexColors {
+
+    pub legend: Vec<LegendEntry>,
+    // RGB per vertex
+    pub colors: Vec<f32>,
+
+}
+
+/// Parse "#rrggbb" (or "rrggbb") into RGB
+pub fn parse_color( color: &str ) -> Result<[u8; 3], String> {
+
+    let hex = color.trim_start_matches( '#' );
+
+    if hex.len() != 6 || !hex.chars().all( |c| c.is_ascii_hexdigit() ) {
+        return Err( format!( "Invalid color '{}', expected #rrggbb", color ) );
+    }
+
+    let channel = | i: usize | u8::from_str_radix( &hex[ i..i + 2 ], 16 ).unwrap();
+
+    Ok( [ channel( 0 ), channel( 2 ), channel( 4 ) ] )
+
+}
+
+pub fn format_color( color: [u8; 3] ) -> String {
+
+    format!( "#{:02x}{:02x}{:02x}", color[ 0 ], color[ 1 ], color[ 2 ] )
+
+}
+
+// Interpolate n colors along a palette that is used as a color ramp
+fn ramp( palette: &[[u8; 3]], n: usize ) -> Vec<[u8; 3]> {
+
+    if palette.len() == 1 || n == 1 {
+        return vec![ palette[ 0 ]; n ];
+    }
+
+    ( 0..n ).map( |k| {
+
+        let t = k as f64 / ( n - 1 ) as f64 * ( palette.len() - 1 ) as f64;
+        let i = ( t.floor() as usize ).min( palette.len() - 2 );
+        let f = t - i as f64;
+
+        let mut color = [ 0; 3 ];
+        for c in 0..3 {
+            color[ c ] = ( palette[ i ][ c ] as f64 * ( 1.0 - f ) + palette[ i + 1 ][ c ] as f64 * f ).round() as u8;
+        }
+        color
+
+    } ).collect()
+
+}
+
+fn format_number( x: f64 ) -> String {
+
+    if x.fract() == 0.0 && x.abs() < 1e15 {
+        return format!( "{}", x as i64 );
+    }
+
+    let s = format!( "{:.2}", x );
+    s.trim_end_matches( '0' ).trim_end_matches( '.' ).to_string()
+
+}
+
+// String representation of a value for categorical classification
+fn category( value: &Value ) -> Option<String> {
+
+    match value {
+        Value::Null => None,
+        Value::String( s ) => Some( s.clone() ),
+        other => Some( other.to_string() ),
+    }
+
+}
+
+// Class bounds (classes + 1 values) for numeric classifications. Duplicate bounds are removed, so that no class is empty by construction.
+fn breaks( values: &mut [f64], classification: Classification, classes: usize ) -> Vec<f64> {
+
+    values.sort_by( |a, b| a.partial_cmp( b ).unwrap() );
+
+    let min = values[ 0 ];
+    let max = values[ values.len() - 1 ];
+
+    let mut bounds: Vec<f64> = match classification {
+        Classification::Quantile => ( 0..classes ).map( |k| values[ k * values.len() / classes ] ).chain( std::iter::once( max ) ).collect(),
+        _ => ( 0..=classes ).map( |k| min + ( max - min ) * k as f64 / classes as f64 ).collect(),
+    };
+
+    bounds.dedup();
+
+    if bounds.len() == 1 {
+        bounds.push( max );
+    }
+
+    bounds
+
+}
+
+/// Divide CityObjects into classes by their attribute values. classes is the amount of numeric classes, or the maximum amount of
+/// categories (0 for all), with the remaining values in an "Other" class. Numeric palettes are interpolated, categorical palettes are repeated.
+pub fn classify( values: &[( String, Option<Value> )], classification: Classification, classes: usize, palette: &[String] ) -> Result<Thematic, String> {
+
+    if palette.is_empty() {
+        return Err( "The palette needs at least one color".to_string() );
+    }
+
+    if classes == 0 && classification != Classification::Categorical {
+        return Err( "At least one class is needed".to_string() );
+    }
+
+    let palette = palette.iter().map( |c| parse_color( c ) ).collect::<Result<Vec<_>, _>>()?;
+
+    let mut legend = Vec::new();
+    let mut colors = Vec::new();
+    let mut object_classes = HashMap::new();
+    let mut no_data = Vec::new();
+
+    if classification == Classification::Categorical {
+
+        let mut counts: HashMap<String, u32> = HashMap::new();
+
+        for ( _, value ) in values {
+            if let Some( c ) = value.as_ref().and_then( category ) {
+                *counts.entry( c ).or_default() += 1;
+            }
+        }
+
+        // Most frequent first, then alphabetically for a stable order
+        let mut categories: Vec<( String, u32 )> = counts.into_iter().collect();
+        categories.sort_by( |a, b| b.1.cmp( &a.1 ).then( a.0.cmp( &b.0 ) ) );
+
+        let limit = if classes == 0 { categories.len() } else { classes.min( categories.len() ) };
+        let mut index = HashMap::new();
+
+        for ( i, ( value, count ) ) in categories.iter().take( limit ).enumerate() {
+
+            index.insert( value.clone(), i );
+            colors.push( palette[ i % palette.len() ] );
+            legend.push( LegendEntry { label: value.clone(), color: String::new(), min: None, max: None, value: Some( value.clone() ), count: *count } );
+
+        }
+
+        let other: u32 = categories.iter().skip( limit ).map( |( _, count )| count ).sum();
+
+        if other > 0 {
+            colors.push( palette[ limit % palette.len() ] );
+            legend.push( LegendEntry { label: "Other".to_string(), color: String::new(), min: None, max: None, value: None, count: other } );
+        }
+
+        for ( id, value ) in values {
+
+            match value.as_ref().and_then( category ) {
+                Some( c ) => { object_classes.insert( id.clone(), *index.get( &c ).unwrap_or( &limit ) ); },
+                None => no_data.push( id.clone() ),
+            }
+
+        }
+
+    } else {
+
+        let mut numbers: Vec<f64> = values.iter().filter_map( |( _, v )| v.as_ref().and_then( filter::as_number ) ).filter( |x| x.is_finite() ).collect();
+
+        if !numbers.is_empty() {
+
+            let bounds = breaks( &mut numbers, classification, classes );
+            let n = bounds.len() - 1;
+
+            colors = ramp( &palette, n );
+
+            for k in 0..n {
+                legend.push( LegendEntry { label: format!( "{} – {}", format_number( bounds[ k ] ), format_number( bounds[ k + 1 ] ) ),
+                                           color: String::new(), min: Some( bounds[ k ] ), max: Some( bounds[ k + 1 ] ), value: None, count: 0 } );
+            }
+
+            for ( id, value ) in values {
+
+                match value.as_ref().and_then( filter::as_number ).filter( |x| x.is_finite() ) {
+                    Some( x ) => {
+                        // Values on a bound belong to the class above it, the maximum to the last class
+                        let class = bounds[ 1..n ].partition_point( |&b| b <= x );
+                        legend[ class ].count += 1;
+                        object_classes.insert( id.clone(), class );
+                    },
+                    None => no_data.push( id.clone() ),
+                }
+
+            }
+
+        } else {
+
+            no_data = values.iter().map( |( id, _ )| id.clone() ).collect();
+
+        }
+
+    }
+
+    if !no_data.is_empty() {
+
+        let class = legend.len();
+
+        colors.push( NO_DATA_COLOR );
+        legend.push( LegendEntry { label: "No data".to_string(), color: String::new(), min: None, max: None, value: None, count: no_data.len() as u32 } );
+
+        for id in no_data {
+            object_classes.insert( id, class );
+        }
+
+    }
+
+    for ( entry, color ) in legend.iter_mut().zip( &colors ) {
+        entry.color = format_color( *color );
+    }
+
+    Ok( Thematic { legend, classes: object_classes, colors } )
+
+}
+
+impl Thematic {
+
+    /// [start, count, legend index] of consecutive runs of triangles with the same class, using the picking index of the last parsed file
+    pub fn groups( &self ) -> Vec<[u32; 3]> {
+
+        let mut groups: Vec<[u32; 3]> = Vec::new();
+
+        for interval in picking::all_triangle_ranges() {
+
+            let class = match self.classes.get( &interval.id ) {
+                Some( class ) => *class as u32,
+                None => continue,
+            };
+
+            // Same units as the groups of parse_cityobjects(): indices in the triangles array
+            let start = interval.start * 3;
+            let count = ( interval.end - interval.start + 1 ) * 3;
+
+            match groups.last_mut() {
+                Some( last ) if last[ 2 ] == class && last[ 0 ] + last[ 1 ] == start => last[ 1 ] += count,
+                _ => groups.push( [ start, count, class ] ),
+            }
+
+        }
+
+        groups
+
+    }
+
+    /// RGB colors (0-1) per vertex. Vertices that are shared by CityObjects of different classes get the color of the last one.
+    pub fn vertex_colors( &self, triangles: &[( String, Vec<u32> )], vertex_count: usize ) -> Vec<f32> {
+
+        let no_data = NO_DATA_COLOR.iter().map( |c| *c as f32 / 255.0 ).collect::<Vec<f32>>();
+        let mut out: Vec<f32> = no_data.iter().cycle().take( vertex_count * 3 ).cloned().collect();
+
+        for ( id, vertices ) in triangles {
+
+            let color = match self.classes.get( id ) {
+                Some( class ) => self.colors[ *class ],
+                None => continue,
+            };
+
+            for v in vertices.iter().map( |v| *v as usize ).filter( |v| *v < vertex_count ) {
+                for c in 0..3 {
+                    out[ v * 3 + c ] = color[ c ] as f32 / 255.0;
+                }
+            }
+
+        }
+
+        out
+
+    }
+
+}
+
+// Stream over the CityObjects and take the value of the attribute, and optionally the triangles, of each of them
+fn read_values( buffer: &[u8], attribute: &str, with_triangles: bool ) -> Result<( Values, Triangles ), String> {
+
+    let path = filter::parse_field( attribute ).map_err( |e| format!( "Invalid attribute: {}", e ) )?;
+
+    let mut values = Vec::new();
+    let mut triangles = Vec::new();
+
+    stream::for_each_cityobject( buffer, |id, co| {
+
+        let id_value = Value::String( id.clone() );
+        let value = filter::resolve( &path, &id_value, &co ).cloned();
+
+        if with_triangles {
+            triangles.push( ( id.clone(), to_bufferattributes::cityobject_triangles( &id, &co ) ) );
+        }
+
+        values.push( ( id, value ) );
+
+    } ).map_err( |e| format!( "Error parsing CityJSON buffer: {}", e ) )?;
+
+    Ok( ( values, triangles ) )
+
+}
+
+/// Classify the CityObjects by an attribute. palette is a list of "#rrggbb" colors, classes the amount of classes
+/// (for Categorical: the maximum amount of categories, 0 for all).
+pub fn thematic_classes( buffer: &[u8], attribute: &str, classification: Classification, classes: usize, palette: &[String] ) -> Result<ThematicResult, String> {
+
+    log!("Classifying CityObjects by {}...", attribute);
+
+    let ( values, _ ) = read_values( buffer, attribute, false )?;
+    let thematic = classify( &values, classification, classes, palette )?;
+    let groups = thematic.groups();
+
+    Ok( ThematicResult { legend: thematic.legend, groups, classes: thematic.classes } )
+
+}
+
+/// Same classification as thematic_classes(), with a color per vertex instead of groups. vertex_count is the amount of vertices.
+pub fn thematic_vertex_colors( buffer: &[u8], attribute: &str, classification: Classification, classes: usize, palette: &[String], vertex_count: usize ) -> Result<VertexColors, String> {
+
+    log!("Computing vertex colors by {}...", attribute);
+
+    let ( values, triangles ) = read_values( buffer, attribute, true )?;
+    let thematic = classify( &values, classification, classes, palette )?;
+    let colors = thematic.vertex_colors( &triangles, vertex_count );
+
+    Ok( VertexColors { legend: thematic.legend, colors } )
+
+}
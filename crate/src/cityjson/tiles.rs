@@ -0,0 +1,257 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::gltf;
+use super::mesh::Mesh;
+
+///// 3D Tiles (1.1) generation. CityObjects are partitioned with a quadtree or octree over the centers of their bounding boxes, until a
+///// tile has at most max_features CityObjects. Leaf tiles get GLB content (see gltf.rs, with per-feature IDs and attributes), the tiles
+///// above them only have a bounding volume, so that a viewer only loads the leaves that are visible.
+///// 3D Tiles are in ECEF (EPSG:4978). Tiles and their content are relative to the center of the model, and the root transform places
+///// that center on the globe: a translation for files in ECEF, and otherwise a local east-north-up frame at a given Georeference. The
+///// latter treats projected coordinates as meters east, north and up, which is a good approximation at the scale of a city.
+
+/// How tiles are subdivided
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TileSubdivision {
+
+    // Split in x and y
+    Quadtree,
+    // Split in x, y and z
+    Octree,
+
+}
+
+/// Where the model is on the globe: the WGS84 longitude and latitude (in degrees) and ellipsoidal height (in meters) of the center of
+/// the model, i.e. of the bounding box of its triangles. In JS { longitude: 4.37, latitude: 52.01, height: 45 } (height optional).
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Georeference {
+
+    pub longitude: f64,
+    pub latitude: f64,
+    #[serde(default)]
+    pub height: f64,
+
+}
+
+// Don't subdivide further than this, e.g. when many CityObjects have the same center
+const MAX_DEPTH: u32 = 16;
+
+// WGS84 ellipsoid
+const SEMI_MAJOR_AXIS: f64 = 6378137.0;
+const FLATTENING: f64 = 1.0 / 298.257223563;
+
+struct TileBuilder<'a> {
+
+    mesh: &'a Mesh,
+    ranges: Vec<( usize, usize )>,
+    bounds: Vec<[[f64; 3]; 2]>,
+    subdivision: TileSubdivision,
+    max_features: usize,
+    // Center of the model, which is subtracted from the content
+    origin: [f64; 3],
+    // ( file name, content )
+    files: Vec<( String, Vec<u8> )>,
+
+}
+
+fn union( bounds: &[[[f64; 3]; 2]], features: &[u32] ) -> [[f64; 3]; 2] {
+
+    if features.is_empty() {
+        return [ [ 0.0; 3 ]; 2 ];
+    }
+
+    let mut out = [ [ f64::INFINITY; 3 ], [ f64::NEG_INFINITY; 3 ] ];
+
+    for f in features {
+
+        let b = bounds[ *f as usize ];
+
+        for c in 0..3 {
+            out[ 0 ][ c ] = out[ 0 ][ c ].min( b[ 0 ][ c ] );
+            out[ 1 ][ c ] = out[ 1 ][ c ].max( b[ 1 ][ c ] );
+        }
+
+    }
+
+    out
+
+}
+
+// 3D Tiles box: center and three half-axes
+fn bounding_box( b: [[f64; 3]; 2] ) -> Value {
+
+    let center = | c: usize | ( b[ 0 ][ c ] + b[ 1 ][ c ] ) / 2.0;
+    let half = | c: usize | ( b[ 1 ][ c ] - b[ 0 ][ c ] ) / 2.0;
+
+    json!( { "box": [ center( 0 ), center( 1 ), center( 2 ), half( 0 ), 0.0, 0.0, 0.0, half( 1 ), 0.0, 0.0, 0.0, half( 2 ) ] } )
+
+}
+
+fn diagonal( b: [[f64; 3]; 2] ) -> f64 {
+
+    ( 0..3 ).map( |c| ( b[ 1 ][ c ] - b[ 0 ][ c ] ).powi( 2 ) ).sum::<f64>().sqrt()
+
+}
+
+impl<'a> TileBuilder<'a> {
+
+    // Build the tile with the given features (ascending) and its children. name is the address of the tile in the tree.
+    fn tile( &mut self, features: Vec<u32>, name: String, depth: u32 ) -> Result<Value, String> {
+
+        let b = union( &self.bounds, &features );
+
+        if features.len() <= self.max_features || depth >= MAX_DEPTH {
+            return self.leaf( &features, name, b );
+        }
+
+        // Split at the center of the tile
+        let axes = if self.subdivision == TileSubdivision::Octree { 3 } else { 2 };
+        let mid: Vec<f64> = ( 0..axes ).map( |c| ( b[ 0 ][ c ] + b[ 1 ][ c ] ) / 2.0 ).collect();
+        let mut children: Vec<Vec<u32>> = vec![ Vec::new(); 1 << axes ];
+
+        for f in features.iter().cloned() {
+
+            let fb = self.bounds[ f as usize ];
+            let child = ( 0..axes ).filter( |c| ( fb[ 0 ][ *c ] + fb[ 1 ][ *c ] ) / 2.0 >= mid[ *c ] ).map( |c| 1 << c ).sum::<usize>();
+
+            children[ child ].push( f );
+
+        }
+
+        // All CityObjects in one child: splitting doesn't help
+        if children.iter().any( |c| c.len() == features.len() ) {
+            return self.leaf( &features, name, b );
+        }
+
+        let children: Vec<Value> = children.into_iter().enumerate().filter( |( _, c )| !c.is_empty() )
+                                           .map( |( i, c )| self.tile( c, format!( "{}{}", name, i ), depth + 1 ) ).collect::<Result<_, _>>()?;
+
+        Ok( json!( {
+            "boundingVolume": bounding_box( b ),
+            "geometricError": diagonal( b ),
+            "refine": "ADD",
+            "children": children,
+        } ) )
+
+    }
+
+    fn leaf( &mut self, features: &[u32], name: String, b: [[f64; 3]; 2] ) -> Result<Value, String> {
+
+        let mut mesh = self.mesh.select( features, &self.ranges );
+
+        for v in mesh.vertices.iter_mut() {
+            v.iter_mut().zip( &self.origin ).for_each( |( v, o )| *v -= o );
+        }
+
+        mesh.offset.iter_mut().zip( &self.origin ).for_each( |( v, o )| *v += o );
+
+        let uri = format!( "tiles/{}.glb", name );
+        let content = gltf::write_glb( &mesh )?;
+
+        self.files.push( ( uri.clone(), content ) );
+
+        Ok( json!( {
+            "boundingVolume": bounding_box( b ),
+            "geometricError": 0.0,
+            "refine": "ADD",
+            "content": { "uri": uri },
+        } ) )
+
+    }
+
+}
+
+// Whether a reference system is ECEF, e.g. "urn:ogc:def:crs:EPSG::4978" or "https://www.opengis.net/def/crs/EPSG/0/4978"
+fn is_ecef( reference_system: &str ) -> bool {
+
+    reference_system.to_uppercase().contains( "EPSG" ) && reference_system.rsplit( [ ':', '/' ] ).next() == Some( "4978" )
+
+}
+
+/// ECEF coordinates of a geographic position
+pub fn ecef( georeference: &Georeference ) -> [f64; 3] {
+
+    let ( lon, lat ) = ( georeference.longitude.to_radians(), georeference.latitude.to_radians() );
+    let e2 = FLATTENING * ( 2.0 - FLATTENING );
+    // Radius of curvature in the prime vertical
+    let n = SEMI_MAJOR_AXIS / ( 1.0 - e2 * lat.sin().powi( 2 ) ).sqrt();
+
+    [ ( n + georeference.height ) * lat.cos() * lon.cos(),
+      ( n + georeference.height ) * lat.cos() * lon.sin(),
+      ( n * ( 1.0 - e2 ) + georeference.height ) * lat.sin() ]
+
+}
+
+// Column-major transform of the root tile, from coordinates relative to the model center to ECEF
+fn root_transform( mesh: &Mesh, center: [f64; 3], georeference: Option<&Georeference> ) -> Result<[f64; 16], String> {
+
+    if mesh.reference_system.as_deref().map( is_ecef ).unwrap_or( false ) {
+
+        let t: Vec<f64> = ( 0..3 ).map( |c| center[ c ] + mesh.offset[ c ] ).collect();
+
+        return Ok( [ 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, t[ 0 ], t[ 1 ], t[ 2 ], 1.0 ] );
+
+    }
+
+    let georeference = georeference.ok_or_else( || format!(
+        "3D Tiles are in ECEF (EPSG:4978), but the model is in {}. Give the longitude, latitude and height of its center to place it on the globe.",
+        mesh.reference_system.as_deref().unwrap_or( "an unknown reference system" ) ) )?;
+
+    let ( lon, lat ) = ( georeference.longitude.to_radians(), georeference.latitude.to_radians() );
+    let t = ecef( georeference );
+
+    // East, north and up axes
+    Ok( [ -lon.sin(), lon.cos(), 0.0, 0.0,
+          -lat.sin() * lon.cos(), -lat.sin() * lon.sin(), lat.cos(), 0.0,
+          lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin(), 0.0,
+          t[ 0 ], t[ 1 ], t[ 2 ], 1.0 ] )
+
+}
+
+/// Generate a tileset of a mesh. Returns the files as ( path, content ), the first one being tileset.json. A georeference is needed
+/// unless the mesh is in ECEF.
+pub fn write_tileset( mesh: &Mesh, subdivision: TileSubdivision, max_features: usize, georeference: Option<&Georeference> ) -> Result<Vec<( String, Vec<u8> )>, String> {
+
+    if mesh.triangles.is_empty() {
+        return Err( "There are no triangles to export".to_string() );
+    }
+
+    let origin = mesh.center();
+    let transform = root_transform( mesh, origin, georeference )?;
+
+    let bounds = mesh.feature_bounds().into_iter().map( |mut b| {
+        for c in 0..3 {
+            b[ 0 ][ c ] -= origin[ c ];
+            b[ 1 ][ c ] -= origin[ c ];
+        }
+        b
+    } ).collect();
+
+    let mut builder = TileBuilder {
+        mesh,
+        ranges: mesh.feature_ranges(),
+        bounds,
+        subdivision,
+        max_features: max_features.max( 1 ),
+        origin,
+        files: Vec::new(),
+    };
+
+    let features: Vec<u32> = ( 0..mesh.features.len() as u32 ).collect();
+    let mut root = builder.tile( features, "r".to_string(), 0 )?;
+
+    root[ "transform" ] = json!( transform );
+
+    let tileset = json!( {
+        "asset": { "version": "1.1", "generator": "rust-parcel CityJSON export" },
+        "geometricError": root[ "geometricError" ].as_f64().unwrap_or( 0.0 ).max( 1.0 ),
+        "root": root,
+    } );
+
+    let mut files = vec![ ( "tileset.json".to_string(), serde_json::to_vec_pretty( &tileset ).unwrap() ) ];
+    files.append( &mut builder.files );
+
+    Ok( files )
+
+}
@@ -1,15 +1,19 @@
-use phf::{phf_map, phf_set};
-use wasm_bindgen::prelude::*;
 use serde::{Serialize, Deserialize, Deserializer};
 use serde::de::{self, Visitor, MapAccess, DeserializeSeed, SeqAccess};
-use serde_json::{Value, json};
+use serde_json::value::RawValue;
 use std::fmt;
 use std::marker::PhantomData;
 use lazy_static::lazy_static;
 use std::sync::Mutex;
 use std::ops::{Index, IndexMut};
 use std::collections::HashMap;
-use super::{WasmMemBuffer};
+use super::picking::{self, PickingIndex};
+use super::hierarchy::{Hierarchy, HIERARCHY};
+use super::search::{self, SearchIndex};
+use super::progress::{self, CancelToken};
+use super::format::{self, Format};
+use super::join;
+use super::edit;
 
 // Globals for keeping IDs and triangle intervals for these IDs in WASM memory
 lazy_static! {
@@ -22,8 +26,11 @@ lazy_static! {
 
 static mut TRIANGULATED: bool = false;
 
-#[wasm_bindgen]
-pub fn parse_cityobjects(buf: &WasmMemBuffer) -> wasm_bindgen::JsValue {
+// CityObject types that are parsed, in the order of the groups
+pub const CO_TYPES: [&str; 21] = ["Building", "BuildingPart", "BuildingInstallation", "Bridge", "BridgePart", "BridgeInstallation", "BridgeConstructionElement", "CityObjectGroup", "CityFurniture", "GenericCityObject", "LandUse", "PlantCover", "Railway", "Road", "SolitaryVegetationObject", "TINRelief", "TransportSquare", "Tunnel", "TunnelPart", "TunnelInstallation", "WaterBody"];
+
+/// Parse the CityObjects into triangles and groups, and store the IDs and the picking index globally (what parse_cityobjects() returns)
+pub fn triangulate( buffer: &[u8], picking: PickingIndex ) -> Result<ThreeGroups, serde_json::Error> {
 
     log!("Rust: Parsing CityObjects...");
 
@@ -31,32 +38,48 @@ pub fn parse_cityobjects(buf: &WasmMemBuffer) -> wasm_bindgen::JsValue {
         TRIANGULATED = true;
     }
 
+    // Store the requested picking index storage globally, so that the deserializer can read it
+    let previous = std::mem::replace( &mut *picking::PICKING.lock().unwrap(), picking );
+
     // Take the buffer and deserialize it into a ThreeAttributes
-    let mut res: ThreeAttributes = serde_json::from_slice(&buf.buffer).expect("Error parsing CityJSON buffer");
+    let res: Result<ThreeAttributes, _> = format::from_slice( buffer );
+
+    // The picking index of the previous file is still there if parsing failed (or was cancelled), so keep its storage too
+    if res.is_err() {
+        *picking::PICKING.lock().unwrap() = previous;
+    }
+
+    let res = res?;
 
     log!("Rust: CityObjects parsed");
 
-    // Parse into JsValue to be able to return it to JS
-    serde_wasm_bindgen::to_value( &res.attributes ).expect("Could not convert serde_json::Value into JsValue")
+    Ok( res.attributes )
+
+}
+
+/// Same as triangulate(), with a progress callback (called for every percent of the buffer) and/or a token to cancel the parse with.
+/// Returns progress::CANCELLED as the error if the parse was cancelled; the picking index of the previous file is kept then.
+pub fn triangulate_with_progress( buffer: &[u8], picking: PickingIndex, callback: Option<progress::Callback>, token: Option<CancelToken> ) -> Result<ThreeGroups, String> {
+
+    progress::with_progress( buffer, callback, token, || triangulate( buffer, picking ) )
 
 }
 
-#[wasm_bindgen]
-pub fn parse_vertices( buf: &WasmMemBuffer ) -> wasm_bindgen::JsValue {
+/// The vertices as one flat vector, as returned by parse_vertices()
+pub fn flatten_vertices( buffer: &[u8] ) -> Result<Vec<u32>, serde_json::Error> {
 
     log!("Rust: getting vertices...");
 
     // Take the buffer and deserialize it into a Vertices (flattened vertices vector)
-    let vertices: Vertices = serde_json::from_slice(&buf.buffer).expect("Error parsing CityJSON buffer");
-    
+    let vertices: Vertices = format::from_slice( buffer )?;
+
     log!("Rust: vertices parsed");
-    
-    // Parse into JsValue to be able to return it to JS
-    serde_wasm_bindgen::to_value(&vertices.vertices).expect("Could not convert serde_json::Value into JsValue")
+
+    Ok( vertices.vertices )
 
 }
 
-pub fn parse_all( buf: &WasmMemBuffer ) -> wasm_bindgen::JsValue {
+pub fn parse_all( buffer: &[u8] ) -> Result<ThreeGroups, serde_json::Error> {
 
     unsafe {
         TRIANGULATED = false;
@@ -65,26 +88,26 @@ pub fn parse_all( buf: &WasmMemBuffer ) -> wasm_bindgen::JsValue {
     log!("Rust: getting vertices...");
 
     // Take the buffer and deserialize it into a Vertices (flattened vertices vector)
-    let vertices: Vertices = serde_json::from_slice(&buf.buffer).expect("Error parsing CityJSON buffer");
+    let _vertices: Vertices = format::from_slice( buffer )?;
 
     log!("Rust: vertices parsed");
 
     log!("Rust: Parsing CityObjects...");
 
     // Take the buffer and deserialize it into a ThreeAttributes
-    let mut res: ThreeAttributes = serde_json::from_slice(&buf.buffer).expect("Error parsing CityJSON buffer");
+    let res: ThreeAttributes = format::from_slice( buffer )?;
 
     log!("Rust: CityObjects parsed");
 
-    // Parse into JsValue to be able to return it to JS
-    serde_wasm_bindgen::to_value( &res.attributes ).expect("Could not convert serde_json::Value into JsValue")
-
+    Ok( res.attributes )
 
 }
 
 ///// Serde (JSON) streaming code, adapted from https://serde.rs/stream-array.html, https://serde.rs/deserialize-map.html, and https://serde.rs/deserialize-struct.html /////
 
 // Default enables easy initialization (with CityObjects { ..Default::default() }; )
+// Field names are the CityObject types as they appear in CityJSON
+#[allow(non_snake_case)]
 #[derive(Serialize, Deserialize, Default)]
 struct CityObjectsAttributes<T> {
 
@@ -173,11 +196,12 @@ impl<T> IndexMut<&'_ str> for CityObjectsAttributes<T> {
 }
 
 #[derive(Serialize, Deserialize, Default)]
-struct ThreeGroups {
+pub struct ThreeGroups {
 
-    triangles: Vec<u32>,
-    groups: HashMap<String, Vec<u32>>,
-    vertices: Vec<u32>,
+    pub triangles: Vec<u32>,
+    // [start, count] per CityObject type
+    pub groups: HashMap<String, Vec<u32>>,
+    pub vertices: Vec<u32>,
 
 }
 
@@ -201,125 +225,489 @@ where
 
 {
 
-    struct COVisitor;
+    let builder = CityObjectsVisitor( CityObjectsBuilder::new( *picking::PICKING.lock().unwrap() ) ).deserialize( deserializer )?;
+
+    Ok( builder.finish() )
+
+}
+
+// Adds the CityObjects to a builder, and reports progress
+struct CityObjectsVisitor( CityObjectsBuilder );
+
+impl<'de> DeserializeSeed<'de> for CityObjectsVisitor
+{
+    type Value = CityObjectsBuilder;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // Create the visitor and ask the deserializer to drive it. The
+        // deserializer will call visitor.visit_map() if a map is present in
+        // the input data.
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for CityObjectsVisitor
+{
+    /// Return type of this visitor
+    type Value = CityObjectsBuilder;
+
+    // Error message if data that is not of this type is encountered while deserializing
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a key/value entry")
+    }
 
-    impl<'de> Visitor<'de> for COVisitor
+    // Traverse CityObjects
+    fn visit_map<S>(self, mut map: S) -> Result<CityObjectsBuilder, S::Error>
+    where
+        S: MapAccess<'de>,
     {
-        /// Return type of this visitor
-        type Value = ThreeGroups;
 
-        // Error message if data that is not of this type is encountered while deserializing
-        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("a key/value entry")
+        // Progress counter
+        let mut i = 1;
+
+        let mut builder = self.0;
+        let tracking = progress::tracking();
+        // Where a CityObject ends in the buffer is only known for JSON text
+        let positions = tracking && format::current() == Format::Json;
+
+        // Iterate over keys and values in "CityObjects"
+        while let Some( key ) = map.next_key::<String>()? {
+
+            // When progress is reported, take the CityObject as raw JSON first, to know where it ends in the buffer
+            let ( value, raw ) = if positions {
+                let raw: &RawValue = map.next_value()?;
+                ( serde_json::from_str::<serde_json::Value>( raw.get() ).map_err( de::Error::custom )?, Some( raw ) )
+            } else {
+                ( map.next_value::<serde_json::Value>()?, None )
+            };
+
+            builder.add( &key, &value ).map_err( de::Error::custom )?;
+
+            // Report progress and stop if the parse was cancelled
+            let proceed = match raw {
+                Some( raw ) => progress::step( i, raw.get() ),
+                None if tracking => progress::count( i ),
+                None => true,
+            };
+
+            if !proceed {
+                return Err( de::Error::custom( progress::CANCELLED ) );
+            }
+
+            i += 1;
+
         }
 
-        // Traverse CityObjects
-        fn visit_map<S>(self, mut map: S) -> Result<ThreeGroups, S::Error>
-        where
-            S: MapAccess<'de>,
-        {
+        Ok( builder )
 
-            // Progress counter
-            let mut i = 1;
-        
-            let mut triangle_groups = CityObjectsAttributes { ..Default::default() };
-            let mut interval_groups = CityObjectsAttributes { ..Default::default() };
-            let mut id_groups = CityObjectsAttributes { ..Default::default() };
+    }
+}
 
-            let co_types = ["Building", "BuildingPart", "BuildingInstallation", "Bridge", "BridgePart", "BridgeInstallation", "BridgeConstructionElement", "CityObjectGroup", "CityFurniture", "GenericCityObject", "LandUse", "PlantCover", "Railway", "Road", "SolitaryVegetationObject", "TINRelief", "TransportSquare", "Tunnel", "TunnelPart", "TunnelInstallation", "WaterBody"];
+// Visits the top-level CityJSON object for parse_local(), and hands "CityObjects" to a CityObjectsVisitor
+struct LocalDocumentSeed( PickingIndex );
 
-            // Iterate over keys and values in "CityObjects"
-            while let Some( ( key, value ) ) = map.next_entry::<String, serde_json::Value>()? {
+impl<'de> DeserializeSeed<'de> for LocalDocumentSeed
+{
+    type Value = ParsedCityObjects;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+}
 
-                // Parse CityObjects geometries into triangle vector per CityObject type
-                parse_cityobject( &key, &value, &mut triangle_groups );
+impl<'de> Visitor<'de> for LocalDocumentSeed
+{
+    type Value = ParsedCityObjects;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a CityJSON object")
+    }
 
-                let co_type: &str = value[ "type" ].as_str().expect( "CityObject has no valid type" );
+    fn visit_map<S>(self, mut map: S) -> Result<ParsedCityObjects, S::Error>
+    where
+        S: MapAccess<'de>,
+    {
 
-                // Store ID and triangle interval (the triangles to which this ID belongs) - for each CityObject type, vectors are merged later
-                let triangles_len = triangle_groups[ co_type ].len() as u32;
-                // What happens if a CityObject did not have geometry? Maybe do something like this (but check if .last() == None)
-                // if *interval_groups[ co_type ].last().unwrap() != triangles_len
-                interval_groups[ co_type ].push( triangles_len / 3 ); // Divided by 3, since triangle vectors are flat and thus every element is a vertex
-                id_groups[ co_type ].push( key.to_string() );
+        let mut parsed = None;
 
-                if i % 1000 == 0 {
-                    log!("{} CityObjects parsed", i);
-                }
+        while let Some( key ) = map.next_key::<String>()? {
 
-                i += 1;
+            if key == "CityObjects" && parsed.is_none() {
 
+                // Nothing is searched in a file that is parsed locally
+                let mut builder = CityObjectsBuilder::new( self.0 );
+                builder.search_index = None;
+
+                parsed = Some( map.next_value_seed( CityObjectsVisitor( builder ) )?.finish_local() );
+
+            } else {
+                map.next_value::<de::IgnoredAny>()?;
             }
 
-            // Lock global variables, so that they can be mutated within this scope
-            let mut ids = IDS.lock().unwrap();
-            let mut intervals = INTERVALS.lock().unwrap();
+        }
+
+        parsed.ok_or_else( || de::Error::missing_field( "CityObjects" ) )
+
+    }
+}
+
+/// CityObjects that were parsed without storing anything globally (see parse_local())
+pub struct ParsedCityObjects {
+
+    pub triangles: Vec<u32>,
+    // [start, count] per CityObject type
+    pub groups: HashMap<String, Vec<u32>>,
+    // In the order of the picking index
+    pub ids: Vec<String>,
+    // Triangle intervals for PickingIndex::Intervals, one object index per triangle for PickingIndex::Dense
+    pub index: Vec<u32>,
+    pub hierarchy: Hierarchy,
+
+}
+
+/// Parse the CityObjects like triangulate(), but return the IDs, the picking index and the hierarchy instead of storing them globally,
+/// so that the file that is shown, with its joined columns and edits, is left alone. No search index is built. Reports progress when
+/// called within progress::with_progress().
+pub fn parse_local( buffer: &[u8], picking: PickingIndex ) -> Result<ParsedCityObjects, serde_json::Error> {
+
+    format::deserialize_seed( buffer, LocalDocumentSeed( picking ) )
+
+}
+
+/// Collects the triangles, IDs, triangle intervals, hierarchy and search index of CityObjects one at a time, and merges them per CityObject type
+/// at the end. Used by the deserializer of "CityObjects" and by the push parser (push.rs), which gets the CityObjects in chunks.
+pub struct CityObjectsBuilder {
+
+    picking: PickingIndex,
+    objects: u32,
+    triangle_groups: CityObjectsAttributes<u32>,
+    interval_groups: CityObjectsAttributes<u32>,
+    id_groups: CityObjectsAttributes<String>,
+    hierarchy: Hierarchy,
+    search_index: Option<SearchIndex>,
+    // Per CityObject type (in the order of CO_TYPES), the amount of triangle indices that take_batch() already returned
+    taken: [usize; 21],
+    // Triangle indices in triangle_groups
+    pending: usize,
+    // IDs and triangle intervals of the CityObjects that drain_batch() moved out, in the order of the batches
+    drained_ids: Vec<String>,
+    drained_intervals: Vec<u32>,
+
+}
+
+impl CityObjectsBuilder {
+
+    pub fn new( picking: PickingIndex ) -> Self {
+
+        let search_fields = search::SEARCH_FIELDS.lock().unwrap().clone();
+
+        CityObjectsBuilder {
+            picking,
+            objects: 0,
+            triangle_groups: CityObjectsAttributes { ..Default::default() },
+            interval_groups: CityObjectsAttributes { ..Default::default() },
+            id_groups: CityObjectsAttributes { ..Default::default() },
+            hierarchy: Hierarchy::default(),
+            search_index: search_fields.as_ref().map( |fields| SearchIndex::new( fields ) ),
+            taken: [ 0; 21 ],
+            pending: 0,
+            drained_ids: Vec::new(),
+            drained_intervals: vec![0],
+        }
+
+    }
+
+    /// The amount of CityObjects that were added
+    pub fn objects( &self ) -> u32 {
+
+        self.objects
+
+    }
+
+    /// The amount of triangle indices that are kept, i.e. that were not moved out by drain_batch()
+    pub fn pending( &self ) -> usize {
+
+        self.pending
+
+    }
+
+    /// Add a CityObject. Fails if it has no type, or a type that isn't one of CO_TYPES (e.g. of an Extension).
+    pub fn add( &mut self, key: &str, value: &serde_json::Value ) -> Result<(), String> {
+
+        let co_type = cityobject_type( key, value )?;
+        let triangles_before = self.triangle_groups[ co_type ].len() as u32;
 
-            // Count amount of triangles to be able to init vector with_capacity(n)
-            let mut triangles_n = 0;
+        // Parse CityObjects geometries into triangle vector per CityObject type
+        parse_cityobject( key, value, co_type, &mut self.triangle_groups );
 
-            for co_type in &co_types {
+        // Store ID and triangle interval (the triangles to which this ID belongs) - for each CityObject type, vectors are merged later
+        // CityObjects without (triangulated) geometry are left out, since a zero-length interval can't be clicked. They can still be retrieved with get_attributes().
+        let triangles_len = self.triangle_groups[ co_type ].len() as u32;
 
-                triangles_n += triangle_groups[ &co_type.to_string() ].len();
+        if triangles_len != triangles_before {
+
+            self.interval_groups[ co_type ].push( triangles_len / 3 ); // Divided by 3, since triangle vectors are flat and thus every element is a vertex
+            self.id_groups[ co_type ].push( key.to_string() );
+            self.pending += ( triangles_len - triangles_before ) as usize;
+
+        }
+
+        // Record the parent-child relationships, so that a picked CityObject can report its parent(s)
+        self.hierarchy.add( key, value );
+
+        // Index the ID and the selected attributes for searching, if requested
+        if let Some( index ) = self.search_index.as_mut() {
+            index.add( key, value );
+        }
+
+        self.objects += 1;
+
+        if self.objects.is_multiple_of( 1000 ) {
+            log!("{} CityObjects parsed", self.objects);
+        }
+
+        Ok( () )
+
+    }
+
+    /// The triangles of the CityObjects that were added since the previous call, with groups ([start, count] per CityObject type) within
+    /// these triangles. The final triangles of finish() are ordered differently, since they are merged per CityObject type.
+    pub fn take_batch( &mut self ) -> ( Vec<u32>, HashMap<String, Vec<u32>> ) {
+
+        let mut triangles = Vec::new();
+        let mut groups = HashMap::new();
+
+        for ( t, co_type ) in CO_TYPES.iter().enumerate() {
+
+            let new = &self.triangle_groups[ co_type ][ self.taken[ t ].. ];
+
+            if !new.is_empty() {
+
+                groups.insert( co_type.to_string(), vec!( triangles.len() as u32, new.len() as u32 ) );
+                triangles.extend_from_slice( new );
+                self.taken[ t ] += new.len();
 
             }
 
-            // Merge triangle vectors, create triangle groups (for Three.js, with start index and count)
-            let mut res = ThreeGroups { triangles: Vec::with_capacity(triangles_n),
-                                        groups: HashMap::new(),
-                                        vertices: Vec::<u32>::new() };
-            
-            let triangles = &mut res.triangles;
-            let groups = &mut res.groups;
+        }
 
-            for co_type in &co_types {
+        ( triangles, groups )
 
-                if triangle_groups[ &co_type.to_string() ].len() > 0 {
+    }
 
-                    let start = triangles.len();
+    /// Like take_batch(), but moves the triangles out instead of copying them, so that the builder doesn't keep them. The picking index then
+    /// follows the order of the batches: the triangles of a batch come after those of the previous batches. Finish with finish_drained().
+    pub fn drain_batch( &mut self ) -> ( Vec<u32>, HashMap<String, Vec<u32>> ) {
 
-                    triangles.append( &mut triangle_groups[ &co_type.to_string() ] );
+        let mut triangles = Vec::with_capacity( self.pending );
+        let mut groups = HashMap::new();
 
-                    let count = triangles.len() - start;
+        // The triangle after the last one of the previous batches
+        let base = *self.drained_intervals.last().unwrap();
 
-                    groups.insert( co_type.to_string(), vec!(start as u32, count as u32) );
+        for co_type in &CO_TYPES {
 
-                    // Globally store IDs and triangle intervals
-                    ids.append( &mut id_groups[ co_type ] );
-                    // Add current length of triangles to intervals, since the intervals were local for every CityObject type
-                    interval_groups[ co_type ].iter_mut().for_each(|x| *x += ( start as u32 ) );
-                    intervals.append( &mut interval_groups[ co_type ] );
+            if !self.triangle_groups[ co_type ].is_empty() {
 
-                }
+                let start = triangles.len();
 
-            };
+                triangles.append( &mut self.triangle_groups[ co_type ] );
+                groups.insert( co_type.to_string(), vec!( start as u32, ( triangles.len() - start ) as u32 ) );
+
+                // The intervals are local for every CityObject type in this batch, so add the triangles before them
+                let offset = base + ( start / 3 ) as u32;
+
+                self.drained_ids.append( &mut self.id_groups[ co_type ] );
+                self.drained_intervals.extend( self.interval_groups[ co_type ].drain( .. ).map( |x| x + offset ) );
+
+            }
+
+        }
+
+        self.pending = 0;
+        self.taken = [ 0; 21 ];
+
+        ( triangles, groups )
+
+    }
+
+    /// Merge the CityObjects per type into triangles and groups, and store the IDs, the picking index, the hierarchy and the search index globally
+    pub fn finish( mut self ) -> ThreeGroups {
+
+        let ( res, ids, intervals ) = self.merge();
+
+        self.store( ids, intervals );
+
+        res
+
+    }
+
+    /// Like finish(), but return the IDs, the picking index and the hierarchy instead of storing anything globally
+    pub fn finish_local( mut self ) -> ParsedCityObjects {
+
+        let ( ThreeGroups { triangles, groups, .. }, ids, intervals ) = self.merge();
+
+        let index = match self.picking {
+            PickingIndex::Dense => picking::intervals_to_object_index( &intervals ),
+            PickingIndex::Intervals => intervals,
+        };
+
+        let mut hierarchy = self.hierarchy;
+        hierarchy.finalize();
+
+        ParsedCityObjects { triangles, groups, ids, index, hierarchy }
+
+    }
+
+    // Merge the CityObjects per type into triangles and groups, with the IDs and triangle intervals in the same order
+    fn merge( &mut self ) -> ( ThreeGroups, Vec<String>, Vec<u32> ) {
+
+        // Count amount of triangles to be able to init vector with_capacity(n)
+        let mut triangles_n = 0;
 
-            Ok( res )
+        for co_type in &CO_TYPES {
+
+            triangles_n += self.triangle_groups[ co_type ].len();
 
         }
+
+        // Merge triangle vectors, create triangle groups (for Three.js, with start index and count)
+        let mut res = ThreeGroups { triangles: Vec::with_capacity(triangles_n),
+                                    groups: HashMap::new(),
+                                    vertices: Vec::<u32>::new() };
+
+        let mut ids = Vec::new();
+        let mut intervals = vec![0];
+
+        let triangles = &mut res.triangles;
+        let groups = &mut res.groups;
+
+        for co_type in &CO_TYPES {
+
+            if !self.triangle_groups[ co_type ].is_empty() {
+
+                let start = triangles.len();
+
+                triangles.append( &mut self.triangle_groups[ co_type ] );
+
+                let count = triangles.len() - start;
+
+                groups.insert( co_type.to_string(), vec!(start as u32, count as u32) );
+
+                // Collect IDs and triangle intervals
+                ids.append( &mut self.id_groups[ co_type ] );
+                // Add current amount of triangles to intervals, since the intervals were local for every CityObject type
+                self.interval_groups[ co_type ].iter_mut().for_each(|x| *x += ( start / 3 ) as u32 );
+                intervals.append( &mut self.interval_groups[ co_type ] );
+
+            }
+
+        };
+
+        ( res, ids, intervals )
+
+    }
+
+    /// Drain the last batch (see drain_batch()) and store the picking index of all batches etc. globally, like finish()
+    pub fn finish_drained( mut self ) -> ThreeGroups {
+
+        let ( triangles, groups ) = self.drain_batch();
+        let ids = std::mem::take( &mut self.drained_ids );
+        let intervals = std::mem::take( &mut self.drained_intervals );
+
+        self.store( ids, intervals );
+
+        ThreeGroups { triangles, groups, vertices: Vec::new() }
+
     }
 
-    // Create the visitor and ask the deserializer to drive it. The
-    // deserializer will call visitor.visit_map() if a map is present in
-    // the input data.
+    // Store the IDs, the picking index, the hierarchy and the search index globally
+    fn store( self, new_ids: Vec<String>, new_intervals: Vec<u32> ) {
+
+        // Lock global variables, so that they can be mutated within this scope. This replaces the picking index of a previously parsed file.
+        let mut ids = IDS.lock().unwrap();
+        let mut intervals = INTERVALS.lock().unwrap();
+
+        *ids = new_ids;
+        *intervals = new_intervals;
 
-    deserializer.deserialize_map(COVisitor)
+        let mut hierarchy = self.hierarchy;
+        hierarchy.finalize();
+        *HIERARCHY.lock().unwrap() = hierarchy;
+
+        let mut search_index = self.search_index.unwrap_or_default();
+        search_index.finish();
+        *search::SEARCH_INDEX.lock().unwrap() = search_index;
+
+        // Joined columns and edits belong to the CityObjects of the previous file, even where a new file reuses their IDs
+        join::clear_joins();
+        edit::clear_edits();
+
+        // Replace the intervals by one object index per triangle if that was requested, so that only one of the two is kept in memory
+        *picking::PICKING.lock().unwrap() = self.picking;
+        let mut object_index = picking::OBJECT_INDEX.lock().unwrap();
+
+        if self.picking == PickingIndex::Dense {
+
+            *object_index = picking::intervals_to_object_index( &intervals );
+            *intervals = vec![0];
+
+        } else {
+
+            object_index.clear();
+
+        }
+
+    }
+
+}
+
+/// Triangles (flat vertex indices) of a single CityObject, for queries that need the geometry of individual CityObjects.
+/// Empty for a CityObject without a type of CO_TYPES, which isn't triangulated.
+pub fn cityobject_triangles( id: &str, co: &serde_json::Value ) -> Vec<u32> {
+
+    let co_type = match cityobject_type( id, co ) {
+        Ok( co_type ) => co_type,
+        Err( _ ) => return Vec::new(),
+    };
+
+    let mut triangles = CityObjectsAttributes { ..Default::default() };
+
+    parse_cityobject( id, co, co_type, &mut triangles );
+
+    std::mem::take( &mut triangles[ co_type ] )
+
+}
+
+// The type of a CityObject, which selects its triangle group
+fn cityobject_type<'a>( id: &str, co: &'a serde_json::Value ) -> Result<&'a str, String> {
+
+    match co.get( "type" ).and_then( |t| t.as_str() ) {
+        Some( co_type ) if CO_TYPES.contains( &co_type ) => Ok( co_type ),
+        Some( co_type ) => Err( format!( "CityObject \"{}\" has type \"{}\", which is not supported", id, co_type ) ),
+        None => Err( format!( "CityObject \"{}\" has no type", id ) ),
+    }
 
 }
 
-fn parse_cityobject( id: &String, co: &serde_json::Value, triangles: &mut CityObjectsAttributes<u32> ) {
+fn parse_cityobject( id: &str, co: &serde_json::Value, co_type: &str, triangles: &mut CityObjectsAttributes<u32> ) {
 
-    let co_type: &str = co["type"].as_str().expect("CityObject has no valid type");
-    let mut geom = co.get("geometry");
+    let geom = co.get("geometry");
 
     // Return early if the CityObject has no geometry
-    if geom.is_none() || geom.unwrap().as_array().unwrap().len() == 0 {
+    if geom.is_none() || geom.unwrap().as_array().unwrap().is_empty() {
 
         return;
 
     }
 
-    let mut geom = geom.expect("CityObject does not have \"geometry\"");
+    let geom = geom.expect("CityObject does not have \"geometry\"");
     let geom_n = geom.as_array().unwrap().len();
 
     for g_i in 0..geom_n {
@@ -339,7 +727,7 @@ fn parse_cityobject( id: &String, co: &serde_json::Value, triangles: &mut CityOb
         }
         else if geom_type == "MultiSurface" || geom_type == "CompositeSurface" {
 
-            parse_shell( &boundaries, triangles, co_type, id );
+            parse_shell( boundaries, triangles, co_type, id );
 
         }
         else if geom_type == "MultiSolid" || geom_type == "CompositeSolid" {
@@ -362,7 +750,7 @@ fn parse_cityobject( id: &String, co: &serde_json::Value, triangles: &mut CityOb
 
 }
 
-fn parse_shell( boundaries: &serde_json::Value, triangles: &mut CityObjectsAttributes<u32>, co_type: &str, id: &str ){
+fn parse_shell( boundaries: &serde_json::Value, triangles: &mut CityObjectsAttributes<u32>, co_type: &str, _id: &str ){
 
     let boundaries_n = boundaries.as_array().unwrap().len();
 
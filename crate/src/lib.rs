@@ -1,5 +1,4 @@
 #[macro_use]
-
 extern crate cfg_if;
 extern crate wasm_bindgen;
 extern crate web_sys;
@@ -10,9 +9,6 @@ extern crate serde_wasm_bindgen;
 extern crate phf;
 extern crate lazy_static;
 
-use wasm_bindgen::prelude::*;
-
-
 ///// Boilerplate code, from https://github.com/rustwasm/rust-parcel-template and I think the wasm-pack-template /////
 
 cfg_if! {
@@ -37,52 +33,18 @@ cfg_if! {
     }
 }
 
-// A macro to provide `println!(..)`-style syntax for `console.log` logging.
+// A macro to provide `println!(..)`-style syntax for logging. It goes to the logger of the logging module: the browser console in the viewer,
+// stderr natively (the command-line tool).
 #[macro_export]
 macro_rules! log {
     ( $( $t:tt )* ) => {
-        web_sys::console::log_1(&format!( $( $t )* ).into());
+        $crate::logging::log( &format!( $( $t )* ) )
     }
 }
 
-// Only import module after macros have been defined
-mod cityjson;
-
-// Called by our JS entry point to run the example
-#[wasm_bindgen]
-pub fn init() -> Result<(), JsValue> {
-    // If the `console_error_panic_hook` feature is enabled this will set a panic hook, otherwise
-    // it will do nothing.
-    set_panic_hook();
-    console_error_panic_hook::set_once();
-
-    Ok(())
-}
-
-
-
-// This allows the creation and direct use of buffers in WASM memory. It avoids having to copy an ArrayBuffer from JS to Rust, which would mean that the data is in memory twice.
-// See https://github.com/rustwasm/wasm-bindgen/issues/1079, https://github.com/rustwasm/wasm-bindgen/issues/1643
-// Code has been sourced from there.
-#[wasm_bindgen]
-pub struct WasmMemBuffer {
-    buffer: Vec<u8>,
-}
-
-#[wasm_bindgen]
-impl WasmMemBuffer {
-    #[wasm_bindgen(constructor)]
-    pub fn new(byte_length: u32, f: &js_sys::Function) -> Self {
-        let mut buffer: Vec<u8> = Vec::new();
-        buffer.reserve(byte_length as usize);
-        unsafe {
-            let array =
-                js_sys::Uint8Array::view_mut_raw(buffer.as_mut_ptr(),
-                                                 byte_length as usize);
-            f.call1(&JsValue::NULL, &JsValue::from(array))
-                .expect("The callback function should not throw");
-            buffer.set_len(byte_length as usize);
-        }
-        Self { buffer }
-    }
-}
+// Only import modules after macros have been defined
+pub mod logging;
+// Platform-independent core, working on &[u8] and returning Rust types
+pub mod cityjson;
+// The wasm-bindgen API for the viewer, a thin layer over cityjson
+pub mod wasm;
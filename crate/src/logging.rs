@@ -0,0 +1,63 @@
+use lazy_static::lazy_static;
+use std::sync::RwLock;
+
+///// Logging for the core, which doesn't know whether it runs in the browser or natively. log!() formats the message and passes it to
+///// the current logger: stderr by default, the browser console once init() has been called (see wasm/mod.rs), or whatever is set with
+///// set_logger(), e.g. Silent in tests or a closure that collects the messages.
+
+/// Receives the messages of log!()
+pub trait Logger: Send + Sync {
+
+    fn log( &self, message: &str );
+
+}
+
+/// Logs to stderr (the default)
+pub struct Stderr;
+
+impl Logger for Stderr {
+
+    fn log( &self, message: &str ) {
+
+        eprintln!( "{}", message );
+
+    }
+
+}
+
+/// Drops all messages
+pub struct Silent;
+
+impl Logger for Silent {
+
+    fn log( &self, _message: &str ) {}
+
+}
+
+impl<F: Fn( &str ) + Send + Sync> Logger for F {
+
+    fn log( &self, message: &str ) {
+
+        self( message )
+
+    }
+
+}
+
+lazy_static! {
+    static ref LOGGER: RwLock<Box<dyn Logger>> = RwLock::new(Box::new(Stderr));
+}
+
+/// Replace the logger
+pub fn set_logger( logger: Box<dyn Logger> ) {
+
+    *LOGGER.write().unwrap() = logger;
+
+}
+
+/// Pass a message to the current logger. Use log!() instead, which takes format!() arguments.
+pub fn log( message: &str ) {
+
+    LOGGER.read().unwrap().log( message );
+
+}
@@ -0,0 +1,79 @@
+use wasm_bindgen::prelude::*;
+use std::collections::HashMap;
+
+use crate::cityjson::cache::{self, CacheView, GeometryCache};
+use super::WasmMemBuffer;
+
+// Typed array of a section, copied out of WASM memory: straight from the bytes if they are aligned (the cache is little endian, like
+// WASM), otherwise decoded first. A view would become invalid once WASM memory grows or the buffer is freed.
+fn u32_array( bytes: &[u8] ) -> js_sys::Uint32Array {
+
+    #[cfg(target_endian = "little")]
+    {
+        let ( prefix, values, _ ) = unsafe { bytes.align_to::<u32>() };
+
+        if prefix.is_empty() {
+            return js_sys::Uint32Array::from( values );
+        }
+    }
+
+    js_sys::Uint32Array::from( &cache::u32s( bytes )[..] )
+
+}
+
+fn f64_array( bytes: &[u8] ) -> js_sys::Float64Array {
+
+    #[cfg(target_endian = "little")]
+    {
+        let ( prefix, values, _ ) = unsafe { bytes.align_to::<f64>() };
+
+        if prefix.is_empty() {
+            return js_sys::Float64Array::from( values );
+        }
+    }
+
+    js_sys::Float64Array::from( &cache::f64s( bytes )[..] )
+
+}
+
+/// Parse the CityObjects and vertices and return them as a geometry cache file (Uint8Array), e.g. to store it next to the CityJSON file
+#[wasm_bindgen]
+pub fn write_geometry_cache( buf: &WasmMemBuffer ) -> Result<js_sys::Uint8Array, JsValue> {
+
+    log!("Writing geometry cache...");
+
+    let cache = GeometryCache::build( &buf.buffer ).map_err( super::to_js_error )?;
+
+    let bytes = cache.to_bytes().map_err( super::to_js_error )?;
+
+    Ok( js_sys::Uint8Array::from( &bytes[..] ) )
+
+}
+
+/// Load a geometry cache file instead of parsing CityJSON. Returns { triangles, groups, vertices, transform, extents, ids } with typed arrays
+/// (use new THREE.BufferAttribute( triangles, 1 ) for the index). Picking (get_interval_and_id(), pick_cityobject()) works afterwards,
+/// but attributes still need the CityJSON buffer.
+#[wasm_bindgen]
+pub fn load_geometry_cache( buf: &WasmMemBuffer ) -> Result<JsValue, JsValue> {
+
+    log!("Loading geometry cache...");
+
+    let view = CacheView::parse( &buf.buffer ).map_err( super::to_js_error )?;
+    let groups = view.groups().map_err( super::to_js_error )?;
+    let ids = cache::restore_picking( &view ).map_err( super::to_js_error )?;
+
+    // Same form as the groups of parse_cityobjects()
+    let groups: HashMap<String, Vec<u32>> = groups.into_iter().map( |( t, start, count )| ( t, vec![ start, count ] ) ).collect();
+
+    let res = js_sys::Object::new();
+
+    js_sys::Reflect::set( &res, &JsValue::from_str( "triangles" ), &u32_array( view.triangles ) )?;
+    js_sys::Reflect::set( &res, &JsValue::from_str( "groups" ), &serde_wasm_bindgen::to_value( &groups ).expect("Could not convert groups into JsValue") )?;
+    js_sys::Reflect::set( &res, &JsValue::from_str( "vertices" ), &u32_array( view.vertices ) )?;
+    js_sys::Reflect::set( &res, &JsValue::from_str( "transform" ), &f64_array( view.transform ) )?;
+    js_sys::Reflect::set( &res, &JsValue::from_str( "extents" ), &f64_array( view.extents ) )?;
+    js_sys::Reflect::set( &res, &JsValue::from_str( "ids" ), &serde_wasm_bindgen::to_value( &ids ).expect("Could not convert IDs into JsValue") )?;
+
+    Ok( res.into() )
+
+}
@@ -0,0 +1,60 @@
+use wasm_bindgen::prelude::*;
+use serde_json::Value;
+
+use crate::cityjson::edit;
+use super::WasmMemBuffer;
+
+/// Set an attribute of the given CityObjects to a value (any JSON value)
+#[wasm_bindgen]
+pub fn set_attribute( ids: Vec<String>, key: String, value: JsValue ) -> Result<(), JsValue> {
+
+    let value: Value = serde_wasm_bindgen::from_value( value ).map_err( |e| JsValue::from_str( &format!( "Invalid attribute value: {}", e ) ) )?;
+
+    edit::set_attribute( &ids, &key, value );
+
+    Ok( () )
+
+}
+
+/// Rename an attribute of the given CityObjects. An existing attribute with the new name is replaced.
+#[wasm_bindgen]
+pub fn rename_attribute( ids: Vec<String>, from: String, to: String ) {
+
+    edit::rename_attribute( &ids, &from, &to );
+
+}
+
+/// Delete an attribute of the given CityObjects
+#[wasm_bindgen]
+pub fn delete_attribute( ids: Vec<String>, key: String ) {
+
+    edit::delete_attribute( &ids, &key );
+
+}
+
+/// Returns the IDs of the CityObjects that have been edited
+#[wasm_bindgen]
+pub fn get_edited_ids() -> JsValue {
+
+    serde_wasm_bindgen::to_value( &edit::edited_ids() ).expect("Could not convert IDs into JsValue")
+
+}
+
+/// Forget all edits
+#[wasm_bindgen]
+pub fn clear_edits() {
+
+    edit::clear_edits();
+
+}
+
+/// Returns the CityJSON document with the edits applied, as a Uint8Array (e.g. for a download Blob).
+/// If include_joined is true, the columns of joined tables are written as attributes too.
+#[wasm_bindgen]
+pub fn write_edited_cityjson( buf: &WasmMemBuffer, include_joined: bool ) -> Result<js_sys::Uint8Array, JsValue> {
+
+    let out = edit::write_edited_cityjson( &buf.buffer, include_joined ).map_err( super::to_js_error )?;
+
+    Ok( js_sys::Uint8Array::from( &out[..] ) )
+
+}
@@ -0,0 +1,47 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::export;
+use super::WasmMemBuffer;
+use super::mesh;
+
+/// Returns the model as OBJ: { obj, mtl } (two strings), with mtl_name the file name under which the MTL will be saved.
+/// options: { lod, types, recenter } (see MeshOptions), or undefined.
+#[wasm_bindgen]
+pub fn export_obj( buf: &WasmMemBuffer, mtl_name: String, options: JsValue ) -> Result<JsValue, JsValue> {
+
+    log!("Exporting OBJ...");
+
+    let mesh = mesh::build( buf, options )?;
+
+    let res = js_sys::Object::new();
+
+    js_sys::Reflect::set( &res, &JsValue::from_str( "obj" ), &JsValue::from_str( &export::write_obj( &mesh, &mtl_name ) ) )?;
+    js_sys::Reflect::set( &res, &JsValue::from_str( "mtl" ), &JsValue::from_str( &export::write_mtl( &mesh ) ) )?;
+
+    Ok( res.into() )
+
+}
+
+/// Returns the model as binary STL (Uint8Array). options: { lod, types, recenter } (see MeshOptions), or undefined.
+#[wasm_bindgen]
+pub fn export_stl( buf: &WasmMemBuffer, options: JsValue ) -> Result<js_sys::Uint8Array, JsValue> {
+
+    log!("Exporting STL...");
+
+    let mesh = mesh::build( buf, options )?;
+
+    Ok( js_sys::Uint8Array::from( &export::write_stl( &mesh )[..] ) )
+
+}
+
+/// Returns the model as binary PLY (Uint8Array) with an object index per face. options: { lod, types, recenter } (see MeshOptions), or undefined.
+#[wasm_bindgen]
+pub fn export_ply( buf: &WasmMemBuffer, options: JsValue ) -> Result<js_sys::Uint8Array, JsValue> {
+
+    log!("Exporting PLY...");
+
+    let mesh = mesh::build( buf, options )?;
+
+    Ok( js_sys::Uint8Array::from( &export::write_ply( &mesh )[..] ) )
+
+}
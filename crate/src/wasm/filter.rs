@@ -0,0 +1,26 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::filter;
+use super::WasmMemBuffer;
+
+/// Returns { ids, intervals } of the CityObjects that match a filter expression, e.g. `type == "Building" && measuredHeight > 20`.
+/// Throws a message with the position of the error if the expression is invalid.
+#[wasm_bindgen]
+pub fn filter_cityobjects( buf: &WasmMemBuffer, expression: String ) -> Result<JsValue, JsValue> {
+
+    let res = filter::filter_cityobjects( &buf.buffer, &expression ).map_err( super::to_js_error )?;
+
+    Ok( serde_wasm_bindgen::to_value( &res ).expect("Could not convert filter result into JsValue") )
+
+}
+
+/// Checks a filter expression without running it. Returns null if it is valid, otherwise the error message.
+#[wasm_bindgen]
+pub fn check_filter( expression: String ) -> JsValue {
+
+    match filter::parse( &expression ) {
+        Ok( _ ) => JsValue::NULL,
+        Err( e ) => JsValue::from_str( &e.to_string() ),
+    }
+
+}
@@ -0,0 +1,52 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::get_attributes;
+use crate::cityjson::picking;
+use super::WasmMemBuffer;
+
+#[wasm_bindgen]
+pub fn get_attributes( buf: &WasmMemBuffer, selected_id: String ) -> JsValue {
+
+    let attributes = get_attributes::get_attributes( &buf.buffer, &selected_id ).expect("Error getting attributes");
+
+    super::to_js_json( &attributes )
+
+}
+
+/// Batch variant of get_attributes(): returns an array with the CityObjects of all ids (null for unknown IDs), in the order of ids.
+/// keys optionally limits the returned attributes, e.g. [ "measuredHeight", "roofType" ] for an attribute table.
+#[wasm_bindgen]
+pub fn get_attributes_batch( buf: &WasmMemBuffer, ids: Vec<String>, keys: Option<Vec<String>> ) -> Result<JsValue, JsValue> {
+
+    log!("Getting attributes of {} CityObjects...", ids.len());
+
+    let out = get_attributes::get_attributes_of( &buf.buffer, &ids, keys.as_deref() ).map_err( |e| JsValue::from_str( &format!( "Error parsing CityJSON buffer: {}", e ) ) )?;
+
+    Ok( super::to_js_json( &out ) )
+
+}
+
+#[wasm_bindgen]
+pub fn get_interval_and_id( query: u32 ) -> JsValue {
+
+    log!("Getting CityObject ID and triangle interval from clicked triangle index...");
+
+    match picking::pick( query ) {
+
+        Some( ( id, first, last ) ) => {
+
+            let res = format!("{} {} {}", id, first, last );
+
+            JsValue::from_str( &res )
+
+        },
+        None => {
+
+            log!("ID corresponding to triangle index not found!");
+            JsValue::from_str("")
+
+        },
+
+    }
+
+}
@@ -0,0 +1,21 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::gltf;
+use super::WasmMemBuffer;
+use super::mesh;
+
+/// Returns the model as a GLB file (Uint8Array), with the CityObject IDs and attributes as feature metadata.
+/// options: { lod, types, recenter } (see MeshOptions), or undefined.
+#[wasm_bindgen]
+pub fn export_glb( buf: &WasmMemBuffer, options: JsValue ) -> Result<js_sys::Uint8Array, JsValue> {
+
+    log!("Exporting GLB...");
+
+    let mesh = mesh::build( buf, options )?;
+    let out = gltf::write_glb( &mesh ).map_err( super::to_js_error )?;
+
+    log!("GLB with {} triangles and {} CityObjects exported", mesh.triangles.len() / 3, mesh.features.len());
+
+    Ok( js_sys::Uint8Array::from( &out[..] ) )
+
+}
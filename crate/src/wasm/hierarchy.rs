@@ -0,0 +1,52 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::hierarchy::{self, HIERARCHY};
+
+fn to_js( ids: &Vec<String> ) -> JsValue {
+
+    serde_wasm_bindgen::to_value( ids ).expect("Could not convert IDs into JsValue")
+
+}
+
+/// Returns the IDs of the CityObjects that have no parent
+#[wasm_bindgen]
+pub fn get_roots() -> JsValue {
+
+    to_js( &HIERARCHY.lock().unwrap().roots() )
+
+}
+
+/// Returns the IDs of the direct children of a CityObject
+#[wasm_bindgen]
+pub fn get_children( id: String ) -> JsValue {
+
+    to_js( &HIERARCHY.lock().unwrap().children_of( &id ) )
+
+}
+
+/// Returns the IDs of all ancestors of a CityObject, nearest first
+#[wasm_bindgen]
+pub fn get_parent_chain( id: String ) -> JsValue {
+
+    to_js( &HIERARCHY.lock().unwrap().parent_chain( &id ) )
+
+}
+
+/// Returns the IDs of the members of a CityObjectGroup
+#[wasm_bindgen]
+pub fn get_group_members( id: String ) -> JsValue {
+
+    to_js( &HIERARCHY.lock().unwrap().members_of( &id ) )
+
+}
+
+/// Returns the triangle intervals ([{ id, start, end }]) of a CityObject and all its descendants (or group members), so that they can be selected together.
+/// CityObjects without triangles are skipped.
+#[wasm_bindgen]
+pub fn get_descendant_intervals( id: String ) -> JsValue {
+
+    let intervals = hierarchy::descendant_intervals( &id );
+
+    serde_wasm_bindgen::to_value( &intervals ).expect("Could not convert triangle intervals into JsValue")
+
+}
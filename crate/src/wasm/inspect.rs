@@ -0,0 +1,24 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::inspect;
+use super::WasmMemBuffer;
+
+/// Returns a summary of the file: version, reference system, counts per CityObject type, geometry type and LoD, triangles, vertex stats and extent
+#[wasm_bindgen]
+pub fn get_info( buf: &WasmMemBuffer ) -> Result<JsValue, JsValue> {
+
+    let info = inspect::info( &buf.buffer ).map_err( super::to_js_error )?;
+
+    Ok( super::to_js_json( &info ) )
+
+}
+
+/// Check the file. Returns [{ severity: "Error" | "Warning", cityobject, message }].
+#[wasm_bindgen]
+pub fn validate_cityjson( buf: &WasmMemBuffer ) -> Result<JsValue, JsValue> {
+
+    let issues = inspect::validate( &buf.buffer ).map_err( super::to_js_error )?;
+
+    Ok( super::to_js_json( &issues ) )
+
+}
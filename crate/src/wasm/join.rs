@@ -0,0 +1,50 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::join;
+use super::WasmMemBuffer;
+
+/// Format of a table to join
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TableFormat {
+
+    // CSV with a header row. The delimiter (, ; or tab) is detected from the header.
+    Csv,
+    // A JSON array of objects, or an object with one object per key
+    Json,
+
+}
+
+impl From<TableFormat> for join::TableFormat {
+
+    fn from( format: TableFormat ) -> Self {
+
+        match format {
+            TableFormat::Csv => join::TableFormat::Csv,
+            TableFormat::Json => join::TableFormat::Json,
+        }
+
+    }
+
+}
+
+/// Join a table (CSV or JSON text) onto the CityObjects. table_key is the column in the table with the key, join_on the attribute of
+/// the CityObjects to match it with (null for the CityObject ID). Joined columns are added to the attributes everywhere attributes are
+/// used, except in the search index. Several tables can be joined after each other. Returns { rows, matched_rows, matched_objects, columns,
+/// unmatched, duplicates }.
+#[wasm_bindgen]
+pub fn join_table( buf: &WasmMemBuffer, data: String, format: TableFormat, table_key: String, join_on: Option<String> ) -> Result<JsValue, JsValue> {
+
+    let report = join::join_table( &buf.buffer, &data, format.into(), &table_key, join_on.as_deref() ).map_err( super::to_js_error )?;
+
+    Ok( serde_wasm_bindgen::to_value( &report ).expect("Could not convert join report into JsValue") )
+
+}
+
+/// Remove all joined columns
+#[wasm_bindgen]
+pub fn clear_joins() {
+
+    join::clear_joins();
+
+}
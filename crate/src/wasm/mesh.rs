@@ -0,0 +1,24 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::mesh::{Mesh, MeshOptions};
+use super::WasmMemBuffer;
+
+// Options of the exporters from JS: { lod: "2.2", types: [ "Building" ], recenter: true } (all optional), or undefined/null for the defaults
+fn mesh_options( options: JsValue ) -> Result<MeshOptions, JsValue> {
+
+    if options.is_undefined() || options.is_null() {
+        return Ok( MeshOptions::default() );
+    }
+
+    serde_wasm_bindgen::from_value( options ).map_err( |e| JsValue::from_str( &format!( "Invalid options: {}", e ) ) )
+
+}
+
+/// Build the mesh for an export
+pub fn build( buf: &WasmMemBuffer, options: JsValue ) -> Result<Mesh, JsValue> {
+
+    let options = mesh_options( options )?;
+
+    Mesh::build( &buf.buffer, &options ).map_err( super::to_js_error )
+
+}
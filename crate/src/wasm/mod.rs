@@ -0,0 +1,113 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::compression;
+use crate::logging::{self, Logger};
+
+///// The wasm-bindgen API of the viewer. The functions here only convert between JS and Rust types and call the core in cityjson/,
+///// which has the same module names. Errors of the core (Strings) are thrown as JS strings.
+
+mod to_bufferattributes;
+mod get_attributes;
+mod picking;
+mod hierarchy;
+mod filter;
+mod thematic;
+mod stats;
+mod search;
+mod join;
+mod edit;
+mod subset;
+mod mesh;
+mod gltf;
+mod export;
+mod tiles;
+mod inspect;
+mod cache;
+mod progress;
+mod model;
+mod push;
+
+// Logs to the browser console
+struct Console;
+
+impl Logger for Console {
+
+    fn log( &self, message: &str ) {
+
+        web_sys::console::log_1( &message.into() );
+
+    }
+
+}
+
+// Called by our JS entry point to run the example
+#[wasm_bindgen]
+pub fn init() -> Result<(), JsValue> {
+    // If the `console_error_panic_hook` feature is enabled this will set a panic hook, otherwise
+    // it will do nothing.
+    super::set_panic_hook();
+    console_error_panic_hook::set_once();
+
+    logging::set_logger( Box::new( Console ) );
+
+    Ok(())
+}
+
+
+
+// This allows the creation and direct use of buffers in WASM memory. It avoids having to copy an ArrayBuffer from JS to Rust, which would mean that the data is in memory twice.
+// See https://github.com/rustwasm/wasm-bindgen/issues/1079, https://github.com/rustwasm/wasm-bindgen/issues/1643
+// Code has been sourced from there.
+#[wasm_bindgen]
+pub struct WasmMemBuffer {
+    buffer: Vec<u8>,
+}
+
+// A gzip or zlib compressed file (e.g. .city.json.gz) is decompressed once it is in the buffer, so that all functions that take a
+// WasmMemBuffer work on the CityJSON text. To avoid having the decompressed text in memory as a whole, use a StreamParser instead.
+// Segments of a file for StreamParser.push_buffer() are created with WasmMemBuffer.segment(), which keeps the bytes as they are: the
+// first segment of a compressed file can't be decompressed on its own, and a segment of a JSON file can start like a zlib header.
+#[wasm_bindgen]
+impl WasmMemBuffer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(byte_length: u32, f: &js_sys::Function) -> Result<WasmMemBuffer, JsValue> {
+        let buffer = compression::decompress( fill( byte_length, f ) ).map_err( to_js_error )?;
+        Ok( Self { buffer } )
+    }
+
+    /// A buffer with a segment of a file, for StreamParser.push_buffer(). It isn't decompressed.
+    pub fn segment(byte_length: u32, f: &js_sys::Function) -> WasmMemBuffer {
+        Self { buffer: fill( byte_length, f ) }
+    }
+}
+
+// Let f fill a Uint8Array view on a new buffer in WASM memory
+fn fill(byte_length: u32, f: &js_sys::Function) -> Vec<u8> {
+    let mut buffer: Vec<u8> = Vec::with_capacity(byte_length as usize);
+    unsafe {
+        let array =
+            js_sys::Uint8Array::view_mut_raw(buffer.as_mut_ptr(),
+                                             byte_length as usize);
+        f.call1(&JsValue::NULL, &JsValue::from(array))
+            .expect("The callback function should not throw");
+        buffer.set_len(byte_length as usize);
+    }
+    buffer
+}
+
+// Convert to a JsValue through JSON, so that maps become plain JS objects (serde_wasm_bindgen turns them into JS Maps).
+// This is how wasm-bindgen's deprecated JsValue::from_serde() worked.
+fn to_js_json<T: serde::Serialize>( value: &T ) -> JsValue {
+
+    let json = serde_json::to_string( value ).expect("Could not serialize into JSON");
+
+    js_sys::JSON::parse( &json ).expect("Could not parse JSON into JsValue")
+
+}
+
+// Throw an error of the core as a JS string
+fn to_js_error( e: String ) -> JsValue {
+
+    JsValue::from_str( &e )
+
+}
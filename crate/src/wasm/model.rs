@@ -0,0 +1,85 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::compression;
+use crate::cityjson::model;
+use super::picking::PickingIndex;
+use super::progress;
+
+/// A parsed file for use in a Web Worker (see js/parser.worker.js). The constructor parses the bytes of the file; take_geometry() then
+/// returns the outputs as typed arrays that can be transferred to the main thread, while the model stays in the worker for picking and
+/// attribute queries. Call free() when the file is no longer needed.
+#[wasm_bindgen]
+pub struct CityModel {
+    model: model::CityModel,
+}
+
+#[wasm_bindgen]
+impl CityModel {
+
+    /// Parse the bytes of a CityJSON file, optionally gzip or zlib compressed. progress is an optional function( objects, bytes, total ),
+    /// as for parse_cityobjects(); bytes and total are those of the decompressed file.
+    #[wasm_bindgen(constructor)]
+    pub fn new( bytes: Vec<u8>, picking: PickingIndex, progress: Option<js_sys::Function> ) -> Result<CityModel, JsValue> {
+
+        let bytes = compression::decompress( bytes ).map_err( super::to_js_error )?;
+        let model = model::CityModel::parse( bytes, picking.into(), progress::callback( progress ), None ).map_err( super::to_js_error )?;
+
+        Ok( CityModel { model } )
+
+    }
+
+    /// Returns { triangles: Uint32Array, vertices: Uint32Array, groups: Map, transfer } (groups as from parse_cityobjects()), where transfer lists the ArrayBuffers of the typed arrays
+    /// for postMessage( message, transfer ). The arrays are copied out of WASM memory and freed here, so this can only be called once.
+    pub fn take_geometry( &mut self ) -> Result<JsValue, JsValue> {
+
+        let geometry = self.model.take_geometry().ok_or_else( || JsValue::from_str( "The geometry was already taken" ) )?;
+
+        let triangles = js_sys::Uint32Array::from( &geometry.triangles[..] );
+        let vertices = js_sys::Uint32Array::from( &geometry.vertices[..] );
+        let transfer = js_sys::Array::of2( &triangles.buffer(), &vertices.buffer() );
+
+        let res = js_sys::Object::new();
+        js_sys::Reflect::set( &res, &"triangles".into(), &triangles )?;
+        js_sys::Reflect::set( &res, &"vertices".into(), &vertices )?;
+        js_sys::Reflect::set( &res, &"groups".into(), &serde_wasm_bindgen::to_value( &geometry.groups ).expect("Could not convert groups into JsValue") )?;
+        js_sys::Reflect::set( &res, &"transfer".into(), &transfer )?;
+
+        Ok( res.into() )
+
+    }
+
+    /// Returns { id, start, end, parents } for a clicked triangle of this file, or null (like pick_cityobject())
+    pub fn pick( &self, query: u32 ) -> JsValue {
+
+        match self.model.pick( query ) {
+            Some( picked ) => super::to_js_json( &picked ),
+            None => JsValue::NULL,
+        }
+
+    }
+
+    pub fn get_attributes( &self, id: String ) -> Result<JsValue, JsValue> {
+
+        let attributes = self.model.attributes( &id ).map_err( super::to_js_error )?;
+
+        Ok( super::to_js_json( &attributes ) )
+
+    }
+
+    /// Like get_attributes_batch(), for this file
+    pub fn get_attributes_batch( &self, ids: Vec<String>, keys: Option<Vec<String>> ) -> Result<JsValue, JsValue> {
+
+        let out = self.model.attributes_of( &ids, keys.as_deref() ).map_err( super::to_js_error )?;
+
+        Ok( super::to_js_json( &out ) )
+
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn ids( &self ) -> JsValue {
+
+        serde_wasm_bindgen::to_value( &self.model.ids() ).expect("Could not convert IDs into JsValue")
+
+    }
+
+}
@@ -0,0 +1,64 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::picking;
+use crate::cityjson::to_bufferattributes::IDS;
+
+/// How the picking index (clicked triangle -> CityObject ID) is stored in WASM memory
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PickingIndex {
+
+    // Sorted triangle intervals with an aligned vector of IDs, searched with a binary search. Costs memory per CityObject.
+    Intervals,
+    // One object index per triangle, pointing into the ID table. Costs memory per triangle, but lookups are O(1).
+    Dense,
+
+}
+
+impl From<PickingIndex> for picking::PickingIndex {
+
+    fn from( index: PickingIndex ) -> Self {
+
+        match index {
+            PickingIndex::Intervals => picking::PickingIndex::Intervals,
+            PickingIndex::Dense => picking::PickingIndex::Dense,
+        }
+
+    }
+
+}
+
+/// Returns the dense picking index as a Uint32Array (empty if the file was parsed with PickingIndex::Intervals).
+/// Together with get_ids() it can be used for lookups in JS or uploaded to the GPU as a vertex/texture attribute.
+#[wasm_bindgen]
+pub fn get_object_index() -> js_sys::Uint32Array {
+
+    let object_index = picking::OBJECT_INDEX.lock().unwrap();
+
+    js_sys::Uint32Array::from( &object_index[..] )
+
+}
+
+/// Returns the table of CityObject IDs that the picking index points into
+#[wasm_bindgen]
+pub fn get_ids() -> JsValue {
+
+    let ids = IDS.lock().unwrap();
+
+    serde_wasm_bindgen::to_value( &*ids ).expect("Could not convert IDs into JsValue")
+
+}
+
+/// Like get_interval_and_id(), but returns an object { id, start, end, parents } that also contains the parents of the clicked CityObject (e.g. the Building of a BuildingPart).
+/// Returns null if the triangle does not belong to a CityObject.
+#[wasm_bindgen]
+pub fn pick_cityobject( query: u32 ) -> JsValue {
+
+    match picking::pick_cityobject( query ) {
+
+        Some( picked ) => serde_wasm_bindgen::to_value( &picked ).expect("Could not convert picked CityObject into JsValue"),
+        None => JsValue::NULL,
+
+    }
+
+}
@@ -0,0 +1,62 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::progress::{self, CancelToken, Progress};
+
+/// Cancels a running parse: pass it to parse_cityobjects_cancellable() and call cancel(), e.g. from the progress callback
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct CancellationToken {
+    token: CancelToken,
+}
+
+#[wasm_bindgen]
+impl CancellationToken {
+
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+
+        Self::default()
+
+    }
+
+    pub fn cancel( &self ) {
+
+        self.token.cancel();
+
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn cancelled( &self ) -> bool {
+
+        self.token.is_cancelled()
+
+    }
+
+}
+
+impl CancellationToken {
+
+    // The core token, which shares its state with this one
+    pub fn token( &self ) -> CancelToken {
+
+        self.token.clone()
+
+    }
+
+}
+
+// Call a JS function( objects, bytes, total ) with the progress. A callback that throws or returns false cancels the parse.
+pub fn callback( f: Option<js_sys::Function> ) -> Option<progress::Callback> {
+
+    let f = f?;
+
+    Some( Box::new( move | p: Progress | {
+
+        match f.call3( &JsValue::NULL, &JsValue::from( p.objects ), &JsValue::from( p.bytes as f64 ), &JsValue::from( p.total as f64 ) ) {
+            Ok( res ) => res != JsValue::FALSE,
+            Err( _ ) => false,
+        }
+
+    } ) )
+
+}
@@ -0,0 +1,124 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::push;
+use super::WasmMemBuffer;
+use super::picking::PickingIndex;
+
+/// Parses a CityJSON file from chunks, e.g. the Uint8Arrays of a fetch stream (response.body.getReader()), without keeping the whole file
+/// in WASM memory. push() every chunk, optionally take_batch() to show the geometry that arrived so far, and finish() at the end, which
+/// returns { triangles, groups, vertices } of the whole file and stores the picking index like parse_cityobjects().
+/// For files that don't fit in WASM memory, create it with StreamParser.with_batch_size() and take the output with next_batch().
+#[wasm_bindgen]
+pub struct StreamParser {
+    parser: push::PushParser,
+}
+
+// { triangles: Uint32Array, groups: Map, vertices: Uint32Array }, with the groups as from parse_cityobjects()
+fn geometry( triangles: &[u32], groups: &impl serde::Serialize, vertices: &[u32] ) -> Result<JsValue, JsValue> {
+
+    let res = js_sys::Object::new();
+
+    js_sys::Reflect::set( &res, &"triangles".into(), &js_sys::Uint32Array::from( triangles ) )?;
+    js_sys::Reflect::set( &res, &"groups".into(), &serde_wasm_bindgen::to_value( groups ).expect("Could not convert groups into JsValue") )?;
+    js_sys::Reflect::set( &res, &"vertices".into(), &js_sys::Uint32Array::from( vertices ) )?;
+
+    Ok( res.into() )
+
+}
+
+#[wasm_bindgen]
+impl StreamParser {
+
+    #[wasm_bindgen(constructor)]
+    pub fn new( picking: PickingIndex ) -> StreamParser {
+
+        StreamParser { parser: push::PushParser::new( picking.into() ) }
+
+    }
+
+    /// A parser that doesn't keep the output: next_batch() returns it in batches of about batch_size triangle indices or vertex
+    /// coordinates, and finish() returns the last batch. The triangles of all batches index into the vertices of all batches.
+    pub fn with_batch_size( picking: PickingIndex, batch_size: u32 ) -> StreamParser {
+
+        StreamParser { parser: push::PushParser::with_batch_size( picking.into(), batch_size as usize ) }
+
+    }
+
+    /// Process the next chunk. The chunk is copied into WASM memory and released once its CityObjects are complete.
+    pub fn push( &mut self, chunk: &[u8] ) -> Result<(), JsValue> {
+
+        self.parser.push( chunk ).map_err( super::to_js_error )
+
+    }
+
+    /// Process the next segment of the file, a WasmMemBuffer.segment() with a slice of a File. The buffer is freed.
+    pub fn push_buffer( &mut self, buf: WasmMemBuffer ) -> Result<(), JsValue> {
+
+        self.parser.push_owned( buf.buffer ).map_err( super::to_js_error )
+
+    }
+
+    /// With a batch size: the next full batch { triangles, groups, vertices }, or null. Call it after every push(), until it returns null.
+    pub fn next_batch( &mut self ) -> Result<JsValue, JsValue> {
+
+        match self.parser.next_batch() {
+            Some( batch ) => geometry( &batch.triangles, &batch.groups, &batch.vertices ),
+            None => Ok( JsValue::NULL ),
+        }
+
+    }
+
+    /// { triangles, groups, vertices } that arrived since the previous batch. Groups are [start, count] within these triangles, and
+    /// the vertices are appended to those of the previous batches, so the triangles index into all vertices so far.
+    pub fn take_batch( &mut self ) -> Result<JsValue, JsValue> {
+
+        let batch = self.parser.take_batch();
+
+        geometry( &batch.triangles, &batch.groups, &batch.vertices )
+
+    }
+
+    /// Check that the whole document was pushed and return { triangles, groups, vertices } of the whole file. This frees the parser.
+    pub fn finish( self ) -> Result<JsValue, JsValue> {
+
+        let res = self.parser.finish().map_err( super::to_js_error )?;
+
+        geometry( &res.triangles, &res.groups, &res.vertices )
+
+    }
+
+    /// Bytes that are kept until the CityObject they belong to is complete
+    #[wasm_bindgen(getter)]
+    pub fn pending( &self ) -> u32 {
+
+        self.parser.pending() as u32
+
+    }
+
+    /// Bytes that were processed and released
+    #[wasm_bindgen(getter)]
+    pub fn released( &self ) -> f64 {
+
+        self.parser.released() as f64
+
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn objects( &self ) -> u32 {
+
+        self.parser.objects()
+
+    }
+
+}
+
+/// Parse a CityJSON Text Sequence (.city.jsonl: a header and one CityJSONFeature per line) that is in a WasmMemBuffer into
+/// { triangles, groups, vertices }, with the features merged like the CityObjects of one file. Also works for a single CityJSON document.
+#[wasm_bindgen]
+pub fn parse_cityjsonseq( buf: &WasmMemBuffer, picking: PickingIndex ) -> Result<JsValue, JsValue> {
+
+    let res = push::parse_buffer( &buf.buffer, picking.into() ).map_err( super::to_js_error )?;
+
+    geometry( &res.triangles, &res.groups, &res.vertices )
+
+}
@@ -0,0 +1,48 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::search;
+
+/// How a search query is matched against the indexed values
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SearchMode {
+
+    // The value or one of its words starts with the query
+    Prefix,
+    // The value contains the query
+    Substring,
+
+}
+
+impl From<SearchMode> for search::SearchMode {
+
+    fn from( mode: SearchMode ) -> Self {
+
+        match mode {
+            SearchMode::Prefix => search::SearchMode::Prefix,
+            SearchMode::Substring => search::SearchMode::Substring,
+        }
+
+    }
+
+}
+
+/// Build a search index while parsing the next file, over the CityObject IDs and the given fields, e.g. [ "address", "identificatie" ].
+/// Must be called before parse_cityobjects(). Pass null to stop building an index.
+#[wasm_bindgen]
+pub fn set_search_fields( fields: Option<Vec<String>> ) {
+
+    search::set_search_fields( fields );
+
+}
+
+/// Search the index of the last parsed file. Returns [{ id, field, value, score, start, end }], best match first,
+/// with start/end the triangle interval of the CityObject (as returned by get_interval_and_id()) if it has triangles.
+#[wasm_bindgen]
+pub fn search_cityobjects( query: String, mode: SearchMode, limit: u32 ) -> JsValue {
+
+    let results = search::search_cityobjects( &query, mode.into(), limit as usize );
+
+    serde_wasm_bindgen::to_value( &results ).expect("Could not convert search results into JsValue")
+
+}
@@ -0,0 +1,19 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::stats;
+use super::WasmMemBuffer;
+
+/// Returns { object_counts, attributes, by_type } describing the attributes of the CityObjects: per attribute the count, the types,
+/// the null count, min/max/mean of numbers and the top_n most frequent string values. by_type has the same statistics per CityObject type.
+#[wasm_bindgen]
+pub fn get_attribute_schema( buf: &WasmMemBuffer, top_n: u32 ) -> Result<JsValue, JsValue> {
+
+    log!("Scanning attributes...");
+
+    let schema = stats::attribute_schema( &buf.buffer, top_n as usize ).map_err( |e| JsValue::from_str( &format!( "Error parsing CityJSON buffer: {}", e ) ) )?;
+
+    log!("{} attributes found", schema.attributes.len());
+
+    Ok( super::to_js_json( &schema ) )
+
+}
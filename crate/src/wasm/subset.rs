@@ -0,0 +1,16 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::subset;
+use super::WasmMemBuffer;
+
+/// Returns a new CityJSON document (as a Uint8Array) with only the given CityObjects, optionally with their children and parents
+#[wasm_bindgen]
+pub fn export_subset( buf: &WasmMemBuffer, ids: Vec<String>, include_children: bool, include_parents: bool ) -> Result<js_sys::Uint8Array, JsValue> {
+
+    log!("Exporting {} CityObjects...", ids.len());
+
+    let out = subset::subset( &buf.buffer, &ids, include_children, include_parents ).map_err( super::to_js_error )?;
+
+    Ok( js_sys::Uint8Array::from( &out[..] ) )
+
+}
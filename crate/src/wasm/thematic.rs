@@ -0,0 +1,61 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::thematic;
+use super::WasmMemBuffer;
+
+/// How attribute values are divided into classes
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Classification {
+
+    // Classes of equal width between the minimum and the maximum value
+    EqualInterval,
+    // Classes with (about) the same amount of CityObjects
+    Quantile,
+    // One class per distinct value, most frequent first
+    Categorical,
+
+}
+
+impl From<Classification> for thematic::Classification {
+
+    fn from( classification: Classification ) -> Self {
+
+        match classification {
+            Classification::EqualInterval => thematic::Classification::EqualInterval,
+            Classification::Quantile => thematic::Classification::Quantile,
+            Classification::Categorical => thematic::Classification::Categorical,
+        }
+
+    }
+
+}
+
+/// Classify the CityObjects by an attribute and return { legend, groups, classes }.
+/// groups are [start, count, legend index] for BufferGeometry.addGroup() with one material per legend entry, classes maps CityObject IDs to legend entries.
+/// palette is a list of "#rrggbb" colors, classes the amount of classes (for Categorical: the maximum amount of categories, 0 for all).
+#[wasm_bindgen]
+pub fn thematic_classes( buf: &WasmMemBuffer, attribute: String, classification: Classification, classes: u32, palette: Vec<String> ) -> Result<JsValue, JsValue> {
+
+    let res = thematic::thematic_classes( &buf.buffer, &attribute, classification.into(), classes as usize, &palette ).map_err( super::to_js_error )?;
+
+    Ok( super::to_js_json( &res ) )
+
+}
+
+/// Same classification as thematic_classes(), but returns { legend, colors } with colors a Float32Array of RGB values per vertex,
+/// for a color BufferAttribute. vertex_count is the amount of vertices (the length of the array from parse_vertices() / 3).
+#[wasm_bindgen]
+pub fn thematic_vertex_colors( buf: &WasmMemBuffer, attribute: String, classification: Classification, classes: u32, palette: Vec<String>, vertex_count: u32 ) -> Result<JsValue, JsValue> {
+
+    let res = thematic::thematic_vertex_colors( &buf.buffer, &attribute, classification.into(), classes as usize, &palette, vertex_count as usize ).map_err( super::to_js_error )?;
+
+    let out = js_sys::Object::new();
+    let legend = serde_wasm_bindgen::to_value( &res.legend ).expect("Could not convert legend into JsValue");
+
+    js_sys::Reflect::set( &out, &JsValue::from_str( "legend" ), &legend )?;
+    js_sys::Reflect::set( &out, &JsValue::from_str( "colors" ), &js_sys::Float32Array::from( &res.colors[..] ) )?;
+
+    Ok( out.into() )
+
+}
@@ -0,0 +1,59 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::tiles;
+use super::WasmMemBuffer;
+use super::mesh;
+
+/// How tiles are subdivided
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TileSubdivision {
+
+    // Split in x and y
+    Quadtree,
+    // Split in x, y and z
+    Octree,
+
+}
+
+impl From<TileSubdivision> for tiles::TileSubdivision {
+
+    fn from( subdivision: TileSubdivision ) -> Self {
+
+        match subdivision {
+            TileSubdivision::Quadtree => tiles::TileSubdivision::Quadtree,
+            TileSubdivision::Octree => tiles::TileSubdivision::Octree,
+        }
+
+    }
+
+}
+
+/// Generate 3D Tiles. Returns an object with a Uint8Array per file path ("tileset.json", "tiles/r0.glb", ...).
+/// max_features is the maximum amount of CityObjects per tile; options: { lod, types, recenter } (see MeshOptions), or undefined.
+/// georeference: { longitude, latitude, height } of the center of the model (see Georeference), or undefined for files in ECEF.
+#[wasm_bindgen]
+pub fn generate_tileset( buf: &WasmMemBuffer, subdivision: TileSubdivision, max_features: u32, options: JsValue, georeference: JsValue ) -> Result<JsValue, JsValue> {
+
+    log!("Generating tileset...");
+
+    let georeference: Option<tiles::Georeference> = if georeference.is_undefined() || georeference.is_null() {
+        None
+    } else {
+        Some( serde_wasm_bindgen::from_value( georeference ).map_err( |e| JsValue::from_str( &format!( "Invalid georeference: {}", e ) ) )? )
+    };
+
+    let mesh = mesh::build( buf, options )?;
+    let files = tiles::write_tileset( &mesh, subdivision.into(), max_features as usize, georeference.as_ref() ).map_err( super::to_js_error )?;
+
+    log!("Tileset with {} tiles generated", files.len() - 1);
+
+    let res = js_sys::Object::new();
+
+    for ( path, content ) in files {
+        js_sys::Reflect::set( &res, &JsValue::from_str( &path ), &js_sys::Uint8Array::from( &content[..] ) )?;
+    }
+
+    Ok( res.into() )
+
+}
@@ -0,0 +1,51 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cityjson::to_bufferattributes;
+use super::WasmMemBuffer;
+use super::picking::PickingIndex;
+use super::progress::{self, CancellationToken};
+
+/// Parse the CityObjects into { triangles, groups }. Optionally, progress is a function( objects, bytes, total ) that is called for every
+/// percent of the buffer; if it returns false (or throws) parsing stops and "Parsing cancelled" is thrown.
+#[wasm_bindgen]
+pub fn parse_cityobjects( buf: &WasmMemBuffer, progress: Option<js_sys::Function> ) -> Result<JsValue, JsValue> {
+
+    parse_cityobjects_with_picking( buf, PickingIndex::Intervals, progress )
+
+}
+
+/// Same as parse_cityobjects(), but lets the caller choose how the picking index (triangle -> CityObject ID) is stored
+#[wasm_bindgen]
+pub fn parse_cityobjects_with_picking( buf: &WasmMemBuffer, picking: PickingIndex, progress: Option<js_sys::Function> ) -> Result<JsValue, JsValue> {
+
+    let res = match progress {
+        None => to_bufferattributes::triangulate( &buf.buffer, picking.into() ).map_err( |e| format!( "Error parsing CityJSON buffer: {}", e ) ),
+        progress => to_bufferattributes::triangulate_with_progress( &buf.buffer, picking.into(), progress::callback( progress ), None ),
+    }.map_err( super::to_js_error )?;
+
+    // Parse into JsValue to be able to return it to JS
+    Ok( serde_wasm_bindgen::to_value( &res ).expect("Could not convert serde_json::Value into JsValue") )
+
+}
+
+/// Same as parse_cityobjects_with_picking(), with a CancellationToken that is checked after every CityObject. Calling token.cancel()
+/// (e.g. from the progress callback) stops parsing and throws "Parsing cancelled"; the picking index of the previous file is kept.
+#[wasm_bindgen]
+pub fn parse_cityobjects_cancellable( buf: &WasmMemBuffer, picking: PickingIndex, progress: Option<js_sys::Function>, token: &CancellationToken ) -> Result<JsValue, JsValue> {
+
+    let res = to_bufferattributes::triangulate_with_progress( &buf.buffer, picking.into(), progress::callback( progress ), Some( token.token() ) )
+        .map_err( super::to_js_error )?;
+
+    Ok( serde_wasm_bindgen::to_value( &res ).expect("Could not convert serde_json::Value into JsValue") )
+
+}
+
+#[wasm_bindgen]
+pub fn parse_vertices( buf: &WasmMemBuffer ) -> JsValue {
+
+    let vertices = to_bufferattributes::flatten_vertices( &buf.buffer ).expect("Error parsing CityJSON buffer");
+
+    // Parse into JsValue to be able to return it to JS
+    serde_wasm_bindgen::to_value(&vertices).expect("Could not convert serde_json::Value into JsValue")
+
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use serde_json::json;
+
+use rust_parcel::cityjson::cache::{self, CacheView, GeometryCache, VERSION};
+use rust_parcel::cityjson::edit;
+use rust_parcel::cityjson::get_attributes::get_attributes;
+use rust_parcel::cityjson::hierarchy::HIERARCHY;
+use rust_parcel::cityjson::join::{self, TableFormat};
+use rust_parcel::cityjson::picking::{self, PickingIndex};
+use rust_parcel::cityjson::to_bufferattributes::{self, IDS, INTERVALS};
+
+const FIXTURES: [&str; 5] = [ "minimal", "edge_cases", "3dbag_sample", "denhaag_sample", "railway_sample" ];
+
+// Overwrite a header field at a byte offset
+fn set_u32( bytes: &mut [u8], offset: usize, value: u32 ) {
+
+    bytes[ offset..offset + 4 ].copy_from_slice( &value.to_le_bytes() );
+
+}
+
+#[test]
+fn round_trip() {
+
+    let _guard = common::setup();
+
+    for name in &FIXTURES {
+
+        let buffer = common::fixture( &format!( "{}.json", name ) );
+        let cache = GeometryCache::build( &buffer ).unwrap();
+        let bytes = cache.to_bytes().unwrap();
+
+        assert_eq!( &bytes[ 0..4 ], cache::MAGIC );
+        assert_eq!( bytes.len() % 8, 0 );
+        assert_eq!( GeometryCache::from_bytes( &bytes ).unwrap(), cache, "{}", name );
+
+        // The same outputs as parsing the file
+        let groups = to_bufferattributes::triangulate( &buffer, PickingIndex::Intervals ).unwrap();
+
+        assert_eq!( cache.triangles, groups.triangles );
+        assert_eq!( cache.vertices, to_bufferattributes::flatten_vertices( &buffer ).unwrap() );
+        assert_eq!( cache.ids, *IDS.lock().unwrap() );
+        assert_eq!( cache.intervals, *INTERVALS.lock().unwrap() );
+        assert_eq!( cache.extents.len(), cache.ids.len() );
+
+        for ( co_type, start, count ) in &cache.groups {
+            assert_eq!( groups.groups[ co_type ], vec![ *start, *count ] );
+        }
+
+    }
+
+}
+
+#[test]
+fn rejected() {
+
+    let _guard = common::setup();
+
+    let bytes = GeometryCache::build( &common::fixture( "3dbag_sample.json" ) ).unwrap().to_bytes().unwrap();
+    let error = | bytes: &[u8] | GeometryCache::from_bytes( bytes ).unwrap_err();
+
+    assert!( error( b"{\"type\": \"CityJSON\"}" ).contains( "Not a geometry cache" ) );
+
+    // Another version
+    let mut other = bytes.clone();
+    set_u32( &mut other, 4, VERSION + 1 );
+    assert!( error( &other ).contains( "version" ) );
+
+    // Truncated, or with bytes appended
+    assert!( error( &bytes[ ..bytes.len() - 8 ] ).contains( "truncated" ) );
+
+    let mut longer = bytes.clone();
+    longer.extend_from_slice( &[ 0; 8 ] );
+    assert!( error( &longer ).contains( "truncated" ) );
+
+    // Sections that don't add up to the length
+    let mut sections = bytes.clone();
+    set_u32( &mut sections, 12, 3 );
+    assert!( error( &sections ).contains( "sections" ) );
+
+    // A changed byte in the body
+    let mut corrupt = bytes.clone();
+    let last = corrupt.len() - 20;
+    corrupt[ last ] ^= 1;
+    assert!( error( &corrupt ).contains( "Checksum" ) );
+
+}
+
+#[test]
+fn restore_picking() {
+
+    let _guard = common::setup();
+
+    let buffer = common::fixture( "edge_cases.json" );
+    let bytes = GeometryCache::build( &buffer ).unwrap().to_bytes().unwrap();
+
+    // Parsed, some CityObjects report their parents
+    to_bufferattributes::triangulate( &buffer, PickingIndex::Dense ).unwrap();
+    let triangles = picking::all_triangle_ranges().last().map( |r| r.end + 1 ).unwrap();
+
+    assert!( ( 0..triangles ).any( |t| !picking::pick_cityobject( t ).unwrap().parents.is_empty() ) );
+
+    // Loaded from the cache, the same triangles are picked, but the parents of the parsed file are gone
+    let expected: Vec<_> = ( 0..triangles ).map( picking::pick ).collect();
+    let ids = cache::restore_picking( &CacheView::parse( &bytes ).unwrap() ).unwrap();
+
+    assert_eq!( ids, *IDS.lock().unwrap() );
+    assert_eq!( ( 0..triangles ).map( picking::pick ).collect::<Vec<_>>(), expected );
+    assert!( ( 0..triangles ).all( |t| picking::pick_cityobject( t ).unwrap().parents.is_empty() ) );
+
+    // Intervals that don't match the IDs are not installed
+    let mut cache = GeometryCache::from_bytes( &bytes ).unwrap();
+    cache.intervals.pop();
+    let invalid = cache.to_bytes().unwrap();
+
+    assert!( cache::restore_picking( &CacheView::parse( &invalid ).unwrap() ).is_err() );
+    assert_eq!( ( 0..triangles ).map( picking::pick ).collect::<Vec<_>>(), expected );
+
+}
+
+#[test]
+fn build_leaves_the_shown_file_alone() {
+
+    let _guard = common::setup();
+
+    // The file that is shown, with a dense picking index, an edit and a joined column
+    let shown = common::fixture( "edge_cases.json" );
+
+    to_bufferattributes::triangulate( &shown, PickingIndex::Dense ).unwrap();
+    edit::set_attribute( &[ "quad-and-hole".to_string() ], "name", json!( "edited" ) );
+    join::join_table( &shown, "id,owner\nmultisolid,city", TableFormat::Csv, "id", None ).unwrap();
+
+    let triangles = picking::all_triangle_ranges().last().map( |r| r.end + 1 ).unwrap();
+    let picked: Vec<_> = ( 0..triangles ).map( picking::pick_cityobject ).collect();
+    let roots = HIERARCHY.lock().unwrap().roots();
+
+    // Writing a cache of another file
+    let cache = GeometryCache::build( &common::fixture( "3dbag_sample.json" ) ).unwrap();
+    assert!( !cache.ids.is_empty() );
+
+    // Copied out first, so that a failure doesn't poison the lock for the other tests
+    let current = *picking::PICKING.lock().unwrap();
+    assert_eq!( current, PickingIndex::Dense );
+    assert_eq!( ( 0..triangles ).map( picking::pick_cityobject ).collect::<Vec<_>>(), picked );
+    assert_eq!( HIERARCHY.lock().unwrap().roots(), roots );
+    assert_eq!( get_attributes( &shown, "quad-and-hole" ).unwrap()[ "attributes" ][ "name" ], "edited" );
+    assert_eq!( get_attributes( &shown, "multisolid" ).unwrap()[ "attributes" ][ "owner" ], "city" );
+
+    edit::clear_edits();
+    join::clear_joins();
+
+}
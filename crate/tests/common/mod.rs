@@ -0,0 +1,80 @@
+// Not every test file uses every helper
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+use serde_json::Value;
+
+use rust_parcel::logging::{self, Silent};
+
+///// Helpers for the integration tests: fixtures, golden outputs, and a lock for the global state of the core
+
+// The core keeps the picking index, hierarchy etc. of the last parsed file globally, so tests that parse must not run at the same time
+static GLOBAL_STATE: Mutex<()> = Mutex::new(());
+
+/// Take the lock on the global state of the core and silence the logging. Keep the guard for the whole test.
+pub fn setup() -> MutexGuard<'static, ()> {
+
+    let guard = GLOBAL_STATE.lock().unwrap_or_else( |e| e.into_inner() );
+
+    logging::set_logger( Box::new( Silent ) );
+
+    guard
+
+}
+
+fn path( dir: &str, name: &str ) -> PathBuf {
+
+    PathBuf::from( env!( "CARGO_MANIFEST_DIR" ) ).join( "tests" ).join( dir ).join( name )
+
+}
+
+/// Read a file from tests/fixtures
+pub fn fixture( name: &str ) -> Vec<u8> {
+
+    let path = path( "fixtures", name );
+
+    fs::read( &path ).unwrap_or_else( |e| panic!( "Could not read {}: {}", path.display(), e ) )
+
+}
+
+// Objects on the first two levels get a line per member, everything deeper is written compactly, so that arrays of numbers stay on one line
+fn format( value: &Value, depth: usize ) -> String {
+
+    match value {
+        Value::Object( map ) if depth < 2 && !map.is_empty() => {
+
+            let indent = "  ".repeat( depth + 1 );
+            let members: Vec<String> = map.iter().map( |( k, v )| format!( "{}{}: {}", indent, Value::String( k.clone() ), format( v, depth + 1 ) ) ).collect();
+
+            format!( "{{\n{}\n{}}}", members.join( ",\n" ), "  ".repeat( depth ) )
+
+        },
+        value => value.to_string(),
+    }
+
+}
+
+/// Compare with tests/golden/<name>.json. Run with UPDATE_GOLDEN=1 to (re)write the golden file instead, and review the diff.
+pub fn assert_golden( name: &str, actual: &Value ) {
+
+    let path = path( "golden", &format!( "{}.json", name ) );
+
+    if env::var_os( "UPDATE_GOLDEN" ).is_some() {
+        fs::write( &path, format( actual, 0 ) + "\n" ).unwrap();
+        return;
+    }
+
+    let text = fs::read_to_string( &path ).unwrap_or_else( |e| panic!( "Could not read {} (run with UPDATE_GOLDEN=1 to create it): {}", path.display(), e ) );
+    let expected: Value = serde_json::from_str( &text ).unwrap();
+
+    for key in expected.as_object().unwrap().keys() {
+        assert_eq!( actual[ key ], expected[ key ], "{} differs from {}", key, path.display() );
+    }
+
+    assert_eq!( *actual, expected, "Output differs from {}", path.display() );
+
+}
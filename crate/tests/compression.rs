@@ -0,0 +1,123 @@
+mod common;
+
+use rust_parcel::cityjson::compression::{self, Compression};
+use rust_parcel::cityjson::picking::PickingIndex;
+use rust_parcel::cityjson::push::{self, PushParser};
+use rust_parcel::cityjson::to_bufferattributes;
+
+// Compressed fixtures and the files they were compressed from
+const FIXTURES: [( &str, &str, Compression ); 3] = [
+    ( "3dbag_sample.city.json.gz", "3dbag_sample.json", Compression::Gzip ),
+    ( "denhaag_sample.json.zz", "denhaag_sample.json", Compression::Zlib ),
+    ( "railway_sample.city.jsonl.gz", "railway_sample.city.jsonl", Compression::Gzip ),
+];
+
+#[test]
+fn detect() {
+
+    for ( compressed, plain, compression ) in &FIXTURES {
+        assert_eq!( compression::detect( &common::fixture( compressed ) ), *compression );
+        assert_eq!( compression::detect( &common::fixture( plain ) ), Compression::None );
+    }
+
+    assert_eq!( compression::detect( b"{" ), Compression::None );
+    assert_eq!( compression::detect( b" \n{}" ), Compression::None );
+
+}
+
+#[test]
+fn compressed_gives_the_same_output() {
+
+    let _guard = common::setup();
+
+    for ( compressed, plain, _ ) in &FIXTURES {
+
+        let compressed = common::fixture( compressed );
+        let plain = common::fixture( plain );
+
+        assert_eq!( compression::decompress( compressed.clone() ).unwrap(), plain );
+        assert_eq!( compression::decompress( plain.clone() ).unwrap(), plain );
+
+        let expected = push::parse_buffer( &plain, PickingIndex::Intervals ).unwrap();
+        let res = push::parse_buffer( &compressed, PickingIndex::Intervals ).unwrap();
+
+        assert_eq!( res.triangles, expected.triangles );
+        assert_eq!( res.groups, expected.groups );
+        assert_eq!( res.vertices, expected.vertices );
+
+        // Streamed in small chunks, the decompressed text is never kept as a whole
+        let mut parser = PushParser::new( PickingIndex::Intervals );
+        let mut max_pending = 0;
+
+        for chunk in compressed.chunks( 16 ) {
+            parser.push( chunk ).unwrap();
+            max_pending = max_pending.max( parser.pending() );
+        }
+
+        assert!( max_pending < plain.len() / 2 );
+        assert_eq!( parser.finish().unwrap().triangles, expected.triangles );
+
+    }
+
+    // The parse of a whole document after decompressing it
+    let buffer = compression::decompress( common::fixture( "3dbag_sample.city.json.gz" ) ).unwrap();
+    let expected = to_bufferattributes::triangulate( &common::fixture( "3dbag_sample.json" ), PickingIndex::Intervals ).unwrap();
+
+    assert_eq!( to_bufferattributes::triangulate( &buffer, PickingIndex::Intervals ).unwrap().triangles, expected.triangles );
+
+}
+
+#[test]
+fn corrupt_input() {
+
+    let _guard = common::setup();
+
+    let mut compressed = common::fixture( "3dbag_sample.city.json.gz" );
+
+    // Truncated
+    assert!( push::parse_buffer( &compressed[ ..compressed.len() / 2 ], PickingIndex::Intervals ).is_err() );
+
+    // Corrupted deflate data
+    let middle = compressed.len() / 2;
+    compressed[ middle.. ].iter_mut().for_each( |b| *b = !*b );
+
+    assert!( compression::decompress( compressed.clone() ).is_err() );
+    assert!( push::parse_buffer( &compressed, PickingIndex::Intervals ).is_err() );
+
+}
+
+#[test]
+fn segments_are_not_decompressed() {
+
+    let _guard = common::setup();
+
+    let buffer = common::fixture( "3dbag_sample.json" );
+    let expected = push::parse_buffer( &buffer, PickingIndex::Intervals ).unwrap();
+
+    // A segment of a JSON file that starts in the middle of a number can look like a zlib header ("80": 0x3830 is a multiple of 31)
+    let split = buffer.windows( 2 ).position( |w| w == b"80" ).unwrap();
+
+    assert_eq!( compression::detect( &buffer[ split.. ] ), Compression::Zlib );
+
+    // The segments of a plain file are parsed as they are
+    let segments = vec![ buffer[ ..split ].to_vec(), buffer[ split.. ].to_vec() ];
+    let mut triangles = Vec::new();
+
+    push::parse_segments( segments, PickingIndex::Intervals, 1 << 20, |b| triangles.extend( b.triangles ) ).unwrap();
+
+    assert_eq!( triangles, expected.triangles );
+
+    // And so are the segments of a compressed file, of which the first one can't be decompressed on its own
+    let compressed = common::fixture( "3dbag_sample.city.json.gz" );
+    let half = compressed.len() / 2;
+
+    assert!( compression::decompress( compressed[ ..half ].to_vec() ).is_err() );
+
+    let segments = vec![ compressed[ ..half ].to_vec(), compressed[ half.. ].to_vec() ];
+    let mut triangles = Vec::new();
+
+    push::parse_segments( segments, PickingIndex::Intervals, 1 << 20, |b| triangles.extend( b.triangles ) ).unwrap();
+
+    assert_eq!( triangles, expected.triangles );
+
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use rust_parcel::cityjson::edit::{self, Edit, Edits};
+use rust_parcel::cityjson::get_attributes::get_attributes;
+
+///// Attribute edits: how they are applied when reading, and writing them back into the original document
+
+// Formatting, key order, number notation and non-ASCII text that a round trip through serde_json would change
+const DOCUMENT: &str = r#"{
+  "type": "CityJSON", "version": "1.1",
+  "CityObjects": {
+    "a": { "type": "Building", "attributes": { "height": 1.50, "name": "Gebäude", "code": 1e3 }, "geometry": [] },
+    "b": {"type":"Building","attributes":{"z":1,"a":2}},
+    "c": { "type": "Road" },
+    "d": { "type": "Road", "attributes": null },
+    "e": {}
+  },
+  "vertices": [ [ 0, 0, 0 ] ]
+}"#;
+
+fn edits( edits: Vec<( &str, Edit )> ) -> Edits {
+
+    let mut out: Edits = HashMap::new();
+
+    for ( id, edit ) in edits {
+        out.entry( id.to_string() ).or_default().push( edit );
+    }
+
+    out
+
+}
+
+// A CityObject of the written document
+fn cityobject( out: &[u8], id: &str ) -> Value {
+
+    serde_json::from_slice::<Value>( out ).unwrap()[ "CityObjects" ][ id ].clone()
+
+}
+
+#[test]
+fn unedited_bytes_are_kept() {
+
+    let _guard = common::setup();
+
+    // Without edits the output is the input
+    let out = edit::write_cityjson( DOCUMENT.as_bytes(), &HashMap::new(), None ).unwrap();
+    assert_eq!( out, DOCUMENT.as_bytes() );
+
+    let out = edit::write_cityjson( DOCUMENT.as_bytes(), &edits( vec![ ( "b", Edit::Set( "m".to_string(), json!( 3 ) ) ) ] ), None ).unwrap();
+    let out = String::from_utf8( out ).unwrap();
+
+    // Only the attributes of "b" are rewritten, in their original order, and everything else is byte for byte the same
+    let original = r#""attributes":{"z":1,"a":2}"#;
+    let edited = r#""attributes":{"z":1,"a":2,"m":3}"#;
+
+    assert_eq!( out, DOCUMENT.replace( original, edited ) );
+
+}
+
+#[test]
+fn only_edited_members_change() {
+
+    let _guard = common::setup();
+
+    let edits = edits( vec![
+        ( "a", Edit::Set( "height".to_string(), json!( 2 ) ) ),
+        ( "a", Edit::Rename( "name".to_string(), "label".to_string() ) ),
+        ( "b", Edit::Delete( "z".to_string() ) ),
+    ] );
+
+    let out = String::from_utf8( edit::write_cityjson( DOCUMENT.as_bytes(), &edits, None ).unwrap() ).unwrap();
+
+    // Values that were not edited keep their text ("1e3", not "1000.0"), and renamed attributes keep their place
+    let expected = DOCUMENT.replace( r#"{ "height": 1.50, "name": "Gebäude", "code": 1e3 }"#, r#"{"height":2,"label":"Gebäude","code":1e3}"# )
+                           .replace( r#"{"z":1,"a":2}"#, r#"{"a":2}"# );
+
+    assert_eq!( out, expected );
+
+}
+
+#[test]
+fn missing_and_null_attributes() {
+
+    let _guard = common::setup();
+
+    let edits = edits( vec![
+        ( "c", Edit::Set( "lanes".to_string(), json!( 2 ) ) ),
+        ( "d", Edit::Set( "lanes".to_string(), json!( 2 ) ) ),
+        ( "e", Edit::Set( "lanes".to_string(), json!( 2 ) ) ),
+    ] );
+
+    let out = edit::write_cityjson( DOCUMENT.as_bytes(), &edits, None ).unwrap();
+
+    // An "attributes" member is added where there was none, and null is replaced as if it were an empty object
+    for id in [ "c", "d", "e" ] {
+        assert_eq!( cityobject( &out, id )[ "attributes" ], json!( { "lanes": 2 } ), "{}", id );
+    }
+
+    // Reading the edited CityObjects gives the same result as reading the written document
+    edit::clear_edits();
+
+    for id in [ "c", "d", "e" ] {
+        edit::set_attribute( &[ id.to_string() ], "lanes", json!( 2 ) );
+    }
+
+    for id in [ "c", "d", "e" ] {
+        assert_eq!( get_attributes( DOCUMENT.as_bytes(), id ).unwrap()[ "attributes" ], json!( { "lanes": 2 } ), "{}", id );
+    }
+
+    let written = edit::write_edited_cityjson( DOCUMENT.as_bytes(), false ).unwrap();
+    assert_eq!( written, out );
+
+    edit::clear_edits();
+
+}
+
+#[test]
+fn edits_are_applied_in_order() {
+
+    let _guard = common::setup();
+
+    edit::clear_edits();
+
+    let ids = [ "a".to_string(), "b".to_string() ];
+
+    edit::set_attribute( &ids, "status", json!( "new" ) );
+    edit::rename_attribute( &ids, "status", "state" );
+    edit::rename_attribute( &ids[ ..1 ], "height", "name" );
+    edit::delete_attribute( &ids[ 1.. ], "a" );
+
+    let mut edited = edit::edited_ids();
+    edited.sort();
+    assert_eq!( edited, ids.to_vec() );
+
+    // A rename onto an existing attribute replaces it
+    assert_eq!( get_attributes( DOCUMENT.as_bytes(), "a" ).unwrap()[ "attributes" ], json!( { "name": 1.5, "code": 1000.0, "state": "new" } ) );
+    assert_eq!( get_attributes( DOCUMENT.as_bytes(), "b" ).unwrap()[ "attributes" ], json!( { "z": 1, "state": "new" } ) );
+
+    // The written document agrees with what is read
+    let out = edit::write_edited_cityjson( DOCUMENT.as_bytes(), false ).unwrap();
+
+    for id in &ids {
+        assert_eq!( cityobject( &out, id )[ "attributes" ], get_attributes( DOCUMENT.as_bytes(), id ).unwrap()[ "attributes" ] );
+    }
+
+    edit::clear_edits();
+
+}
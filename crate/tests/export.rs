@@ -0,0 +1,385 @@
+mod common;
+
+use std::convert::TryInto;
+
+use serde_json::Value;
+
+use rust_parcel::cityjson::export;
+use rust_parcel::cityjson::gltf;
+use rust_parcel::cityjson::mesh::{Mesh, MeshOptions};
+use rust_parcel::cityjson::picking::PickingIndex;
+use rust_parcel::cityjson::tiles::{self, Georeference, TileSubdivision};
+use rust_parcel::cityjson::to_bufferattributes;
+
+///// The binary layouts of the exports: headers, lengths and counts that other tools rely on
+
+const FIXTURES: [&str; 4] = [ "minimal", "edge_cases", "3dbag_sample", "railway_sample" ];
+
+// Roughly where the 3D BAG sample is
+const DELFT: Georeference = Georeference { longitude: 4.3571, latitude: 52.0116, height: 43.0 };
+
+fn u32_at( bytes: &[u8], offset: usize ) -> u32 {
+
+    u32::from_le_bytes( bytes[ offset..offset + 4 ].try_into().unwrap() )
+
+}
+
+fn mesh( name: &str, options: &MeshOptions ) -> Mesh {
+
+    Mesh::build( &common::fixture( &format!( "{}.json", name ) ), options ).unwrap()
+
+}
+
+// Check the GLB header and chunks, and return the glTF JSON and the binary chunk
+fn glb_chunks( glb: &[u8] ) -> ( Value, &[u8] ) {
+
+    assert_eq!( &glb[ 0..4 ], b"glTF" );
+    assert_eq!( u32_at( glb, 4 ), 2 );
+    assert_eq!( u32_at( glb, 8 ) as usize, glb.len() );
+
+    let json_length = u32_at( glb, 12 ) as usize;
+    assert_eq!( &glb[ 16..20 ], b"JSON" );
+    assert_eq!( json_length % 4, 0 );
+
+    let bin_start = 20 + json_length;
+    let bin_length = u32_at( glb, bin_start ) as usize;
+    assert_eq!( &glb[ bin_start + 4..bin_start + 8 ], b"BIN\0" );
+    assert_eq!( bin_length % 4, 0 );
+    assert_eq!( bin_start + 8 + bin_length, glb.len() );
+
+    let json = serde_json::from_slice( &glb[ 20..bin_start ] ).unwrap();
+
+    ( json, &glb[ bin_start + 8.. ] )
+
+}
+
+// The strings of a STRING property
+fn strings( gltf: &Value, bin: &[u8], property: &Value ) -> Vec<String> {
+
+    let view = | index: &Value | {
+        let view = &gltf[ "bufferViews" ][ index.as_u64().unwrap() as usize ];
+        let offset = view[ "byteOffset" ].as_u64().unwrap_or( 0 ) as usize;
+        &bin[ offset..offset + view[ "byteLength" ].as_u64().unwrap() as usize ]
+    };
+
+    let values = view( &property[ "values" ] );
+    let offsets = view( &property[ "stringOffsets" ] );
+
+    ( 0..offsets.len() / 4 - 1 ).map( |i| {
+        String::from_utf8( values[ u32_at( offsets, i * 4 ) as usize..u32_at( offsets, i * 4 + 4 ) as usize ].to_vec() ).unwrap()
+    } ).collect()
+
+}
+
+#[test]
+fn glb() {
+
+    let _guard = common::setup();
+
+    for name in &FIXTURES {
+
+        let mesh = mesh( name, &MeshOptions::default() );
+        let glb = gltf::write_glb( &mesh ).unwrap();
+        let ( gltf, bin ) = glb_chunks( &glb );
+
+        assert_eq!( gltf[ "asset" ][ "version" ], "2.0" );
+
+        // The buffer fits in the binary chunk, and so do the buffer views
+        let byte_length = gltf[ "buffers" ][ 0 ][ "byteLength" ].as_u64().unwrap() as usize;
+        assert!( byte_length <= bin.len() && bin.len() < byte_length + 4 );
+
+        for view in gltf[ "bufferViews" ].as_array().unwrap() {
+            let offset = view[ "byteOffset" ].as_u64().unwrap_or( 0 ) as usize;
+            assert!( offset + view[ "byteLength" ].as_u64().unwrap() as usize <= byte_length );
+        }
+
+        // Three vertices per triangle, and one primitive per group that together have all triangles
+        let triangle_vertices = mesh.triangles.len() as u64;
+        assert_eq!( gltf[ "accessors" ][ 0 ][ "count" ], triangle_vertices );
+
+        let primitives = gltf[ "meshes" ][ 0 ][ "primitives" ].as_array().unwrap();
+        assert_eq!( primitives.len(), mesh.groups.len() );
+
+        let counts: u64 = primitives.iter().map( |p| gltf[ "accessors" ][ p[ "indices" ].as_u64().unwrap() as usize ][ "count" ].as_u64().unwrap() ).sum();
+        assert_eq!( counts, triangle_vertices );
+
+        // The same triangles as parse_cityobjects(), and a feature per CityObject with its ID in the property table
+        let buffer = common::fixture( &format!( "{}.json", name ) );
+        assert_eq!( mesh.triangles.len(), to_bufferattributes::triangulate( &buffer, PickingIndex::Intervals ).unwrap().triangles.len() );
+
+        let table = &gltf[ "extensions" ][ "EXT_structural_metadata" ][ "propertyTables" ][ 0 ];
+        assert_eq!( table[ "count" ], mesh.features.len() as u64 );
+
+        let ids: Vec<String> = mesh.features.iter().map( |f| f.id.clone() ).collect();
+        assert_eq!( strings( &gltf, bin, &table[ "properties" ][ "id" ] ), ids );
+
+    }
+
+}
+
+#[test]
+fn empty_glb() {
+
+    let _guard = common::setup();
+
+    // glTF doesn't allow empty buffers, so there is nothing to write
+    let options = MeshOptions { types: Some( vec![ "WaterBody".to_string() ] ), ..Default::default() };
+    let mesh = mesh( "minimal", &options );
+
+    assert!( mesh.triangles.is_empty() );
+    assert!( gltf::write_glb( &mesh ).is_err() );
+    assert!( tiles::write_tileset( &mesh, TileSubdivision::Quadtree, 10, Some( &DELFT ) ).is_err() );
+
+    // The JSON chunk is padded with spaces, the binary chunk with zeros
+    let glb = gltf::glb( b"{}", vec![ 1 ] );
+    assert_eq!( glb.len(), 12 + 8 + 4 + 8 + 4 );
+    assert_eq!( &glb[ 20..24 ], b"{}  " );
+    assert_eq!( &glb[ 32..36 ], &[ 1, 0, 0, 0 ] );
+
+}
+
+#[test]
+fn feature_ids() {
+
+    let _guard = common::setup();
+
+    let mesh = mesh( "3dbag_sample", &MeshOptions::default() );
+    let glb = gltf::write_glb( &mesh ).unwrap();
+    let ( gltf, bin ) = glb_chunks( &glb );
+
+    // Exact integers: UNSIGNED_SHORT, in a view with a stride of 4 bytes since vertex attributes are aligned to 4 bytes
+    let accessor = &gltf[ "accessors" ][ gltf[ "meshes" ][ 0 ][ "primitives" ][ 0 ][ "attributes" ][ "_FEATURE_ID_0" ].as_u64().unwrap() as usize ];
+    assert_eq!( accessor[ "componentType" ], 5123 );
+
+    let view = &gltf[ "bufferViews" ][ accessor[ "bufferView" ].as_u64().unwrap() as usize ];
+    assert_eq!( view[ "byteStride" ], 4 );
+
+    let offset = view[ "byteOffset" ].as_u64().unwrap() as usize;
+    let count = accessor[ "count" ].as_u64().unwrap() as usize;
+    let ids: Vec<u32> = ( 0..count ).map( |v| u16::from_le_bytes( bin[ offset + v * 4..offset + v * 4 + 2 ].try_into().unwrap() ) as u32 ).collect();
+
+    // One per vertex, three vertices per triangle
+    let expected: Vec<u32> = mesh.triangle_features.iter().flat_map( |f| vec![ *f; 3 ] ).collect();
+    assert_eq!( ids, expected );
+
+}
+
+#[test]
+fn stl() {
+
+    let _guard = common::setup();
+
+    for name in &FIXTURES {
+
+        let mesh = mesh( name, &MeshOptions { recenter: true, ..Default::default() } );
+        let stl = export::write_stl( &mesh );
+        let triangle_n = mesh.triangles.len() / 3;
+
+        // An 80 byte header, the triangle count, and 50 bytes per triangle
+        assert!( stl[ 0..80 ].starts_with( format!( "Binary STL, {} CityObjects", mesh.features.len() ).as_bytes() ) );
+        assert_eq!( u32_at( &stl, 80 ) as usize, triangle_n );
+        assert_eq!( stl.len(), 84 + 50 * triangle_n );
+
+        // A normal, three vertices and an attribute byte count of 0 per triangle
+        for t in 0..triangle_n {
+
+            let record = &stl[ 84 + t * 50..84 + ( t + 1 ) * 50 ];
+            let f32_at = | i: usize | f32::from_le_bytes( record[ i * 4..i * 4 + 4 ].try_into().unwrap() );
+
+            for ( i, v ) in mesh.triangles[ t * 3..t * 3 + 3 ].iter().enumerate() {
+                for c in 0..3 {
+                    assert_eq!( f32_at( 3 + i * 3 + c ), mesh.vertices[ *v as usize ][ c ] as f32 );
+                }
+            }
+
+            assert_eq!( &record[ 48..50 ], &[ 0, 0 ] );
+
+        }
+
+    }
+
+}
+
+#[test]
+fn obj_and_ply() {
+
+    let _guard = common::setup();
+
+    for name in &FIXTURES {
+
+        let mesh = mesh( name, &MeshOptions::default() );
+        let ( vertices, triangles ) = mesh.compact();
+
+        let obj = export::write_obj( &mesh, "model.mtl" );
+        let lines = | prefix: &str | obj.lines().filter( |l| l.starts_with( prefix ) ).count();
+
+        assert!( obj.lines().any( |l| l == "mtllib model.mtl" ) );
+        assert_eq!( lines( "v " ), vertices.len() );
+        assert_eq!( lines( "f " ), triangles.len() / 3 );
+        assert_eq!( lines( "o " ), mesh.feature_ranges().iter().filter( |( start, end )| end > start ).count() );
+
+        // Face indices start at 1 and refer to existing vertices
+        for face in obj.lines().filter( |l| l.starts_with( "f " ) ) {
+            for v in face[ 2.. ].split( ' ' ) {
+                let v: usize = v.parse().unwrap();
+                assert!( v >= 1 && v <= vertices.len() );
+            }
+        }
+
+        // A material for every material used
+        let mtl = export::write_mtl( &mesh );
+
+        for material in obj.lines().filter_map( |l| l.strip_prefix( "usemtl " ) ) {
+            assert!( mtl.lines().any( |l| l == format!( "newmtl {}", material ) ) );
+        }
+
+        // The PLY header, and then exactly the vertices, faces and CityObject IDs it announces
+        let ply = export::write_ply( &mesh );
+        let end = ply.windows( 11 ).position( |w| w == b"end_header\n" ).unwrap() + 11;
+        let header = std::str::from_utf8( &ply[ ..end ] ).unwrap();
+
+        assert!( header.starts_with( "ply\nformat binary_little_endian 1.0\n" ) );
+        assert!( header.contains( &format!( "element vertex {}\n", vertices.len() ) ) );
+        assert!( header.contains( &format!( "element face {}\n", triangles.len() / 3 ) ) );
+        assert!( header.contains( &format!( "element cityobject {}\n", mesh.features.len() ) ) );
+
+        let ids: usize = mesh.features.iter().map( |f| 4 + f.id.len() ).sum();
+        assert_eq!( ply.len(), end + vertices.len() * 24 + triangles.len() / 3 * 17 + ids );
+
+    }
+
+}
+
+// Check a tile and its children, and collect the URIs of the content
+fn check_tile( tile: &Value, parent: Option<&Value>, uris: &mut Vec<String> ) {
+
+    let b = tile[ "boundingVolume" ][ "box" ].as_array().unwrap();
+    assert_eq!( b.len(), 12 );
+
+    // Within the bounding box of the parent, with a smaller geometric error
+    if let Some( parent ) = parent {
+
+        let p = parent[ "boundingVolume" ][ "box" ].as_array().unwrap();
+        let c = | b: &[Value], i: usize | b[ i ].as_f64().unwrap();
+
+        for axis in 0..3 {
+            let half = c( b, 3 + axis * 4 );
+            let parent_half = c( p, 3 + axis * 4 );
+            assert!( c( b, axis ) - half >= c( p, axis ) - parent_half - 1e-6 );
+            assert!( c( b, axis ) + half <= c( p, axis ) + parent_half + 1e-6 );
+        }
+
+        assert!( tile[ "geometricError" ].as_f64().unwrap() <= parent[ "geometricError" ].as_f64().unwrap() );
+
+    }
+
+    match tile.get( "children" ) {
+        Some( children ) => {
+            assert!( tile.get( "content" ).is_none() );
+            children.as_array().unwrap().iter().for_each( |child| check_tile( child, Some( tile ), uris ) );
+        },
+        None => uris.push( tile[ "content" ][ "uri" ].as_str().unwrap().to_string() ),
+    }
+
+}
+
+#[test]
+fn tileset() {
+
+    let _guard = common::setup();
+
+    let mesh = mesh( "3dbag_sample", &MeshOptions::default() );
+    let mut ids: Vec<String> = mesh.features.iter().map( |f| f.id.clone() ).collect();
+    ids.sort();
+
+    for ( subdivision, max_features ) in &[ ( TileSubdivision::Quadtree, 2 ), ( TileSubdivision::Octree, 3 ), ( TileSubdivision::Quadtree, 10000 ) ] {
+
+        let files = tiles::write_tileset( &mesh, *subdivision, *max_features, Some( &DELFT ) ).unwrap();
+
+        assert_eq!( files[ 0 ].0, "tileset.json" );
+        let tileset: Value = serde_json::from_slice( &files[ 0 ].1 ).unwrap();
+        assert_eq!( tileset[ "asset" ][ "version" ], "1.1" );
+
+        // Every leaf has one GLB file, and every GLB file belongs to a leaf
+        let mut uris = Vec::new();
+        check_tile( &tileset[ "root" ], None, &mut uris );
+
+        let paths: Vec<String> = files[ 1.. ].iter().map( |( path, _ )| path.clone() ).collect();
+        assert_eq!( uris, paths );
+
+        if *max_features >= mesh.features.len() {
+            assert_eq!( paths, vec![ "tiles/r.glb".to_string() ] );
+        } else {
+            assert!( paths.len() > 1 );
+        }
+
+        // Together, the tiles have every CityObject once
+        let mut tile_ids = Vec::new();
+
+        for ( _, glb ) in &files[ 1.. ] {
+
+            let ( gltf, bin ) = glb_chunks( glb );
+            let table = &gltf[ "extensions" ][ "EXT_structural_metadata" ][ "propertyTables" ][ 0 ];
+            tile_ids.extend( strings( &gltf, bin, &table[ "properties" ][ "id" ] ) );
+
+        }
+
+        tile_ids.sort();
+        assert_eq!( tile_ids, ids );
+
+    }
+
+}
+
+#[test]
+fn tileset_placement() {
+
+    let _guard = common::setup();
+
+    let numbers = | v: &Value | -> Vec<f64> { v.as_array().unwrap().iter().map( |n| n.as_f64().unwrap() ).collect() };
+    let close = | a: &[f64], b: &[f64], tolerance: f64 | a.iter().zip( b ).all( |( a, b )| ( a - b ).abs() < tolerance );
+
+    // A projected reference system (EPSG:7415) can't be placed on the globe without a georeference
+    let bag = mesh( "3dbag_sample", &MeshOptions::default() );
+    let error = tiles::write_tileset( &bag, TileSubdivision::Quadtree, 10, None ).unwrap_err();
+    assert!( error.contains( "urn:ogc:def:crs:EPSG::7415" ), "{}", error );
+
+    // With one, the root transform is the east-north-up frame at that position, and the content is relative to the model center
+    for recenter in [ false, true ] {
+
+        let bag = mesh( "3dbag_sample", &MeshOptions { recenter, ..Default::default() } );
+        let files = tiles::write_tileset( &bag, TileSubdivision::Quadtree, 10000, Some( &DELFT ) ).unwrap();
+        let tileset: Value = serde_json::from_slice( &files[ 0 ].1 ).unwrap();
+
+        let transform = numbers( &tileset[ "root" ][ "transform" ] );
+        let ( east, north, up ) = ( &transform[ 0..3 ], &transform[ 4..7 ], &transform[ 8..11 ] );
+        let dot = | a: &[f64], b: &[f64] | a.iter().zip( b ).map( |( a, b )| a * b ).sum::<f64>();
+
+        assert!( close( &transform[ 12..16 ], &[ 3922600.26, 298873.39, 5003631.77, 1.0 ], 0.01 ), "{:?}", transform );
+        assert!( close( &transform[ 12..15 ], &tiles::ecef( &DELFT ), 1e-6 ) );
+        assert!( close( &[ dot( east, east ), dot( north, north ), dot( up, up ), dot( east, north ), dot( east, up ), dot( north, up ) ], &[ 1.0, 1.0, 1.0, 0.0, 0.0, 0.0 ], 1e-9 ) );
+        assert_eq!( east[ 2 ], 0.0 );
+
+        // Up points away from the center of the earth
+        assert!( dot( up, &transform[ 12..15 ] ) > 0.0 );
+
+        let root_box = numbers( &tileset[ "root" ][ "boundingVolume" ][ "box" ] );
+        assert!( close( &root_box[ 0..3 ], &[ 0.0; 3 ], 1e-6 ), "{:?}", root_box );
+
+        let ( gltf, _ ) = glb_chunks( &files[ 1 ].1 );
+        assert!( close( &numbers( &gltf[ "nodes" ][ 0 ][ "translation" ] ), &[ 0.0; 3 ], 1e-6 ) );
+
+    }
+
+    // A file in ECEF only needs a translation to the model center
+    let mut minimal = mesh( "minimal", &MeshOptions { recenter: true, ..Default::default() } );
+    minimal.reference_system = Some( "https://www.opengis.net/def/crs/EPSG/0/4978".to_string() );
+
+    let files = tiles::write_tileset( &minimal, TileSubdivision::Quadtree, 10, None ).unwrap();
+    let tileset: Value = serde_json::from_slice( &files[ 0 ].1 ).unwrap();
+
+    let mut expected = vec![ 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0 ];
+    expected.extend( minimal.offset.iter().chain( &[ 1.0 ] ) );
+    assert!( close( &numbers( &tileset[ "root" ][ "transform" ] ), &expected, 1e-6 ) );
+
+}
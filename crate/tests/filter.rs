@@ -0,0 +1,212 @@
+mod common;
+
+use serde_json::{json, Value};
+
+use rust_parcel::cityjson::filter::{self, CmpOp, Expr, Operand};
+use rust_parcel::cityjson::picking::PickingIndex;
+use rust_parcel::cityjson::to_bufferattributes;
+
+///// Parsing and evaluating filter expressions: precedence, lists, numbers stored as strings, missing fields and the positions of errors
+
+fn building() -> Value {
+
+    json!( {
+        "type": "Building",
+        "attributes": {
+            "measuredHeight": "20.5",
+            "storeys": 4,
+            "roofType": "1000",
+            "code": 2100,
+            "name": "10",
+            "status": null,
+            "monument": false,
+            "address": [ { "street": "Kerkstraat", "number": "12" } ]
+        }
+    } )
+
+}
+
+fn matches( expression: &str ) -> bool {
+
+    filter::parse( expression ).unwrap_or_else( |e| panic!( "{}: {}", expression, e ) ).matches( "b-1", &building() )
+
+}
+
+fn field( name: &str ) -> Operand {
+
+    Operand::Field( vec![ name.to_string() ] )
+
+}
+
+fn truthy( name: &str ) -> Box<Expr> {
+
+    Box::new( Expr::Truthy( field( name ) ) )
+
+}
+
+#[test]
+fn precedence() {
+
+    // ! binds tighter than &&, and && tighter than ||
+    assert_eq!( filter::parse( "a || b && c" ).unwrap(), Expr::Or( truthy( "a" ), Box::new( Expr::And( truthy( "b" ), truthy( "c" ) ) ) ) );
+    assert_eq!( filter::parse( "a && b || c" ).unwrap(), Expr::Or( Box::new( Expr::And( truthy( "a" ), truthy( "b" ) ) ), truthy( "c" ) ) );
+    assert_eq!( filter::parse( "!a && b" ).unwrap(), Expr::And( Box::new( Expr::Not( truthy( "a" ) ) ), truthy( "b" ) ) );
+    assert_eq!( filter::parse( "!( a || b )" ).unwrap(), Expr::Not( Box::new( Expr::Or( truthy( "a" ), truthy( "b" ) ) ) ) );
+    assert_eq!( filter::parse( "not a or b and c" ).unwrap(), filter::parse( "!a || b && c" ).unwrap() );
+
+    // Chains are grouped from the left
+    assert_eq!( filter::parse( "a && b && c" ).unwrap(), Expr::And( Box::new( Expr::And( truthy( "a" ), truthy( "b" ) ) ), truthy( "c" ) ) );
+
+    // ! applies to a comparison as a whole
+    assert_eq!( filter::parse( "!storeys > 3" ).unwrap(), Expr::Not( Box::new( Expr::Compare( field( "storeys" ), CmpOp::Gt, Operand::Literal( json!( 3 ) ) ) ) ) );
+
+    assert!( matches( "type == \"Road\" || type == \"Building\" && storeys > 3" ) );
+    assert!( !matches( "( type == \"Road\" || type == \"Building\" ) && storeys > 4" ) );
+    assert!( matches( "!monument && !missing" ) );
+    assert!( !matches( "!( monument || storeys )" ) );
+    assert!( matches( "!!storeys" ) );
+
+}
+
+#[test]
+fn in_lists() {
+
+    assert!( matches( "roofType in [\"1000\", \"2100\"]" ) );
+    assert!( !matches( "roofType in [\"2100\"]" ) );
+    assert!( matches( "type in [ 'Road', 'Building' ]" ) );
+    assert!( !matches( "type in []" ) );
+    assert!( matches( "!( type in [ 'Road' ] )" ) );
+
+    // Numbers and numeric strings match each other, in both directions
+    assert!( matches( "roofType in [ 1000, 2000 ]" ) );
+    assert!( matches( "code in [ \"2100\" ]" ) );
+    assert!( matches( "storeys in [ 4.0 ]" ) );
+
+    // A missing or null field is in a list with null
+    assert!( matches( "missing in [ null ]" ) );
+    assert!( matches( "status in [ 1, null ]" ) );
+    assert!( !matches( "missing in [ 1, \"\" ]" ) );
+
+    assert_eq!( filter::parse( "a in [ 1, 'x', true, null ]" ).unwrap(), Expr::In( field( "a" ), vec![ json!( 1 ), json!( "x" ), json!( true ), Value::Null ] ) );
+
+}
+
+#[test]
+fn numeric_strings() {
+
+    // A numeric string is compared as a number with a number
+    assert!( matches( "measuredHeight > 20" ) );
+    assert!( matches( "measuredHeight == 20.5" ) );
+    assert!( matches( "measuredHeight < 1e2" ) );
+    assert!( matches( "storeys == \"4\"" ) );
+    assert!( matches( "storeys >= \" 4 \"" ) );
+    assert!( matches( "name > 9" ) );
+
+    // Two strings are compared as strings, even if they are numeric
+    assert!( matches( "name < \"9\"" ) );
+    assert!( !matches( "name == \"10.0\"" ) );
+    assert!( matches( "storeys == 4.0" ) );
+
+    // A string that isn't a number is neither smaller nor greater than a number
+    assert!( !matches( "type > 1" ) );
+    assert!( !matches( "type <= 1" ) );
+    assert!( matches( "type != 1" ) );
+
+    // Negative numbers and exponents
+    assert!( matches( "storeys > -1.5" ) );
+    assert!( matches( "storeys < 4e0 || storeys == 0.4E1" ) );
+
+}
+
+#[test]
+fn missing_fields() {
+
+    // Ordering against a missing field is never true
+    for op in &[ "<", "<=", ">", ">=" ] {
+        assert!( !matches( &format!( "missing {} 1", op ) ), "{}", op );
+        assert!( !matches( &format!( "1 {} missing", op ) ), "{}", op );
+        assert!( !matches( &format!( "status {} 1", op ) ), "{}", op );
+    }
+
+    // A missing field equals null and nothing else
+    assert!( matches( "missing == null" ) );
+    assert!( matches( "status == missing" ) );
+    assert!( !matches( "missing == 0" ) );
+    assert!( !matches( "missing == \"\"" ) );
+    assert!( matches( "missing != 0" ) );
+
+    // On its own, a field is true if it exists and is not null or false
+    assert!( matches( "storeys && roofType && id && type" ) );
+    assert!( !matches( "missing" ) );
+    assert!( !matches( "status" ) );
+    assert!( !matches( "monument" ) );
+
+    // Nested fields, in objects and in (CityJSON 1.1) address arrays
+    assert!( matches( "address.street == 'Kerkstraat'" ) );
+    assert!( matches( "address.0.number == 12" ) );
+    assert!( !matches( "address.1.number" ) );
+    assert!( !matches( "address.city || roofType.code || `measuredHeight`.value" ) );
+    assert!( matches( "attributes.roofType == '1000' && id == 'b-1'" ) );
+
+}
+
+#[test]
+fn error_positions() {
+
+    // Expression, character offset of the error, part of the message
+    let cases = [
+        ( "", 0, "Empty expression" ),
+        ( "   ", 0, "Empty expression" ),
+        ( "storeys >", 9, "found end of expression" ),
+        ( "storeys = 4", 8, "use '=='" ),
+        ( "storeys > 4 &&", 14, "Expected a field or a value" ),
+        ( "storeys && && roofType", 11, "found '&&'" ),
+        ( "storeys roofType", 8, "Expected '&&', '||' or end of expression" ),
+        ( "( storeys > 4", 13, "Expected ')'" ),
+        ( "storeys > 4 )", 12, "found ')'" ),
+        ( "name == \"10", 8, "Unterminated string" ),
+        ( "`name == 10", 0, "Unterminated quoted field" ),
+        ( "address. == 1", 8, "Expected a field name after '.'" ),
+        ( "storeys > 4 # 5", 12, "Unexpected character '#'" ),
+        ( "storeys > 1.2.3", 10, "Invalid number '1.2.3'" ),
+        ( "roofType in \"1000\"", 12, "Expected '[' after 'in'" ),
+        ( "roofType in [ \"1000\" \"2100\" ]", 21, "Expected ',' or ']'" ),
+        ( "roofType in [ \"1000\", ", 22, "Expected a value in list" ),
+        ( "roofType in [ code ]", 14, "Expected a value in list" ),
+        // Offsets count characters, not bytes
+        ( "naam == \"é\" && > 1", 15, "found '>'" ),
+    ];
+
+    for ( expression, position, message ) in &cases {
+
+        let error = filter::parse( expression ).unwrap_err();
+
+        assert_eq!( error.position, *position, "{}: {}", expression, error );
+        assert!( error.message.contains( message ), "{}: {}", expression, error );
+        assert_eq!( error.to_string(), format!( "{} at position {}", error.message, position ) );
+
+    }
+
+    assert!( filter::parse_field( "storeys > 4" ).is_err() );
+    assert_eq!( filter::parse_field( "address.`street name`" ).unwrap(), vec![ "address".to_string(), "street name".to_string() ] );
+
+}
+
+#[test]
+fn filter_cityobjects() {
+
+    let _guard = common::setup();
+    let buffer = common::fixture( "minimal.json" );
+
+    to_bufferattributes::triangulate( &buffer, PickingIndex::Intervals ).unwrap();
+
+    let res = filter::filter_cityobjects( &buffer, "type == 'Building' && measuredHeight >= '3' || function == 'main road' && !roofType" ).unwrap();
+    assert_eq!( res.ids, vec![ "road-1".to_string(), "building-1".to_string() ] );
+    assert_eq!( res.intervals.len(), 2 );
+
+    assert_eq!( filter::filter_cityobjects( &buffer, "measuredHeight > 3" ).unwrap().ids, Vec::<String>::new() );
+
+    let error = filter::filter_cityobjects( &buffer, "measuredHeight >" ).unwrap_err();
+    assert_eq!( error, "Invalid filter: Expected a field or a value, found end of expression at position 16" );
+
+}
@@ -0,0 +1,149 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rust_parcel::cityjson::edit;
+use rust_parcel::cityjson::format::{self, Format};
+use rust_parcel::cityjson::get_attributes;
+use rust_parcel::cityjson::inspect;
+use rust_parcel::cityjson::picking::{self, PickingIndex};
+use rust_parcel::cityjson::progress::{CancelToken, Progress, CANCELLED};
+use rust_parcel::cityjson::push::{self, PushParser};
+use rust_parcel::cityjson::to_bufferattributes::{self, IDS};
+
+// Binary fixtures and the JSON files they were converted from
+const FIXTURES: [( &str, &str, Format ); 2] = [
+    ( "3dbag_sample.city.cbor", "3dbag_sample.json", Format::Cbor ),
+    ( "denhaag_sample.city.msgpack", "denhaag_sample.json", Format::MessagePack ),
+];
+
+#[test]
+fn detect() {
+
+    for ( binary, json, format ) in &FIXTURES {
+        assert_eq!( format::detect( &common::fixture( binary ) ), *format );
+        assert_eq!( format::detect( &common::fixture( json ) ), Format::Json );
+    }
+
+    assert_eq!( format::detect( b"" ), Format::Json );
+    assert_eq!( format::detect( b" \n{}" ), Format::Json );
+    assert_eq!( format::detect( &[ 0xd9, 0xd9, 0xf7, 0xa0 ] ), Format::Cbor );
+    assert_eq!( format::detect( &[ 0xde, 0x00, 0x10 ] ), Format::MessagePack );
+
+}
+
+#[test]
+fn binary_gives_the_same_output() {
+
+    let _guard = common::setup();
+
+    for ( binary, json, _ ) in &FIXTURES {
+
+        let binary = common::fixture( binary );
+        let json = common::fixture( json );
+
+        let expected = to_bufferattributes::triangulate( &json, PickingIndex::Intervals ).unwrap();
+        let expected_vertices = to_bufferattributes::flatten_vertices( &json ).unwrap();
+        let expected_ids = IDS.lock().unwrap().clone();
+
+        let res = to_bufferattributes::triangulate( &binary, PickingIndex::Intervals ).unwrap();
+
+        assert_eq!( res.triangles, expected.triangles );
+        assert_eq!( res.groups, expected.groups );
+        assert_eq!( *IDS.lock().unwrap(), expected_ids );
+        assert_eq!( to_bufferattributes::flatten_vertices( &binary ).unwrap(), expected_vertices );
+
+        // parse_buffer() falls back to the whole-document parse
+        let res = push::parse_buffer( &binary, PickingIndex::Dense ).unwrap();
+
+        assert_eq!( res.triangles, expected.triangles );
+        assert_eq!( res.vertices, expected_vertices );
+
+        for id in &expected_ids {
+            assert_eq!( get_attributes::get_attributes( &binary, id ).unwrap(), get_attributes::get_attributes( &json, id ).unwrap() );
+        }
+
+        assert_eq!( serde_json::to_value( inspect::info( &binary ).unwrap() ).unwrap(), serde_json::to_value( inspect::info( &json ).unwrap() ).unwrap() );
+
+    }
+
+}
+
+#[test]
+fn progress_and_cancel() {
+
+    let _guard = common::setup();
+    let binary = common::fixture( "3dbag_sample.city.cbor" );
+
+    // Only the end of the parse is reported, with all CityObjects
+    let reported = Rc::new( RefCell::new( Vec::new() ) );
+    let r = reported.clone();
+    let callback = Box::new( move | p: Progress | {
+        r.borrow_mut().push( p );
+        true
+    } );
+
+    to_bufferattributes::triangulate_with_progress( &binary, PickingIndex::Intervals, Some( callback ), None ).unwrap();
+
+    let objects = IDS.lock().unwrap().len() as u32;
+    assert_eq!( *reported.borrow().last().unwrap(), Progress { objects, bytes: binary.len(), total: binary.len() } );
+
+    // The cancellation token is still checked for every CityObject, and the last picking index stays
+    let token = CancelToken::new();
+    token.cancel();
+
+    let res = to_bufferattributes::triangulate_with_progress( &binary, PickingIndex::Intervals, None, Some( token ) );
+
+    assert_eq!( res.map( |r| r.triangles.len() ), Err( CANCELLED.to_string() ) );
+    assert!( picking::pick( 0 ).is_some() );
+
+}
+
+#[test]
+fn convert() {
+
+    let _guard = common::setup();
+
+    for name in &[ "minimal.json", "edge_cases.json", "railway_sample.json" ] {
+
+        let json = common::fixture( name );
+        let expected = to_bufferattributes::triangulate( &json, PickingIndex::Intervals ).unwrap();
+        let value: serde_json::Value = serde_json::from_slice( &json ).unwrap();
+
+        for format in &[ Format::Cbor, Format::MessagePack ] {
+
+            let binary = format::encode( &json, *format ).unwrap();
+
+            assert_eq!( format::detect( &binary ), *format );
+            assert!( binary.len() < json.len() );
+
+            // The order of the CityObjects is kept, so the triangles are the same
+            assert_eq!( to_bufferattributes::triangulate( &binary, PickingIndex::Intervals ).unwrap().triangles, expected.triangles, "{}", name );
+
+            // And back to the same JSON
+            let back = format::encode( &binary, Format::Json ).unwrap();
+            assert_eq!( serde_json::from_slice::<serde_json::Value>( &back ).unwrap(), value );
+
+        }
+
+    }
+
+    assert!( format::encode( b"{ \"CityObjects\": ", Format::Cbor ).is_err() );
+
+}
+
+#[test]
+fn unsupported() {
+
+    let _guard = common::setup();
+    let binary = common::fixture( "denhaag_sample.city.msgpack" );
+
+    // The push parser only scans JSON text
+    let mut parser = PushParser::new( PickingIndex::Intervals );
+    assert!( parser.push( &binary[ ..100 ] ).is_err() );
+
+    // Edited documents are written as JSON, keeping the formatting of the input
+    assert!( edit::write_edited_cityjson( &binary, false ).is_err() );
+
+}
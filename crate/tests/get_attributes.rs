@@ -0,0 +1,74 @@
+mod common;
+
+use serde_json::{json, Value};
+
+use rust_parcel::cityjson::get_attributes;
+
+///// Retrieving several CityObjects at once with get_attributes_of(): order, unknown IDs, and the projection on attribute keys
+
+fn ids( ids: &[ &str ] ) -> Vec<String> {
+
+    ids.iter().map( |id| id.to_string() ).collect()
+
+}
+
+#[test]
+fn order_and_unknown_ids() {
+
+    let _guard = common::setup();
+
+    let buffer = common::fixture( "minimal.json" );
+
+    // In the order of the request, not of the file, with null for IDs that don't exist
+    let out = get_attributes::get_attributes_of( &buffer, &ids( &[ "building-1", "does-not-exist", "road-1", "building-1" ] ), None ).unwrap();
+
+    assert_eq!( out.len(), 4 );
+    assert_eq!( out[ 0 ][ "attributes" ], json!( { "measuredHeight": 3.0, "roofType": "flat" } ) );
+    assert_eq!( out[ 0 ][ "type" ], "Building" );
+    assert_eq!( out[ 1 ], Value::Null );
+    assert_eq!( out[ 2 ][ "attributes" ], json!( { "function": "main road" } ) );
+    assert_eq!( out[ 3 ], out[ 0 ] );
+
+    assert!( get_attributes::get_attributes_of( &buffer, &[], None ).unwrap().is_empty() );
+
+}
+
+#[test]
+fn key_projection() {
+
+    let _guard = common::setup();
+
+    let buffer = common::fixture( "minimal.json" );
+    let keys = ids( &[ "roofType", "function", "not-an-attribute" ] );
+
+    let out = get_attributes::get_attributes_of( &buffer, &ids( &[ "building-1", "road-1" ] ), Some( &keys ) ).unwrap();
+
+    // Only the requested attributes are kept, keys that don't exist are not added, and the rest of the CityObject is untouched
+    assert_eq!( out[ 0 ][ "attributes" ], json!( { "roofType": "flat" } ) );
+    assert_eq!( out[ 1 ][ "attributes" ], json!( { "function": "main road" } ) );
+    assert!( out[ 0 ][ "geometry" ].is_array() );
+
+    let out = get_attributes::get_attributes_of( &buffer, &ids( &[ "building-1" ] ), Some( &[] ) ).unwrap();
+    assert_eq!( out[ 0 ][ "attributes" ], json!( {} ) );
+
+}
+
+#[test]
+fn without_attributes() {
+
+    let _guard = common::setup();
+
+    let buffer = common::fixture( "edge_cases.json" );
+    let keys = ids( &[ "name" ] );
+
+    // A CityObject without attributes is returned as it is, with or without keys; it doesn't get an empty "attributes"
+    for keys in [ None, Some( &keys[ .. ] ) ] {
+
+        let out = get_attributes::get_attributes_of( &buffer, &ids( &[ "part-1" ] ), keys ).unwrap();
+
+        assert_eq!( out[ 0 ][ "type" ], "BuildingPart" );
+        assert!( out[ 0 ].get( "attributes" ).is_none() );
+
+    }
+
+}
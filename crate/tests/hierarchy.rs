@@ -0,0 +1,119 @@
+mod common;
+
+use serde_json::{json, Value};
+
+use rust_parcel::cityjson::hierarchy::{self, Hierarchy};
+use rust_parcel::cityjson::picking::{self, PickingIndex, TriangleInterval};
+use rust_parcel::cityjson::to_bufferattributes;
+
+///// The hierarchy built from "parents", "children" and "members": relations stated on either side, nested parents, groups, and the
+///// triangle intervals of everything below a CityObject.
+
+// A Building with a nested BuildingPart, a CityObjectGroup with CityJSON 1.0 "members", and an object that names the group as its parent (1.1)
+fn nested() -> Hierarchy {
+
+    let cityobjects = json!( [
+        [ "building", { "type": "Building", "children": [ "part" ] } ],
+        [ "part", { "type": "BuildingPart", "children": [ "subpart" ] } ],
+        [ "subpart", { "type": "BuildingPart", "parents": [ "part" ] } ],
+        [ "road", { "type": "Road", "parents": [ "group" ] } ],
+        [ "group", { "type": "CityObjectGroup", "members": [ "building" ] } ],
+        [ "cycle-a", { "type": "Building", "parents": [ "cycle-b" ] } ],
+        [ "cycle-b", { "type": "Building", "parents": [ "cycle-a" ] } ],
+    ] );
+
+    let mut hierarchy = Hierarchy::default();
+
+    for co in cityobjects.as_array().unwrap() {
+        hierarchy.add( co[ 0 ].as_str().unwrap(), &co[ 1 ] );
+    }
+
+    hierarchy.finalize();
+
+    hierarchy
+
+}
+
+fn strings( ids: &[ &str ] ) -> Vec<String> {
+
+    ids.iter().map( |id| id.to_string() ).collect()
+
+}
+
+#[test]
+fn relations() {
+
+    let hierarchy = nested();
+
+    // Stated by the parent only, by the child only, and never twice
+    assert_eq!( hierarchy.children_of( "building" ), strings( &[ "part" ] ) );
+    assert_eq!( hierarchy.children_of( "part" ), strings( &[ "subpart" ] ) );
+    assert_eq!( hierarchy.parents[ "part" ], strings( &[ "building" ] ) );
+    assert_eq!( hierarchy.parents[ "subpart" ], strings( &[ "part" ] ) );
+
+    // finalize() turns the group named as a parent into membership, after the group itself was read
+    assert_eq!( hierarchy.members_of( "group" ), strings( &[ "building", "road" ] ) );
+    assert!( !hierarchy.parents.contains_key( "road" ) );
+    assert!( hierarchy.children_of( "group" ).is_empty() );
+
+    assert!( hierarchy.children_of( "does-not-exist" ).is_empty() );
+
+}
+
+#[test]
+fn roots() {
+
+    // Group members are still roots, and a cycle has no root at all
+    assert_eq!( nested().roots(), strings( &[ "building", "road", "group" ] ) );
+
+}
+
+#[test]
+fn parent_chain() {
+
+    let hierarchy = nested();
+
+    assert_eq!( hierarchy.parent_chain( "subpart" ), strings( &[ "part", "building" ] ) );
+    assert!( hierarchy.parent_chain( "building" ).is_empty() );
+    assert!( hierarchy.parent_chain( "road" ).is_empty() );
+    assert_eq!( hierarchy.parent_chain( "cycle-a" ), strings( &[ "cycle-b" ] ) );
+
+}
+
+#[test]
+fn descendants() {
+
+    let hierarchy = nested();
+
+    assert_eq!( hierarchy.descendants( "building" ), strings( &[ "building", "part", "subpart" ] ) );
+    assert_eq!( hierarchy.descendants( "group" ), strings( &[ "group", "building", "road", "part", "subpart" ] ) );
+    assert_eq!( hierarchy.descendants( "subpart" ), strings( &[ "subpart" ] ) );
+    assert_eq!( hierarchy.descendants( "does-not-exist" ), strings( &[ "does-not-exist" ] ) );
+
+}
+
+#[test]
+fn descendant_intervals() {
+
+    let _guard = common::setup();
+
+    let buffer = common::fixture( "edge_cases.json" );
+
+    for index in [ PickingIndex::Intervals, PickingIndex::Dense ] {
+
+        to_bufferattributes::triangulate( &buffer, index ).unwrap();
+
+        let ranges: Vec<TriangleInterval> = picking::all_triangle_ranges();
+        let range = | id: &str | ranges.iter().find( |r| r.id == id ).unwrap().clone();
+
+        // The group has no triangles of its own; its members and their children do
+        assert_eq!( hierarchy::descendant_intervals( "group-1" ), vec![ range( "quad-and-hole" ), range( "multisolid" ), range( "part-1" ) ] );
+        assert_eq!( hierarchy::descendant_intervals( "quad-and-hole" ), vec![ range( "quad-and-hole" ), range( "part-1" ) ] );
+        assert!( hierarchy::descendant_intervals( "no-geometry" ).is_empty() );
+
+        let roots: Value = serde_json::to_value( hierarchy::HIERARCHY.lock().unwrap().roots() ).unwrap();
+        assert_eq!( roots, json!( [ "group-1", "no-geometry", "empty-geometry", "quad-and-hole", "multisolid", "tree-instance", "gebäude-ü ✓" ] ) );
+
+    }
+
+}
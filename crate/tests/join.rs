@@ -0,0 +1,143 @@
+mod common;
+
+use serde_json::{json, Map, Value};
+
+use rust_parcel::cityjson::filter;
+use rust_parcel::cityjson::get_attributes::get_attributes;
+use rust_parcel::cityjson::join::{self, JoinReport, TableFormat};
+
+///// Reading CSV and JSON tables, joining them by ID or by attribute, the report, and the joined columns in the rest of the API
+
+fn row( value: Value ) -> Map<String, Value> {
+
+    value.as_object().unwrap().clone()
+
+}
+
+#[test]
+fn csv_delimiters() {
+
+    // The delimiter that occurs most in the header wins, so that decimal commas don't split values
+    for csv in [ "id,energy\nb1,1.5\n", "id;energy\nb1;1,5\n", "id\tenergy\nb1\t1,5\n" ] {
+
+        let rows = join::parse_csv( csv ).unwrap();
+
+        assert_eq!( rows.len(), 1, "{:?}", csv );
+        assert_eq!( rows[ 0 ][ "id" ], "b1", "{:?}", csv );
+
+    }
+
+    assert_eq!( join::parse_csv( "id;energy\nb1;1,5\n" ).unwrap()[ 0 ][ "energy" ], "1,5" );
+
+}
+
+#[test]
+fn csv_values() {
+
+    // A byte order mark, CRLF, quoted delimiters, quotes and newlines, empty values as null, leading zeros kept, short rows and empty lines
+    let csv = "\u{feff}id,name,code\r\nb1,\"Smith, J.\",0363\r\n\r\nb2,\"say \"\"hi\"\"\nplease\",\nb3\n";
+
+    let rows = join::parse_csv( csv ).unwrap();
+
+    assert_eq!( rows, vec![ row( json!( { "id": "b1", "name": "Smith, J.", "code": "0363" } ) ),
+                            row( json!( { "id": "b2", "name": "say \"hi\"\nplease", "code": null } ) ),
+                            row( json!( { "id": "b3" } ) ) ] );
+
+    assert_eq!( join::parse_csv( "id,name\nb1,\"open\n" ).unwrap_err(), "Unterminated quoted value on line 3" );
+    assert_eq!( join::parse_csv( "id,name\nb1,a,b\n" ).unwrap_err(), "Row 1 has 3 values, but the header has 2 columns" );
+    assert!( join::parse_csv( "" ).is_err() );
+
+}
+
+#[test]
+fn json_tables() {
+
+    let rows = join::parse_json_table( r#"[ { "id": "b1", "energy": 12.5 }, { "id": 2 } ]"#, "id" ).unwrap();
+    assert_eq!( rows, vec![ row( json!( { "id": "b1", "energy": 12.5 } ) ), row( json!( { "id": 2 } ) ) ] );
+
+    // The key of an object of rows becomes the key column, unless the row has one already
+    let rows = join::parse_json_table( r#"{ "b1": { "energy": 1 }, "b2": { "id": "other" } }"#, "id" ).unwrap();
+    assert_eq!( rows, vec![ row( json!( { "energy": 1, "id": "b1" } ) ), row( json!( { "id": "other" } ) ) ] );
+
+    assert_eq!( join::parse_json_table( "[ 1 ]", "id" ).unwrap_err(), "Row 1 is not an object" );
+    assert!( join::parse_json_table( "12", "id" ).is_err() );
+    assert!( join::parse_json_table( "[", "id" ).is_err() );
+
+}
+
+#[test]
+fn by_id() {
+
+    let _guard = common::setup();
+
+    let buffer = common::fixture( "minimal.json" );
+
+    let rows = join::parse_csv( "id,energy,roofType\nbuilding-1,120,gabled\nroad-2,5,\n,7,\nbuilding-1,130,\n" ).unwrap();
+    let ( joined, report ) = join::join_rows( &buffer, rows, "id", None ).unwrap();
+
+    // The last row of a duplicate key wins, also for null
+    assert_eq!( Value::Object( joined[ "building-1" ].clone() ), json!( { "energy": "130", "roofType": null } ) );
+    assert_eq!( joined.len(), 1 );
+
+    assert_eq!( report, JoinReport {
+        rows: 4,
+        matched_rows: 2,
+        matched_objects: 1,
+        columns: vec![ "energy".to_string(), "roofType".to_string() ],
+        unmatched: vec![ "road-2".to_string(), "(row 3 has no id)".to_string() ],
+        duplicates: vec![ "building-1".to_string() ],
+    } );
+
+}
+
+#[test]
+fn by_attribute() {
+
+    let _guard = common::setup();
+
+    let buffer = common::fixture( "3dbag_sample.json" );
+
+    // Numbers in the table match strings in the CityJSON and the other way around, and a key can match several CityObjects
+    let rows = join::parse_json_table( r#"[ { "year": 1951, "era": "post-war" }, { "year": "1978", "era": "seventies" }, { "year": 2020 } ]"#, "year" ).unwrap();
+    let ( joined, report ) = join::join_rows( &buffer, rows, "year", Some( "oorspronkelijkbouwjaar" ) ).unwrap();
+
+    assert_eq!( joined[ "NL.IMBAG.Pand.0503100000032914" ][ "era" ], "post-war" );
+    assert_eq!( joined[ "NL.IMBAG.Pand.0503100000000137" ][ "era" ], "seventies" );
+    assert_eq!( ( report.matched_rows, report.unmatched ), ( 2, vec![ "2020".to_string() ] ) );
+    assert!( report.duplicates.is_empty() );
+
+    assert!( join::join_rows( &buffer, Vec::new(), "year", Some( "a ==" ) ).is_err() );
+
+}
+
+#[test]
+fn joined_attributes() {
+
+    let _guard = common::setup();
+
+    let buffer = common::fixture( "minimal.json" );
+
+    join::clear_joins();
+    join::join_table( &buffer, "id;measuredHeight;energy\nbuilding-1;12;A\n", TableFormat::Csv, "id", None ).unwrap();
+    join::join_table( &buffer, r#"{ "road-1": { "energy": "C" } }"#, TableFormat::Json, "id", None ).unwrap();
+
+    // Joined columns replace attributes with the same name, and several tables add up
+    assert_eq!( get_attributes( &buffer, "building-1" ).unwrap()[ "attributes" ], json!( { "measuredHeight": "12", "roofType": "flat", "energy": "A" } ) );
+    assert_eq!( get_attributes( &buffer, "road-1" ).unwrap()[ "attributes" ], json!( { "function": "main road", "energy": "C" } ) );
+
+    let matched = filter::filter_cityobjects( &buffer, "energy == \"A\" && measuredHeight > 10" ).unwrap();
+    assert_eq!( matched.ids, vec![ "building-1".to_string() ] );
+
+    join::clear_joins();
+
+    assert_eq!( get_attributes( &buffer, "building-1" ).unwrap()[ "attributes" ], json!( { "measuredHeight": 3.0, "roofType": "flat" } ) );
+
+    // "attributes": null is treated as no attributes
+    let buffer = br#"{"type":"CityJSON","version":"1.1","CityObjects":{"a":{"type":"Building","attributes":null}},"vertices":[]}"#;
+
+    join::join_table( buffer, "id,energy\na,B\n", TableFormat::Csv, "id", None ).unwrap();
+    assert_eq!( get_attributes( buffer, "a" ).unwrap()[ "attributes" ], json!( { "energy": "B" } ) );
+
+    join::clear_joins();
+
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use serde_json::json;
+
+use rust_parcel::cityjson::edit;
+use rust_parcel::cityjson::get_attributes::get_attributes;
+use rust_parcel::cityjson::model::CityModel;
+use rust_parcel::cityjson::picking::{self, PickingIndex};
+use rust_parcel::cityjson::progress::{CancelToken, Progress, CANCELLED};
+use rust_parcel::cityjson::to_bufferattributes::{self, IDS};
+
+#[test]
+fn geometry_matches_parse() {
+
+    let _guard = common::setup();
+    let buffer = common::fixture( "3dbag_sample.json" );
+
+    let mut model = CityModel::parse( buffer.clone(), PickingIndex::Intervals, None, None ).unwrap();
+    let expected = to_bufferattributes::triangulate( &buffer, PickingIndex::Intervals ).unwrap();
+
+    let geometry = model.take_geometry().unwrap();
+
+    assert_eq!( geometry.triangles, expected.triangles );
+    assert_eq!( geometry.groups, expected.groups );
+    assert_eq!( geometry.vertices, to_bufferattributes::flatten_vertices( &buffer ).unwrap() );
+
+    // The geometry is handed over once
+    assert!( model.take_geometry().is_none() );
+
+}
+
+#[test]
+fn queries_outlive_the_global_state() {
+
+    let _guard = common::setup();
+    let buffer = common::fixture( "denhaag_sample.json" );
+
+    let intervals = CityModel::parse( buffer.clone(), PickingIndex::Intervals, None, None ).unwrap();
+    let dense = CityModel::parse( buffer.clone(), PickingIndex::Dense, None, None ).unwrap();
+
+    let triangles = to_bufferattributes::triangulate( &buffer, PickingIndex::Intervals ).unwrap().triangles.len() as u32 / 3;
+    let expected: Vec<_> = ( 0..triangles ).map( picking::pick_cityobject ).collect();
+
+    // Parsing another file replaces the global picking index, but not the one of the models
+    to_bufferattributes::triangulate( &common::fixture( "minimal.json" ), PickingIndex::Intervals ).unwrap();
+
+    for ( triangle, picked ) in expected.iter().enumerate() {
+        assert_eq!( intervals.pick( triangle as u32 ), *picked );
+        assert_eq!( dense.pick( triangle as u32 ), *picked );
+    }
+
+    assert_eq!( intervals.pick( triangles ), None );
+
+    let id = &expected[ 0 ].as_ref().unwrap().id;
+    let attributes = intervals.attributes( id ).unwrap();
+
+    assert_eq!( attributes, rust_parcel::cityjson::get_attributes::get_attributes( &buffer, id ).unwrap() );
+    assert!( attributes.as_object().is_some_and( |a| !a.is_empty() ) );
+
+}
+
+#[test]
+fn parse_leaves_the_shown_file_alone() {
+
+    let _guard = common::setup();
+
+    // The file that is shown, with an edit
+    let shown = common::fixture( "minimal.json" );
+
+    to_bufferattributes::triangulate( &shown, PickingIndex::Dense ).unwrap();
+    edit::set_attribute( &[ "building-1".to_string() ], "roofType", json!( "gabled" ) );
+
+    let ids = IDS.lock().unwrap().clone();
+    let picked = picking::pick_cityobject( 0 );
+
+    let model = CityModel::parse( common::fixture( "3dbag_sample.json" ), PickingIndex::Intervals, None, None ).unwrap();
+
+    assert_eq!( model.ids().len(), 4 );
+    assert_eq!( *IDS.lock().unwrap(), ids );
+    assert_eq!( picking::pick_cityobject( 0 ), picked );
+    assert_eq!( get_attributes( &shown, "building-1" ).unwrap()[ "attributes" ][ "roofType" ], "gabled" );
+
+    edit::clear_edits();
+
+}
+
+#[test]
+fn progress_and_cancellation() {
+
+    let _guard = common::setup();
+    let buffer = common::fixture( "railway_sample.json" );
+
+    let reported = Rc::new( RefCell::new( Vec::new() ) );
+    let sink = reported.clone();
+    let callback = Box::new( move | p: Progress | { sink.borrow_mut().push( p ); true } );
+
+    let model = CityModel::parse( buffer.clone(), PickingIndex::Intervals, Some( callback ), None ).unwrap();
+
+    assert_eq!( reported.borrow().last(), Some( &Progress { objects: 13, bytes: buffer.len(), total: buffer.len() } ) );
+    assert!( reported.borrow().len() > 1 );
+    assert!( !model.ids().is_empty() );
+
+    let token = CancelToken::new();
+    token.cancel();
+
+    assert_eq!( CityModel::parse( buffer, PickingIndex::Intervals, None, Some( token ) ).err(), Some( CANCELLED.to_string() ) );
+
+}
@@ -0,0 +1,299 @@
+mod common;
+
+use serde_json::{json, Map, Value};
+
+use rust_parcel::cityjson::cache::{self, CacheView, GeometryCache};
+use rust_parcel::cityjson::edit;
+use rust_parcel::cityjson::get_attributes::get_attributes;
+use rust_parcel::cityjson::hierarchy::HIERARCHY;
+use rust_parcel::cityjson::join::{self, TableFormat};
+use rust_parcel::cityjson::picking::{self, PickingIndex};
+use rust_parcel::cityjson::push::{self, PushParser};
+use rust_parcel::cityjson::to_bufferattributes::{self, CO_TYPES, IDS, INTERVALS};
+
+///// The outputs of parse_cityobjects(), parse_vertices(), get_attributes() and get_interval_and_id() for the fixtures, compared with
+///// golden files, plus checks that hold for every file.
+
+const FIXTURES: [&str; 5] = [ "minimal", "edge_cases", "3dbag_sample", "denhaag_sample", "railway_sample" ];
+
+// What get_interval_and_id() returns for a triangle
+fn interval_and_id( query: u32 ) -> String {
+
+    match picking::pick( query ) {
+        Some( ( id, first, last ) ) => format!( "{} {} {}", id, first, last ),
+        None => "".to_string(),
+    }
+
+}
+
+// Parse a fixture like the viewer does, and collect everything it returns or stores
+fn parse( buffer: &[u8], picking: PickingIndex ) -> Value {
+
+    let res = to_bufferattributes::triangulate( buffer, picking ).unwrap();
+    let vertices = to_bufferattributes::flatten_vertices( buffer ).unwrap();
+
+    let ids = IDS.lock().unwrap().clone();
+    let intervals = INTERVALS.lock().unwrap().clone();
+    let all_ids = HIERARCHY.lock().unwrap().ids.clone();
+
+    let triangle_n = res.triangles.len() as u32 / 3;
+
+    // First and last triangle of every CityObject, and the first triangle after the last one
+    let mut queries: Vec<u32> = picking::all_triangle_ranges().iter().flat_map( |r| vec![ r.start, r.end ] ).collect();
+    queries.push( triangle_n );
+    queries.dedup();
+
+    let picks: Map<String, Value> = queries.iter().map( |q| ( q.to_string(), Value::from( interval_and_id( *q ) ) ) ).collect();
+
+    let mut attributes: Map<String, Value> = all_ids.iter().map( |id| ( id.clone(), get_attributes( buffer, id ).unwrap() ) ).collect();
+    attributes.insert( "does-not-exist".to_string(), get_attributes( buffer, "does-not-exist" ).unwrap() );
+
+    json!( {
+        "triangles": res.triangles,
+        "groups": res.groups,
+        "vertices": vertices,
+        "ids": ids,
+        "intervals": intervals,
+        "picks": picks,
+        "attributes": attributes,
+    } )
+
+}
+
+fn parse_fixture( name: &str ) -> Value {
+
+    parse( &common::fixture( &format!( "{}.json", name ) ), PickingIndex::Intervals )
+
+}
+
+fn u32s( value: &Value ) -> Vec<u32> {
+
+    value.as_array().unwrap().iter().map( |v| v.as_u64().unwrap() as u32 ).collect()
+
+}
+
+#[test]
+fn minimal() {
+
+    let _guard = common::setup();
+    let out = parse_fixture( "minimal" );
+
+    // Buildings come before Roads, whatever the order in the file
+    assert_eq!( u32s( &out[ "triangles" ] ), vec![ 0, 1, 2, 0, 2, 3, 0, 3, 1, 1, 3, 2, 4, 5, 6, 4, 6, 7 ] );
+    assert_eq!( out[ "groups" ], json!( { "Building": [ 0, 12 ], "Road": [ 12, 6 ] } ) );
+    assert_eq!( out[ "ids" ], json!( [ "building-1", "road-1" ] ) );
+    assert_eq!( u32s( &out[ "intervals" ] ), vec![ 0, 4, 6 ] );
+    assert_eq!( u32s( &out[ "vertices" ] ), vec![ 0, 0, 0, 100, 0, 0, 0, 100, 0, 0, 0, 300, 200, 0, 0, 300, 0, 0, 300, 100, 0, 200, 100, 0 ] );
+
+    assert_eq!( interval_and_id( 0 ), "building-1 0 3" );
+    assert_eq!( interval_and_id( 3 ), "building-1 0 3" );
+    assert_eq!( interval_and_id( 4 ), "road-1 4 5" );
+    assert_eq!( interval_and_id( 6 ), "" );
+
+    assert_eq!( out[ "attributes" ][ "building-1" ][ "attributes" ], json!( { "measuredHeight": 3.0, "roofType": "flat" } ) );
+    assert_eq!( out[ "attributes" ][ "does-not-exist" ], json!( {} ) );
+
+    common::assert_golden( "minimal", &out );
+
+}
+
+#[test]
+fn edge_cases() {
+
+    let _guard = common::setup();
+    let out = parse_fixture( "edge_cases" );
+
+    // CityObjects without triangles (no geometry, empty geometry, only a group, only a GeometryInstance) can't be picked
+    assert_eq!( out[ "ids" ], json!( [ "quad-and-hole", "multisolid", "gebäude-ü ✓", "part-1" ] ) );
+
+    // The quad is skipped and the hole of the triangle is ignored
+    assert_eq!( u32s( &out[ "triangles" ] )[ 0..3 ], [ 0, 1, 2 ] );
+    assert_eq!( interval_and_id( 0 ), "quad-and-hole 0 0" );
+    // Both solids of the CompositeSolid
+    assert_eq!( interval_and_id( 1 ), "multisolid 1 3" );
+    assert_eq!( interval_and_id( 4 ), "gebäude-ü ✓ 4 4" );
+    assert_eq!( interval_and_id( 5 ), "part-1 5 6" );
+
+    // CityObjects without triangles still have attributes
+    assert_eq!( out[ "attributes" ][ "no-geometry" ][ "attributes" ][ "note" ], "no geometry member" );
+    assert_eq!( out[ "attributes" ][ "gebäude-ü ✓" ][ "attributes" ][ "naam" ], "Café \"De Zon\"" );
+
+    common::assert_golden( "edge_cases", &out );
+
+}
+
+#[test]
+fn samples() {
+
+    let _guard = common::setup();
+
+    for name in &[ "3dbag_sample", "denhaag_sample", "railway_sample" ] {
+        common::assert_golden( name, &parse_fixture( name ) );
+    }
+
+}
+
+#[test]
+fn outputs_are_consistent() {
+
+    let _guard = common::setup();
+
+    for name in &FIXTURES {
+
+        let out = parse_fixture( name );
+
+        let triangles = u32s( &out[ "triangles" ] );
+        let vertex_n = u32s( &out[ "vertices" ] ).len() as u32 / 3;
+        let intervals = u32s( &out[ "intervals" ] );
+
+        assert!( triangles.iter().all( |v| *v < vertex_n ), "{}: vertex index out of range", name );
+        assert_eq!( *intervals.last().unwrap() as usize, triangles.len() / 3, "{}: intervals don't cover the triangles", name );
+        assert_eq!( intervals.len(), out[ "ids" ].as_array().unwrap().len() + 1, "{}", name );
+
+        // Groups are contiguous, in the order of CO_TYPES, and cover all triangles
+        let groups = out[ "groups" ].as_object().unwrap();
+        let mut start = 0;
+
+        for co_type in CO_TYPES.iter().filter( |t| groups.contains_key( **t ) ) {
+            let group = u32s( &groups[ *co_type ] );
+            assert_eq!( group[ 0 ], start, "{}: group {}", name, co_type );
+            start += group[ 1 ];
+        }
+
+        assert_eq!( start as usize, triangles.len(), "{}: groups don't cover the triangles", name );
+
+    }
+
+}
+
+#[test]
+fn dense_picking_matches_intervals() {
+
+    let _guard = common::setup();
+
+    for name in &FIXTURES {
+
+        let buffer = common::fixture( &format!( "{}.json", name ) );
+
+        let intervals = parse( &buffer, PickingIndex::Intervals );
+        let dense = parse( &buffer, PickingIndex::Dense );
+
+        assert_eq!( dense[ "picks" ], intervals[ "picks" ], "{}", name );
+        assert_eq!( dense[ "triangles" ], intervals[ "triangles" ], "{}", name );
+
+    }
+
+}
+
+#[test]
+fn unsupported_types() {
+
+    let _guard = common::setup();
+
+    // A CityObject without a type, and one with the type of an Extension (CityJSON 2.0)
+    let documents = [
+        br#"{"type":"CityJSON","CityObjects":{"a":{"geometry":[]}},"vertices":[]}"#.to_vec(),
+        br#"{"type":"CityJSON","version":"2.0","CityObjects":{"a":{"type":"+NoiseBarrier","geometry":[{"type":"MultiSurface","boundaries":[[[0,1,2]]]}]}},"vertices":[[0,0,0],[1,0,0],[0,1,0]]}"#.to_vec(),
+    ];
+
+    let minimal = common::fixture( "minimal.json" );
+    to_bufferattributes::triangulate( &minimal, PickingIndex::Intervals ).unwrap();
+    let ids = IDS.lock().unwrap().clone();
+
+    for buffer in &documents {
+
+        assert!( to_bufferattributes::triangulate( buffer, PickingIndex::Intervals ).is_err() );
+        assert!( to_bufferattributes::parse_local( buffer, PickingIndex::Intervals ).is_err() );
+        assert!( GeometryCache::build( buffer ).is_err() );
+
+        // The push parser returns the error, and keeps returning it
+        let mut parser = PushParser::new( PickingIndex::Intervals );
+        assert!( parser.push( buffer ).is_err() );
+        assert!( parser.push( b" " ).is_err() );
+        assert!( parser.finish().is_err() );
+
+        // The picking index of the previous file stays
+        assert_eq!( *IDS.lock().unwrap(), ids );
+
+    }
+
+}
+
+#[test]
+fn edits_and_joins_are_cleared() {
+
+    let _guard = common::setup();
+    let buffer = common::fixture( "minimal.json" );
+    let cache = GeometryCache::build( &buffer ).unwrap().to_bytes().unwrap();
+
+    let attributes = || get_attributes( &buffer, "building-1" ).unwrap()[ "attributes" ].clone();
+
+    // A new file with the same IDs doesn't get the edits or joined columns, whether it is parsed at once, pushed or loaded from a cache
+    let parses: [&dyn Fn(); 3] = [
+        &|| { to_bufferattributes::triangulate( &buffer, PickingIndex::Intervals ).unwrap(); },
+        &|| { push::parse_buffer( &buffer, PickingIndex::Dense ).unwrap(); },
+        &|| { cache::restore_picking( &CacheView::parse( &cache ).unwrap() ).unwrap(); },
+    ];
+
+    for parse in &parses {
+
+        edit::set_attribute( &[ "building-1".to_string() ], "roofType", json!( "gabled" ) );
+        join::join_table( &buffer, "id,owner\nbuilding-1,city", TableFormat::Csv, "id", None ).unwrap();
+        assert_eq!( attributes(), json!( { "measuredHeight": 3.0, "roofType": "gabled", "owner": "city" } ) );
+
+        parse();
+
+        assert_eq!( attributes(), json!( { "measuredHeight": 3.0, "roofType": "flat" } ) );
+        assert!( edit::edited_ids().is_empty() );
+        assert!( join::JOINED.lock().unwrap().is_empty() );
+
+    }
+
+}
+
+#[test]
+fn local_parse() {
+
+    let _guard = common::setup();
+
+    let minimal = common::fixture( "minimal.json" );
+
+    for name in &FIXTURES {
+
+        let buffer = common::fixture( &format!( "{}.json", name ) );
+
+        for picking in [ PickingIndex::Intervals, PickingIndex::Dense ] {
+
+            let groups = to_bufferattributes::triangulate( &buffer, picking ).unwrap();
+            let ids = IDS.lock().unwrap().clone();
+            let index = match picking {
+                PickingIndex::Intervals => INTERVALS.lock().unwrap().clone(),
+                PickingIndex::Dense => picking::OBJECT_INDEX.lock().unwrap().clone(),
+            };
+            let parents = HIERARCHY.lock().unwrap().parents.clone();
+
+            // Another file is shown, with an edit and a joined column
+            to_bufferattributes::triangulate( &minimal, PickingIndex::Intervals ).unwrap();
+            edit::set_attribute( &[ "building-1".to_string() ], "roofType", json!( "gabled" ) );
+            join::join_table( &minimal, "id,owner\nbuilding-1,city", TableFormat::Csv, "id", None ).unwrap();
+
+            // The same outputs as a global parse, and the shown file is left alone
+            let parsed = to_bufferattributes::parse_local( &buffer, picking ).unwrap();
+
+            assert_eq!( ( &parsed.triangles, &parsed.groups ), ( &groups.triangles, &groups.groups ), "{}", name );
+            assert_eq!( ( &parsed.ids, &parsed.index ), ( &ids, &index ), "{}", name );
+            assert_eq!( parsed.hierarchy.parents, parents, "{}", name );
+
+            assert_eq!( *IDS.lock().unwrap(), vec![ "building-1".to_string(), "road-1".to_string() ] );
+            let current = *picking::PICKING.lock().unwrap();
+            assert_eq!( current, PickingIndex::Intervals );
+            assert_eq!( get_attributes( &minimal, "building-1" ).unwrap()[ "attributes" ], json!( { "measuredHeight": 3.0, "roofType": "gabled", "owner": "city" } ) );
+
+        }
+
+    }
+
+    edit::clear_edits();
+    join::clear_joins();
+
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rust_parcel::cityjson::picking::{self, PickingIndex};
+use rust_parcel::cityjson::progress::{CancelToken, Progress, CANCELLED};
+use rust_parcel::cityjson::to_bufferattributes;
+
+// Parse a fixture and collect the reported progress
+fn parse_with_progress( name: &str, token: Option<CancelToken>, cancel_after: Option<u32> ) -> ( Result<usize, String>, Vec<Progress> ) {
+
+    let buffer = common::fixture( name );
+    let reported = Rc::new( RefCell::new( Vec::new() ) );
+    let r = reported.clone();
+
+    let callback = Box::new( move | p: Progress | {
+        r.borrow_mut().push( p );
+        cancel_after.is_none_or( |n| p.objects < n )
+    } );
+
+    let res = to_bufferattributes::triangulate_with_progress( &buffer, PickingIndex::Intervals, Some( callback ), token ).map( |r| r.triangles.len() );
+    let reported = reported.borrow().clone();
+
+    ( res, reported )
+
+}
+
+#[test]
+fn progress_is_reported() {
+
+    let _guard = common::setup();
+    let ( res, reported ) = parse_with_progress( "railway_sample.json", None, None );
+    let total = common::fixture( "railway_sample.json" ).len();
+
+    assert_eq!( res, Ok( 222 ) );
+    assert!( reported.len() > 2 );
+
+    // Increasing, and the last report is the whole buffer with all CityObjects
+    assert!( reported.windows( 2 ).all( |w| w[ 0 ].bytes < w[ 1 ].bytes && w[ 0 ].objects <= w[ 1 ].objects ) );
+    assert!( reported.iter().all( |p| p.total == total ) );
+    assert_eq!( *reported.last().unwrap(), Progress { objects: 13, bytes: total, total } );
+
+}
+
+#[test]
+fn callback_cancels() {
+
+    let _guard = common::setup();
+
+    // The picking index of the last successful parse stays
+    let minimal = common::fixture( "minimal.json" );
+    to_bufferattributes::triangulate( &minimal, PickingIndex::Intervals ).unwrap();
+
+    let ( res, reported ) = parse_with_progress( "railway_sample.json", None, Some( 3 ) );
+
+    assert_eq!( res, Err( CANCELLED.to_string() ) );
+    assert_eq!( reported.last().unwrap().objects, 3 );
+    assert_eq!( picking::pick( 0 ).map( |p| p.0 ), Some( "building-1".to_string() ) );
+
+}
+
+#[test]
+fn token_cancels() {
+
+    let _guard = common::setup();
+
+    let token = CancelToken::new();
+    token.cancel();
+
+    let ( res, reported ) = parse_with_progress( "denhaag_sample.json", Some( token.clone() ), None );
+
+    assert_eq!( res, Err( CANCELLED.to_string() ) );
+    assert!( reported.len() <= 1 );
+
+    // Without cancelling, the same file parses
+    let ( res, _ ) = parse_with_progress( "denhaag_sample.json", Some( CancelToken::new() ), None );
+
+    assert_eq!( res, Ok( 168 ) );
+
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use std::collections::HashMap;
+
+use rust_parcel::cityjson::picking::{self, PickingIndex};
+use rust_parcel::cityjson::push::{self, PushParser};
+use rust_parcel::cityjson::to_bufferattributes::{self, ThreeGroups, IDS, INTERVALS};
+
+// Push a buffer in chunks of the given size, checking that the parser keeps less than the whole file
+fn push_in_chunks( buffer: &[u8], size: usize, picking: PickingIndex ) -> Result<( ThreeGroups, usize ), String> {
+
+    let mut parser = PushParser::new( picking );
+    let mut max_pending = 0;
+
+    for chunk in buffer.chunks( size ) {
+        parser.push( chunk )?;
+        max_pending = max_pending.max( parser.pending() );
+    }
+
+    assert_eq!( parser.released() + parser.pending(), buffer.len() );
+
+    Ok( ( parser.finish()?, max_pending ) )
+
+}
+
+#[test]
+fn chunks_match_whole_buffer() {
+
+    let _guard = common::setup();
+
+    for name in &[ "minimal.json", "edge_cases.json", "3dbag_sample.json", "denhaag_sample.json", "railway_sample.json" ] {
+
+        let buffer = common::fixture( name );
+
+        let expected = to_bufferattributes::triangulate( &buffer, PickingIndex::Intervals ).unwrap();
+        let vertices = to_bufferattributes::flatten_vertices( &buffer ).unwrap();
+        let ids = IDS.lock().unwrap().clone();
+        let intervals = INTERVALS.lock().unwrap().clone();
+
+        for size in &[ 1, 7, 100, buffer.len() ] {
+
+            let ( res, max_pending ) = push_in_chunks( &buffer, *size, PickingIndex::Intervals ).unwrap();
+
+            assert_eq!( res.triangles, expected.triangles, "{} in chunks of {}", name, size );
+            assert_eq!( res.groups, expected.groups, "{} in chunks of {}", name, size );
+            assert_eq!( res.vertices, vertices, "{} in chunks of {}", name, size );
+            assert_eq!( *IDS.lock().unwrap(), ids );
+            assert_eq!( *INTERVALS.lock().unwrap(), intervals );
+
+            if *size < 100 {
+                assert!( max_pending < buffer.len() / 2, "{} keeps {} of {} bytes", name, max_pending, buffer.len() );
+            }
+
+        }
+
+    }
+
+}
+
+#[test]
+fn batches_add_up() {
+
+    let _guard = common::setup();
+    let buffer = common::fixture( "railway_sample.json" );
+
+    let mut parser = PushParser::new( PickingIndex::Dense );
+    let mut triangles = 0;
+    let mut vertices = Vec::new();
+    let mut batches = 0;
+
+    for chunk in buffer.chunks( 256 ) {
+
+        parser.push( chunk ).unwrap();
+
+        let batch = parser.take_batch();
+
+        // The groups cover the triangles of the batch
+        assert_eq!( batch.groups.values().map( |g| g[ 1 ] as usize ).sum::<usize>(), batch.triangles.len() );
+
+        if !batch.triangles.is_empty() {
+            batches += 1;
+        }
+
+        triangles += batch.triangles.len();
+        vertices.extend( batch.vertices );
+
+    }
+
+    let res = parser.finish().unwrap();
+
+    assert!( batches > 1 );
+    assert_eq!( triangles, res.triangles.len() );
+    assert_eq!( vertices, res.vertices );
+
+    // The dense picking index was stored
+    assert_eq!( *picking::PICKING.lock().unwrap(), PickingIndex::Dense );
+    assert_eq!( picking::OBJECT_INDEX.lock().unwrap().len(), res.triangles.len() / 3 );
+
+}
+
+#[test]
+fn member_order_and_strings() {
+
+    let _guard = common::setup();
+
+    // Vertices first, braces and escaped quotes in strings and keys, scalars at the end of objects
+    let buffer = br#" { "vertices" : [ [0,0,0], [1,0,0],[0,1,0] ] ,
+        "metadata": { "title": "a \"}{\" title", "n": [ 1, { } ], "x": 1.5e3 },
+        "CityObjects": { "b\"}{": { "type": "Building", "attributes": { "s": "]]", "t": true },
+                                   "geometry": [ { "type": "MultiSurface", "boundaries": [ [ [ 0, 1, 2 ] ] ] } ] },
+                         "empty": { "type": "Road" } },
+        "version": "1.0", "n": null, "m": 42 }
+    "#;
+
+    for size in &[ 1, 3, buffer.len() ] {
+
+        let ( res, _ ) = push_in_chunks( buffer, *size, PickingIndex::Intervals ).unwrap();
+
+        assert_eq!( res.triangles, vec![ 0, 1, 2 ] );
+        assert_eq!( res.vertices, vec![ 0, 0, 0, 1, 0, 0, 0, 1, 0 ] );
+        assert_eq!( picking::pick( 0 ).map( |p| p.0 ), Some( "b\"}{".to_string() ) );
+
+    }
+
+}
+
+#[test]
+fn errors() {
+
+    let _guard = common::setup();
+    let buffer = common::fixture( "minimal.json" );
+
+    // Incomplete document
+    let mut parser = PushParser::new( PickingIndex::Intervals );
+    parser.push( &buffer[ ..buffer.len() / 2 ] ).unwrap();
+    assert!( parser.finish().is_err() );
+
+    // Invalid document: the error stays
+    let mut parser = PushParser::new( PickingIndex::Intervals );
+    assert!( parser.push( br#"{ "CityObjects": [ ] }"# ).is_err() );
+    assert!( parser.push( b" " ).is_err() );
+
+    assert!( push_in_chunks( br#"{ "CityObjects": { "a": 1 } }"#, 4, PickingIndex::Intervals ).is_err() );
+    assert!( push_in_chunks( br#"{ "vertices": [ [ 0, -1, 0 ] ] }"#, 4, PickingIndex::Intervals ).is_err() );
+    assert!( push_in_chunks( br#"{ } }"#, 4, PickingIndex::Intervals ).is_err() );
+
+}
+
+// Triangles per CityObject ID, found with the global picking index
+fn triangles_per_id( triangles: &[u32] ) -> HashMap<String, Vec<u32>> {
+
+    let mut out: HashMap<String, Vec<u32>> = HashMap::new();
+
+    for ( t, triangle ) in triangles.chunks( 3 ).enumerate() {
+        let ( id, _, _ ) = picking::pick( t as u32 ).unwrap();
+        out.entry( id ).or_default().extend_from_slice( triangle );
+    }
+
+    out
+
+}
+
+#[test]
+fn bounded_batches() {
+
+    let _guard = common::setup();
+
+    for name in &[ "3dbag_sample.json", "denhaag_sample.json", "railway_sample.json" ] {
+
+        let buffer = common::fixture( name );
+
+        let whole = to_bufferattributes::triangulate( &buffer, PickingIndex::Intervals ).unwrap();
+        let expected = triangles_per_id( &whole.triangles );
+        let vertices = to_bufferattributes::flatten_vertices( &buffer ).unwrap();
+
+        for picking in &[ PickingIndex::Intervals, PickingIndex::Dense ] {
+
+            // Segments of the file, as read from disk in pieces
+            let segments: Vec<Vec<u8>> = buffer.chunks( 500 ).map( |c| c.to_vec() ).collect();
+            let mut batches = Vec::new();
+
+            push::parse_segments( segments, *picking, 60, |b| batches.push( b ) ).unwrap();
+
+            assert!( batches.len() > 2, "{}", name );
+
+            for batch in &batches {
+                assert_eq!( batch.groups.values().map( |g| g[ 1 ] as usize ).sum::<usize>(), batch.triangles.len() );
+                // Bounded by the batch size plus the last CityObject or vertex
+                assert!( batch.vertices.len() < 60 + 3 );
+            }
+
+            // All batches together have the same triangles per CityObject and the same vertices as the whole file
+            let triangles: Vec<u32> = batches.iter().flat_map( |b| b.triangles.iter().copied() ).collect();
+            let all_vertices: Vec<u32> = batches.iter().flat_map( |b| b.vertices.iter().copied() ).collect();
+
+            assert_eq!( triangles.len(), whole.triangles.len() );
+            assert_eq!( triangles_per_id( &triangles ), expected, "{}", name );
+            assert_eq!( all_vertices, vertices );
+
+        }
+
+    }
+
+}
+
+#[test]
+fn batches_must_be_taken() {
+
+    let _guard = common::setup();
+    let buffer = common::fixture( "railway_sample.json" );
+
+    let mut parser = PushParser::with_batch_size( PickingIndex::Intervals, 30 );
+    parser.push( &buffer ).unwrap();
+
+    assert!( parser.finish().is_err() );
+
+}
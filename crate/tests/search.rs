@@ -0,0 +1,122 @@
+mod common;
+
+use serde_json::json;
+
+use rust_parcel::cityjson::picking::{self, PickingIndex};
+use rust_parcel::cityjson::search::{self, SearchIndex, SearchMode, SearchResult};
+use rust_parcel::cityjson::to_bufferattributes;
+
+///// Prefix and substring search, scoring, and the index that is built while parsing
+
+fn index() -> SearchIndex {
+
+    let mut index = SearchIndex::new( &[ "name".to_string(), "address".to_string() ] );
+
+    index.add( "main", &json!( { "type": "Building", "attributes": { "name": "Station" } } ) );
+    index.add( "b1", &json!( { "type": "Building", "attributes": { "name": "Main Street 12" } } ) );
+    index.add( "b2", &json!( { "type": "Building", "attributes": { "name": "(Main) street", "address": { "street": "Oude Delft", "number": 8 } } } ) );
+    index.add( "b3", &json!( { "type": "Building", "attributes": { "name": "Domain" } } ) );
+    index.finish();
+
+    index
+
+}
+
+fn found( results: &[SearchResult] ) -> Vec<( &str, &str, f32 )> {
+
+    results.iter().map( |r| ( r.id.as_str(), r.field.as_str(), r.score ) ).collect()
+
+}
+
+#[test]
+fn prefix() {
+
+    let index = index();
+
+    // The whole value, or any word in it, starts with the query, case-insensitively. A word after punctuation is found as well.
+    assert_eq!( found( &index.search( "MAIN", SearchMode::Prefix, 0 ) ),
+                vec![ ( "main", "id", 4.5 ), ( "b1", "name", 3.0 ), ( "b2", "name", 2.0 ) ] );
+
+    // "Domain" only contains it
+    assert!( index.search( "omain", SearchMode::Prefix, 0 ).is_empty() );
+    assert!( index.search( "  ", SearchMode::Prefix, 0 ).is_empty() );
+
+}
+
+#[test]
+fn substring() {
+
+    let index = index();
+
+    assert_eq!( found( &index.search( "main", SearchMode::Substring, 0 ) ),
+                vec![ ( "main", "id", 4.5 ), ( "b1", "name", 3.0 ), ( "b2", "name", 2.0 ), ( "b3", "name", 1.0 ) ] );
+
+    assert_eq!( found( &index.search( "omain", SearchMode::Substring, 0 ) ), vec![ ( "b3", "name", 1.0 ) ] );
+
+}
+
+#[test]
+fn id_boost() {
+
+    let index = index();
+
+    // IDs score half a point more than the same match in another field
+    let results = index.search( "b", SearchMode::Prefix, 0 );
+    assert!( results.iter().all( |r| r.field == "id" && r.score == 3.5 ) );
+
+    assert_eq!( results.len(), 3 );
+
+    // Ties are broken by the shortest value ("(main) street" before "main street 12"), and limit keeps the best
+    assert_eq!( found( &index.search( "st", SearchMode::Prefix, 2 ) ), vec![ ( "main", "name", 3.0 ), ( "b2", "name", 2.0 ) ] );
+
+}
+
+#[test]
+fn multi_word_values() {
+
+    let index = index();
+
+    // Every word of a value is searchable, also in nested values such as an address, and numbers are indexed as text
+    let results = index.search( "delft", SearchMode::Prefix, 0 );
+    assert_eq!( found( &results ), vec![ ( "b2", "address", 2.0 ) ] );
+    assert_eq!( results[ 0 ].value, "Oude Delft" );
+
+    assert_eq!( found( &index.search( "8", SearchMode::Prefix, 0 ) ), vec![ ( "b2", "address", 4.0 ) ] );
+
+    // A query with several words matches the start of the value
+    assert_eq!( found( &index.search( "main street", SearchMode::Prefix, 0 ) ), vec![ ( "b1", "name", 3.0 ) ] );
+
+}
+
+#[test]
+fn index_follows_search_fields() {
+
+    let _guard = common::setup();
+
+    let buffer = common::fixture( "minimal.json" );
+
+    search::set_search_fields( Some( vec![ "roofType".to_string() ] ) );
+    to_bufferattributes::triangulate( &buffer, PickingIndex::Intervals ).unwrap();
+
+    // With the triangle interval of the CityObject
+    let building = picking::triangle_ranges( &[ "building-1".to_string() ] ).remove( 0 );
+    let results = search::search_cityobjects( "flat", SearchMode::Prefix, 0 );
+
+    assert_eq!( found( &results ), vec![ ( "building-1", "roofType", 4.0 ) ] );
+    assert_eq!( ( results[ 0 ].start, results[ 0 ].end ), ( Some( building.start ), Some( building.end ) ) );
+    assert!( search::search_cityobjects( "main", SearchMode::Prefix, 0 ).is_empty() );
+
+    // Other fields are indexed the next time a file is parsed
+    search::set_search_fields( Some( vec![ "function".to_string() ] ) );
+    to_bufferattributes::triangulate( &buffer, PickingIndex::Intervals ).unwrap();
+
+    assert!( search::search_cityobjects( "flat", SearchMode::Prefix, 0 ).is_empty() );
+    assert_eq!( found( &search::search_cityobjects( "main", SearchMode::Prefix, 0 ) ), vec![ ( "road-1", "function", 3.0 ) ] );
+
+    // And without fields there is no index at all, not even of the IDs
+    search::set_search_fields( None );
+    to_bufferattributes::triangulate( &buffer, PickingIndex::Intervals ).unwrap();
+
+    assert!( search::search_cityobjects( "road", SearchMode::Prefix, 0 ).is_empty() );
+
+}
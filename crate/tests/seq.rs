@@ -0,0 +1,94 @@
+mod common;
+
+use std::collections::HashMap;
+
+use rust_parcel::cityjson::picking::{self, PickingIndex};
+use rust_parcel::cityjson::push::{self, PushParser};
+use rust_parcel::cityjson::to_bufferattributes::{self, ThreeGroups, IDS};
+
+// The vertices of the triangles of every CityObject, since the vertex indices of a CityJSONSeq differ from those of the document
+fn triangles_per_id( res: &ThreeGroups ) -> HashMap<String, Vec<[u32; 3]>> {
+
+    let mut out: HashMap<String, Vec<[u32; 3]>> = HashMap::new();
+
+    for ( t, triangle ) in res.triangles.chunks( 3 ).enumerate() {
+
+        let ( id, _, _ ) = picking::pick( t as u32 ).unwrap();
+        let vertices = triangle.iter().map( |&v| {
+            let v = v as usize * 3;
+            [ res.vertices[ v ], res.vertices[ v + 1 ], res.vertices[ v + 2 ] ]
+        } );
+
+        out.entry( id ).or_default().extend( vertices );
+
+    }
+
+    out
+
+}
+
+#[test]
+fn sequence_matches_document() {
+
+    let _guard = common::setup();
+
+    for name in &[ "3dbag_sample", "railway_sample" ] {
+
+        let document = common::fixture( &format!( "{}.json", name ) );
+        let sequence = common::fixture( &format!( "{}.city.jsonl", name ) );
+
+        let mut expected = to_bufferattributes::triangulate( &document, PickingIndex::Intervals ).unwrap();
+        expected.vertices = to_bufferattributes::flatten_vertices( &document ).unwrap();
+        let expected_triangles = triangles_per_id( &expected );
+        let mut expected_ids = IDS.lock().unwrap().clone();
+
+        let res = push::parse_buffer( &sequence, PickingIndex::Intervals ).unwrap();
+        let mut ids = IDS.lock().unwrap().clone();
+
+        assert_eq!( res.groups, expected.groups, "{}", name );
+        assert_eq!( triangles_per_id( &res ), expected_triangles, "{}", name );
+
+        expected_ids.sort();
+        ids.sort();
+        assert_eq!( ids, expected_ids );
+
+        // Line by line gives the same output
+        let mut parser = PushParser::new( PickingIndex::Intervals );
+
+        for line in sequence.split_inclusive( |&b| b == b'\n' ) {
+            parser.push( line ).unwrap();
+        }
+
+        let lines = parser.finish().unwrap();
+
+        assert_eq!( lines.triangles, res.triangles );
+        assert_eq!( lines.vertices, res.vertices );
+
+    }
+
+}
+
+#[test]
+fn features_after_the_header() {
+
+    let _guard = common::setup();
+
+    // Each feature indexes its own vertices
+    let sequence = br#"{"type":"CityJSON","version":"2.0","transform":{"scale":[1,1,1],"translate":[0,0,0]},"CityObjects":{},"vertices":[]}
+{"type":"CityJSONFeature","id":"a","CityObjects":{"a":{"type":"Building","geometry":[{"type":"MultiSurface","boundaries":[[[0,1,2]]]}]}},"vertices":[[0,0,0],[1,0,0],[0,1,0]]}
+{"type":"CityJSONFeature","id":"b","vertices":[[5,5,5],[6,5,5],[5,6,5]],"CityObjects":{"b":{"type":"Building","geometry":[{"type":"MultiSurface","boundaries":[[[2,1,0]]]}]}}}
+"#;
+
+    let res = push::parse_buffer( sequence, PickingIndex::Dense ).unwrap();
+
+    assert_eq!( res.triangles, vec![ 0, 1, 2, 5, 4, 3 ] );
+    assert_eq!( res.vertices.len(), 18 );
+    assert_eq!( picking::pick( 1 ).map( |p| p.0 ), Some( "b".to_string() ) );
+
+    // Only features can follow the header
+    let mut invalid = sequence.to_vec();
+    invalid.extend_from_slice( br#"{"type":"CityJSON","CityObjects":{}}"# );
+
+    assert!( push::parse_buffer( &invalid, PickingIndex::Intervals ).is_err() );
+
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use serde_json::json;
+
+use rust_parcel::cityjson::stats;
+
+///// The attribute schema: counts, types, nulls, numeric strings, number statistics and top values, overall and per CityObject type
+
+fn buffer() -> Vec<u8> {
+
+    let cityjson = json!( {
+        "type": "CityJSON",
+        "version": "1.1",
+        "CityObjects": {
+            "b1": { "type": "Building", "attributes": { "height": 10, "roof": "flat", "code": "0363", "mixed": 1 } },
+            "b2": { "type": "Building", "attributes": { "height": 20.5, "roof": "flat", "code": "12.5", "mixed": "one" } },
+            "b3": { "type": "Building", "attributes": { "height": null, "roof": "slanted", "mixed": true } },
+            "b4": { "type": "Building" },
+            "r1": { "type": "Road", "attributes": { "height": 0, "roof": "none", "mixed": [ 1 ] } },
+        },
+        "vertices": [],
+    } );
+
+    serde_json::to_vec( &cityjson ).unwrap()
+
+}
+
+#[test]
+fn counts_and_types() {
+
+    let _guard = common::setup();
+
+    let schema = stats::attribute_schema( &buffer(), 10 ).unwrap();
+
+    assert_eq!( schema.object_counts, [ ( "Building".to_string(), 4 ), ( "Road".to_string(), 1 ) ].iter().cloned().collect() );
+
+    // Counted per CityObject that has the attribute, whatever its value
+    let counts: Vec<( &str, u32 )> = schema.attributes.iter().map( |( k, s )| ( k.as_str(), s.count ) ).collect();
+    assert_eq!( counts, vec![ ( "code", 2 ), ( "height", 4 ), ( "mixed", 4 ), ( "roof", 4 ) ] );
+
+    let mixed = &schema.attributes[ "mixed" ];
+    assert_eq!( serde_json::to_value( &mixed.types ).unwrap(), json!( { "array": 1, "boolean": 1, "number": 1, "string": 1 } ) );
+
+}
+
+#[test]
+fn nulls_and_numbers() {
+
+    let _guard = common::setup();
+
+    let schema = stats::attribute_schema( &buffer(), 10 ).unwrap();
+
+    // Nulls are counted, but don't take part in the number statistics
+    let height = &schema.attributes[ "height" ];
+    assert_eq!( height.nulls, 1 );
+    assert_eq!( height.types[ "null" ], 1 );
+    assert_eq!( ( height.min, height.max ), ( Some( 0.0 ), Some( 20.5 ) ) );
+    assert_eq!( height.mean, Some( 30.5 / 3.0 ) );
+
+    // Numeric strings are reported, but stay strings
+    let code = &schema.attributes[ "code" ];
+    assert_eq!( code.numeric_strings, 2 );
+    assert_eq!( ( code.min, code.max, code.mean ), ( None, None, None ) );
+
+    assert_eq!( schema.attributes[ "roof" ].numeric_strings, 0 );
+
+}
+
+#[test]
+fn top_values() {
+
+    let _guard = common::setup();
+
+    let schema = stats::attribute_schema( &buffer(), 2 ).unwrap();
+
+    // Most frequent first, ties alphabetically, cut off at top_n
+    let roof = &schema.attributes[ "roof" ];
+    assert_eq!( roof.top_values, vec![ ( "flat".to_string(), 2 ), ( "none".to_string(), 1 ) ] );
+    assert_eq!( roof.distinct_values, 3 );
+
+    // Only strings are values here
+    assert!( schema.attributes[ "height" ].top_values.is_empty() );
+
+}
+
+#[test]
+fn by_type() {
+
+    let _guard = common::setup();
+
+    let schema = stats::attribute_schema( &buffer(), 10 ).unwrap();
+
+    let buildings = &schema.by_type[ "Building" ];
+    let roads = &schema.by_type[ "Road" ];
+
+    assert_eq!( buildings[ "height" ].count, 3 );
+    assert_eq!( buildings[ "height" ].max, Some( 20.5 ) );
+    assert_eq!( roads[ "height" ].count, 1 );
+    assert_eq!( roads[ "height" ].max, Some( 0.0 ) );
+    assert!( !roads.contains_key( "code" ) );
+
+    // The skipped internals don't end up in the output
+    let json = serde_json::to_value( &schema ).unwrap();
+    assert_eq!( json[ "attributes" ][ "roof" ].as_object().unwrap().keys().collect::<Vec<_>>(),
+                vec![ "count", "distinct_values", "nulls", "numeric_strings", "top_values", "types" ] );
+
+}
@@ -0,0 +1,224 @@
+mod common;
+
+use std::collections::HashSet;
+
+use serde_json::{json, Value};
+
+use rust_parcel::cityjson::edit;
+use rust_parcel::cityjson::picking::PickingIndex;
+use rust_parcel::cityjson::subset;
+use rust_parcel::cityjson::to_bufferattributes;
+
+///// Subsets of edge_cases.json: the output is a valid standalone document with the same geometry as the original CityObjects
+
+// Every integer in a (nested) array
+fn indices( value: &Value, out: &mut Vec<u64> ) {
+
+    match value {
+        Value::Number( n ) => out.extend( n.as_u64() ),
+        Value::Array( items ) => items.iter().for_each( |v| indices( v, out ) ),
+        _ => {},
+    }
+
+}
+
+// The triangles of a CityObject, as real coordinates
+fn triangle_coordinates( doc: &Value, id: &str ) -> Vec<[f64; 3]> {
+
+    let scale = &doc[ "transform" ][ "scale" ];
+    let translate = &doc[ "transform" ][ "translate" ];
+
+    to_bufferattributes::cityobject_triangles( id, &doc[ "CityObjects" ][ id ] ).iter().map( |v| {
+        let v = &doc[ "vertices" ][ *v as usize ];
+        let c = | i: usize | v[ i ].as_f64().unwrap() * scale[ i ].as_f64().unwrap() + translate[ i ].as_f64().unwrap();
+        [ c( 0 ), c( 1 ), c( 2 ) ]
+    } ).collect()
+
+}
+
+// Check that all references in a subset are valid and that all vertices and templates are used, and compare the geometry with the original
+fn check( original: &Value, out: &[u8] ) -> Value {
+
+    let doc: Value = serde_json::from_slice( out ).unwrap();
+    let city_objects = doc[ "CityObjects" ].as_object().unwrap();
+
+    let vertex_n = doc[ "vertices" ].as_array().unwrap().len() as u64;
+    let templates = doc[ "geometry-templates" ][ "templates" ].as_array().cloned().unwrap_or_default();
+    let template_vertex_n = doc[ "geometry-templates" ][ "vertices-templates" ].as_array().map( |v| v.len() ).unwrap_or( 0 ) as u64;
+
+    let mut used_vertices = HashSet::new();
+    let mut used_templates = HashSet::new();
+
+    for ( id, co ) in city_objects {
+
+        for member in &[ "parents", "children", "members" ] {
+            if let Some( references ) = co.get( *member ) {
+                let references = references.as_array().unwrap();
+                assert!( !references.is_empty(), "{} has empty {}", id, member );
+                for r in references {
+                    assert!( city_objects.contains_key( r.as_str().unwrap() ), "{} refers to {} outside of the subset", id, r );
+                }
+            }
+        }
+
+        for geometry in co[ "geometry" ].as_array().into_iter().flatten() {
+
+            let mut boundaries = Vec::new();
+            indices( &geometry[ "boundaries" ], &mut boundaries );
+            assert!( boundaries.iter().all( |v| *v < vertex_n ), "{} refers to a vertex that doesn't exist", id );
+            used_vertices.extend( boundaries );
+
+            if geometry[ "type" ] == "GeometryInstance" {
+                let template = geometry[ "template" ].as_u64().unwrap();
+                assert!( template < templates.len() as u64 );
+                used_templates.insert( template );
+            }
+
+        }
+
+        assert_eq!( triangle_coordinates( &doc, id ), triangle_coordinates( original, id ), "{}", id );
+        assert_eq!( co[ "type" ], original[ "CityObjects" ][ id ][ "type" ] );
+
+    }
+
+    for template in &templates {
+        let mut boundaries = Vec::new();
+        indices( &template[ "boundaries" ], &mut boundaries );
+        assert!( boundaries.iter().all( |v| *v < template_vertex_n ) );
+    }
+
+    // Compact: nothing that isn't used is kept
+    assert_eq!( used_vertices.len() as u64, vertex_n );
+    assert_eq!( used_templates.len(), templates.len() );
+
+    if vertex_n > 0 {
+        assert_eq!( doc[ "metadata" ][ "geographicalExtent" ].as_array().unwrap().len(), 6 );
+    } else {
+        assert!( doc[ "metadata" ].get( "geographicalExtent" ).is_none() );
+    }
+
+    assert_eq!( doc[ "transform" ], original[ "transform" ] );
+
+    doc
+
+}
+
+fn ids( doc: &Value ) -> Vec<&str> {
+
+    let mut ids: Vec<&str> = doc[ "CityObjects" ].as_object().unwrap().keys().map( |k| k.as_str() ).collect();
+    ids.sort_unstable();
+    ids
+
+}
+
+#[test]
+fn round_trip() {
+
+    let _guard = common::setup();
+
+    let buffer = common::fixture( "edge_cases.json" );
+    let original: Value = serde_json::from_slice( &buffer ).unwrap();
+    let subset = | selection: &[&str], children: bool, parents: bool | {
+        let selection: Vec<String> = selection.iter().map( |id| id.to_string() ).collect();
+        check( &original, &subset::subset( &buffer, &selection, children, parents ).unwrap() )
+    };
+
+    // A child with its parent, and the parent without its child
+    let doc = subset( &[ "part-1" ], false, true );
+    assert_eq!( ids( &doc ), vec![ "part-1", "quad-and-hole" ] );
+    assert_eq!( doc[ "CityObjects" ][ "quad-and-hole" ][ "children" ], json!( [ "part-1" ] ) );
+
+    let doc = subset( &[ "quad-and-hole" ], false, false );
+    assert_eq!( ids( &doc ), vec![ "quad-and-hole" ] );
+    assert!( doc[ "CityObjects" ][ "quad-and-hole" ].get( "children" ).is_none() );
+
+    let doc = subset( &[ "quad-and-hole" ], true, false );
+    assert_eq!( ids( &doc ), vec![ "part-1", "quad-and-hole" ] );
+
+    // A group keeps only the members in the subset
+    let doc = subset( &[ "group-1", "multisolid" ], false, false );
+    assert_eq!( doc[ "CityObjects" ][ "group-1" ][ "members" ], json!( [ "multisolid" ] ) );
+
+    // Geometry templates are kept only when they are used
+    let doc = subset( &[ "tree-instance", "gebäude-ü ✓" ], false, false );
+    assert_eq!( doc[ "geometry-templates" ][ "templates" ].as_array().unwrap().len(), 1 );
+    assert!( subset( &[ "multisolid" ], false, false ).get( "geometry-templates" ).is_none() );
+
+    // CityObjects without geometry have no vertices
+    let doc = subset( &[ "no-geometry", "empty-geometry", "missing" ], false, false );
+    assert_eq!( ids( &doc ), vec![ "empty-geometry", "no-geometry" ] );
+    assert_eq!( doc[ "vertices" ], json!( [] ) );
+
+    // Everything, and the subset parses like the original
+    let all: Vec<&str> = original[ "CityObjects" ].as_object().unwrap().keys().map( |k| k.as_str() ).collect();
+    let doc = subset( &all, false, false );
+    assert_eq!( ids( &doc ).len(), all.len() );
+
+    let out = serde_json::to_vec( &doc ).unwrap();
+    let expected = to_bufferattributes::triangulate( &buffer, PickingIndex::Intervals ).unwrap();
+    assert_eq!( to_bufferattributes::triangulate( &out, PickingIndex::Intervals ).unwrap().groups, expected.groups );
+
+}
+
+#[test]
+fn edits_are_applied() {
+
+    let _guard = common::setup();
+    let buffer = common::fixture( "minimal.json" );
+
+    to_bufferattributes::triangulate( &buffer, PickingIndex::Intervals ).unwrap();
+    edit::set_attribute( &[ "building-1".to_string() ], "roofType", json!( "gabled" ) );
+
+    let doc: Value = serde_json::from_slice( &subset::subset( &buffer, &[ "building-1".to_string() ], false, false ).unwrap() ).unwrap();
+
+    assert_eq!( doc[ "CityObjects" ][ "building-1" ][ "attributes" ][ "roofType" ], "gabled" );
+    assert_eq!( doc[ "vertices" ].as_array().unwrap().len(), 4 );
+    assert_eq!( doc[ "metadata" ][ "geographicalExtent" ], json!( [ 100.0, 200.0, 0.0, 101.0, 201.0, 3.0 ] ) );
+
+    edit::clear_edits();
+
+}
+
+#[test]
+fn source_order() {
+
+    let _guard = common::setup();
+
+    let buffer = common::fixture( "edge_cases.json" );
+
+    // Not in alphabetical order, and selected in yet another order
+    let file_order = [ "group-1", "no-geometry", "empty-geometry", "quad-and-hole", "part-1", "multisolid", "tree-instance", "gebäude-ü ✓" ];
+    let mut selection: Vec<String> = file_order.iter().map( |id| id.to_string() ).collect();
+    selection.reverse();
+
+    let out = String::from_utf8( subset::subset( &buffer, &selection, false, false ).unwrap() ).unwrap();
+
+    let mut written: Vec<( usize, &str )> = file_order.iter().map( |id| ( out.find( &format!( "{}:{{", Value::from( *id ) ) ).unwrap(), *id ) ).collect();
+    written.sort_unstable();
+
+    assert_eq!( written.iter().map( |( _, id )| *id ).collect::<Vec<_>>(), file_order.to_vec() );
+
+    // The members of the document are in the usual order too
+    let position = | member: &str | out.find( &format!( "\"{}\":", member ) ).unwrap();
+    assert!( position( "type" ) < position( "version" ) && position( "metadata" ) < position( "CityObjects" ) && position( "CityObjects" ) < position( "vertices" ) );
+
+}
+
+#[test]
+fn extent_without_vertices() {
+
+    let _guard = common::setup();
+
+    let mut original: Value = serde_json::from_slice( &common::fixture( "edge_cases.json" ) ).unwrap();
+    original[ "metadata" ] = json!( { "geographicalExtent": [ 0, 0, 0, 1, 1, 1 ], "title": "Edge cases" } );
+
+    let buffer = serde_json::to_vec( &original ).unwrap();
+
+    // The extent of the original document is recomputed for the subset, or left out if the subset has no vertices
+    let doc = check( &original, &subset::subset( &buffer, &[ "multisolid".to_string() ], false, false ).unwrap() );
+    assert_ne!( doc[ "metadata" ][ "geographicalExtent" ], original[ "metadata" ][ "geographicalExtent" ] );
+
+    let doc = check( &original, &subset::subset( &buffer, &[ "no-geometry".to_string() ], false, false ).unwrap() );
+    assert_eq!( doc[ "metadata" ], json!( { "title": "Edge cases" } ) );
+
+}
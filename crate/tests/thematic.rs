@@ -0,0 +1,170 @@
+mod common;
+
+use serde_json::{json, Value};
+
+use rust_parcel::cityjson::picking::{self, PickingIndex};
+use rust_parcel::cityjson::thematic::{self, Classification, LegendEntry, NO_DATA_COLOR};
+use rust_parcel::cityjson::to_bufferattributes;
+
+///// Class breaks, categories, the "No data" class, and the groups and vertex colors that the viewer draws with
+
+fn by_id( values: Value ) -> Vec<( String, Option<Value> )> {
+
+    values.as_object().unwrap().iter().map( |( id, v )| ( id.clone(), if v.is_null() { None } else { Some( v.clone() ) } ) ).collect()
+
+}
+
+fn palette( colors: &[ &str ] ) -> Vec<String> {
+
+    colors.iter().map( |c| c.to_string() ).collect()
+
+}
+
+fn bounds( legend: &[LegendEntry] ) -> Vec<( Option<f64>, Option<f64>, u32 )> {
+
+    legend.iter().map( |e| ( e.min, e.max, e.count ) ).collect()
+
+}
+
+#[test]
+fn equal_interval() {
+
+    let values = by_id( json!( { "a": 0, "b": 1, "c": 2.5, "d": 5, "e": 10, "f": "7.5" } ) );
+    let thematic = thematic::classify( &values, Classification::EqualInterval, 4, &palette( &[ "#000000", "#ffffff" ] ) ).unwrap();
+
+    // Values on a bound go to the class above it, the maximum to the last class. Numeric strings count as numbers.
+    assert_eq!( bounds( &thematic.legend ), vec![ ( Some( 0.0 ), Some( 2.5 ), 2 ), ( Some( 2.5 ), Some( 5.0 ), 1 ),
+                                                  ( Some( 5.0 ), Some( 7.5 ), 1 ), ( Some( 7.5 ), Some( 10.0 ), 2 ) ] );
+
+    assert_eq!( thematic.legend[ 1 ].label, "2.5 – 5" );
+    assert_eq!( thematic.classes[ "c" ], 1 );
+    assert_eq!( thematic.classes[ "e" ], 3 );
+
+    // The palette is a ramp from the first to the last color
+    let colors: Vec<&str> = thematic.legend.iter().map( |e| e.color.as_str() ).collect();
+    assert_eq!( colors, vec![ "#000000", "#555555", "#aaaaaa", "#ffffff" ] );
+
+}
+
+#[test]
+fn quantile() {
+
+    let values = by_id( json!( { "a": 1, "b": 2, "c": 3, "d": 4, "e": 100, "f": 200 } ) );
+    let thematic = thematic::classify( &values, Classification::Quantile, 3, &palette( &[ "#ff0000" ] ) ).unwrap();
+
+    // About the same amount of CityObjects per class, however skewed the values are
+    assert_eq!( bounds( &thematic.legend ), vec![ ( Some( 1.0 ), Some( 3.0 ), 2 ), ( Some( 3.0 ), Some( 100.0 ), 2 ), ( Some( 100.0 ), Some( 200.0 ), 2 ) ] );
+
+    // Duplicate breaks are merged, and a single value still gets a class
+    let same = by_id( json!( { "a": 5, "b": 5, "c": 5 } ) );
+    let thematic = thematic::classify( &same, Classification::Quantile, 3, &palette( &[ "#ff0000" ] ) ).unwrap();
+    assert_eq!( bounds( &thematic.legend ), vec![ ( Some( 5.0 ), Some( 5.0 ), 3 ) ] );
+
+}
+
+#[test]
+fn categorical() {
+
+    let values = by_id( json!( { "a": "flat", "b": "slanted", "c": "flat", "d": "dome", "e": "slanted", "f": "flat", "g": true } ) );
+
+    // Most frequent first, ties alphabetically, and the palette repeats
+    let thematic = thematic::classify( &values, Classification::Categorical, 0, &palette( &[ "#111111", "#222222" ] ) ).unwrap();
+
+    let legend: Vec<( &str, &str, u32 )> = thematic.legend.iter().map( |e| ( e.label.as_str(), e.color.as_str(), e.count ) ).collect();
+    assert_eq!( legend, vec![ ( "flat", "#111111", 3 ), ( "slanted", "#222222", 2 ), ( "dome", "#111111", 1 ), ( "true", "#222222", 1 ) ] );
+    assert_eq!( thematic.legend[ 0 ].value, Some( "flat".to_string() ) );
+
+    // Categories beyond the limit end up in "Other"
+    let thematic = thematic::classify( &values, Classification::Categorical, 2, &palette( &[ "#111111" ] ) ).unwrap();
+
+    let legend: Vec<( &str, u32 )> = thematic.legend.iter().map( |e| ( e.label.as_str(), e.count ) ).collect();
+    assert_eq!( legend, vec![ ( "flat", 3 ), ( "slanted", 2 ), ( "Other", 2 ) ] );
+    assert_eq!( thematic.classes[ "d" ], 2 );
+    assert_eq!( thematic.legend[ 2 ].value, None );
+
+}
+
+#[test]
+fn no_data() {
+
+    let values = by_id( json!( { "a": 1, "b": null, "c": "tall", "d": 3 } ) );
+    let thematic = thematic::classify( &values, Classification::EqualInterval, 2, &palette( &[ "#000000" ] ) ).unwrap();
+
+    // Missing values and values that are not numbers in a numeric classification, with its own color, always last
+    let last = thematic.legend.last().unwrap();
+    assert_eq!( ( last.label.as_str(), last.color.clone(), last.count ), ( "No data", thematic::format_color( NO_DATA_COLOR ), 2 ) );
+    assert_eq!( thematic.classes[ "b" ], 2 );
+    assert_eq!( thematic.classes[ "c" ], 2 );
+
+    // Without a single number everything is "No data"
+    let none = by_id( json!( { "a": null, "b": "x" } ) );
+    let thematic = thematic::classify( &none, Classification::Quantile, 5, &palette( &[ "#000000" ] ) ).unwrap();
+    assert_eq!( bounds( &thematic.legend ), vec![ ( None, None, 2 ) ] );
+
+    // Only null is missing for categories
+    let thematic = thematic::classify( &values, Classification::Categorical, 0, &palette( &[ "#000000" ] ) ).unwrap();
+    assert_eq!( thematic.legend.last().unwrap().count, 1 );
+
+}
+
+#[test]
+fn invalid_arguments() {
+
+    let values = by_id( json!( { "a": 1 } ) );
+
+    assert!( thematic::classify( &values, Classification::EqualInterval, 2, &[] ).is_err() );
+    assert!( thematic::classify( &values, Classification::EqualInterval, 0, &palette( &[ "#000000" ] ) ).is_err() );
+    assert!( thematic::classify( &values, Classification::EqualInterval, 2, &palette( &[ "red" ] ) ).is_err() );
+
+}
+
+#[test]
+fn groups() {
+
+    let _guard = common::setup();
+
+    let buffer = common::fixture( "minimal.json" );
+    to_bufferattributes::triangulate( &buffer, PickingIndex::Intervals ).unwrap();
+
+    let result = thematic::thematic_classes( &buffer, "attributes.measuredHeight", Classification::EqualInterval, 1, &palette( &[ "#ff0000" ] ) ).unwrap();
+
+    assert_eq!( result.classes[ "building-1" ], 0 );
+    assert_eq!( result.classes[ "road-1" ], 1 );
+
+    // [start, count, class] in indices of the triangles array, one per CityObject here since their classes differ
+    let expected: Vec<[u32; 3]> = picking::all_triangle_ranges().iter()
+        .map( |r| [ r.start * 3, ( r.end - r.start + 1 ) * 3, result.classes[ &r.id ] as u32 ] )
+        .collect();
+
+    assert_eq!( result.groups, expected );
+    assert_eq!( result.groups.iter().map( |g| g[ 1 ] ).sum::<u32>(), 6 * 3 );
+
+    // Consecutive CityObjects of the same class are merged into one group, here both in "No data"
+    let result = thematic::thematic_classes( &buffer, "attributes.missing", Classification::Categorical, 0, &palette( &[ "#ff0000" ] ) ).unwrap();
+    assert_eq!( result.groups, vec![ [ 0, 18, 0 ] ] );
+
+}
+
+#[test]
+fn vertex_colors() {
+
+    let _guard = common::setup();
+
+    let buffer = common::fixture( "minimal.json" );
+
+    let result = thematic::thematic_vertex_colors( &buffer, "attributes.measuredHeight", Classification::EqualInterval, 1, &palette( &[ "#ff0000" ] ), 9 ).unwrap();
+
+    let red = [ 1.0, 0.0, 0.0 ];
+    let grey: Vec<f32> = NO_DATA_COLOR.iter().map( |c| *c as f32 / 255.0 ).collect();
+
+    // Vertices 0-3 belong to the building, 4-7 to the road (no height), and 8 to nothing
+    assert_eq!( result.colors.len(), 9 * 3 );
+
+    for v in 0..9 {
+        let expected = if v < 4 { &red[ .. ] } else { &grey[ .. ] };
+        assert_eq!( &result.colors[ v * 3..v * 3 + 3 ], expected, "vertex {}", v );
+    }
+
+    assert_eq!( result.legend.len(), 2 );
+
+}
@@ -0,0 +1,85 @@
+// Sample Web Worker entry point: parses a CityJSON file off the main thread and keeps it for picking and attribute queries.
+//
+// Usage from the main thread:
+//
+//	const worker = new Worker( './parser.worker.js' );
+//	worker.onmessage = e => { ... };
+//	worker.postMessage( { type: 'parse', buffer: arrayBuffer }, [ arrayBuffer ] );
+//	worker.postMessage( { type: 'pick', triangle: faceIndex } );
+//	worker.postMessage( { type: 'attributes', id: objectId } );
+//
+// Messages from the worker:
+//
+//	{ type: 'progress', objects, bytes, total }
+//	{ type: 'parsed', triangles: Uint32Array, vertices: Uint32Array, groups: Map, ids }  (the typed arrays are transferred, not copied)
+//	{ type: 'picked', triangle, picked: { id, start, end, parents } or null }
+//	{ type: 'attributes', id, attributes }
+//	{ type: 'error', request, message }
+
+import rust from '../crate/Cargo.toml';
+rust.init();
+
+// The parsed file, kept in the worker for picking and attribute queries
+let model = null;
+
+function parse( buffer, picking ) {
+
+	if ( model ) {
+
+		model.free();
+		model = null;
+
+	}
+
+	const progress = ( objects, bytes, total ) => {
+
+		self.postMessage( { type: 'progress', objects, bytes, total } );
+
+	};
+
+	model = new rust.CityModel( new Uint8Array( buffer ), picking === 'dense' ? rust.PickingIndex.Dense : rust.PickingIndex.Intervals, progress );
+
+	const geometry = model.take_geometry();
+
+	self.postMessage( {
+		type: 'parsed',
+		triangles: geometry.triangles,
+		vertices: geometry.vertices,
+		groups: geometry.groups,
+		ids: model.ids
+	}, geometry.transfer );
+
+}
+
+self.onmessage = function ( e ) {
+
+	const message = e.data;
+
+	try {
+
+		switch ( message.type ) {
+
+		case 'parse':
+			parse( message.buffer, message.picking );
+			break;
+
+		case 'pick':
+			self.postMessage( { type: 'picked', triangle: message.triangle, picked: model ? model.pick( message.triangle ) : null } );
+			break;
+
+		case 'attributes':
+			self.postMessage( { type: 'attributes', id: message.id, attributes: model ? model.get_attributes( message.id ) : null } );
+			break;
+
+		default:
+			throw "Unknown message type: " + message.type;
+
+		}
+
+	} catch ( err ) {
+
+		self.postMessage( { type: 'error', request: message.type, message: String( err ) } );
+
+	}
+
+};